@@ -4,88 +4,399 @@
 // 导入 Anchor 框架核心模块，提供账户管理、错误处理等功能
 use anchor_lang::prelude::*;
 // 导入 Anchor 的 SPL Token 模块，支持代币操作（如转移、铸造）
-use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+use anchor_spl::token_interface::{
+    self, CloseAccount, Mint, SyncNative, TokenAccount, TokenInterface, TransferChecked,
+};
+// 导入系统程序模块，用于把 lamports 转入临时 wSOL 账户
+use anchor_lang::system_program;
+// 导入关联代币账户地址推导，用于确认失效 maker 关闭的是自己的关联代币账户
+use anchor_spl::associated_token::get_associated_token_address_with_program_id;
 // 导入 Peekable 迭代器，用于预览 remaining_accounts 中的账户
 use std::iter::Peekable;
 // 导入 Iter，用于遍历 remaining_accounts
 use std::slice::Iter;
 // 导入 Account 类型，用于手动反序列化账户信息
 use anchor_lang::accounts::account::Account;
+// 导入 SHA-256 哈希函数，用于构建成交记录的 Merkle 树
+use anchor_lang::solana_program::hash::hashv;
 
 // 声明程序 ID，与部署的程序 ID 保持一致
 declare_id!("2LoSwHzHBVco5nzB6gFyF17DEtd8BhtAwEduHDyv6Nsv");
 
+// 推荐码最大长度（字节），同时受 PDA 种子 32 字节上限约束
+pub const MAX_REFERRAL_CODE_LEN: usize = 16;
+
+// 待结算成交叶子的最大数量，达到上限时自动提交为新的 Merkle 根
+pub const MAX_PENDING_FILLS: usize = 32;
+
+// 保留的最近成交批次 Merkle 根数量，按批次编号循环覆盖；更早批次的成交无法再证明
+pub const MAX_FILL_ROOTS: usize = 6;
+
+// 管理员操作日志保留的最大条目数，超出时丢弃最早的记录
+pub const MAX_ADMIN_LOG_ENTRIES: usize = 16;
+
+// 新建订单簿时每一方向（买/卖）可容纳的挂单数量，之后可通过 grow_book 扩容；
+// 受 CPI 创建账户 10240 字节上限约束
+pub const INITIAL_MAX_ORDERS: u32 = 24;
+
+// 订单簿账户布局的当前版本：新增字段时递增，并在 migrate 中为旧版本账户补充默认值
+pub const ORDERBOOK_VERSION: u8 = 1;
+
+// 深度查询每一方向最多返回的价位数量，保证返回数据不超过 1024 字节上限
+pub const MAX_DEPTH_LEVELS: usize = 30;
+
+// 模拟下单最多返回的逐笔成交数量，保证返回数据不超过 1024 字节上限
+pub const MAX_SIMULATED_FILLS: usize = 48;
+
+// 每个订单簿可同时存在的未触发止损单数量上限
+pub const MAX_STOP_ORDERS: usize = 8;
+
+// remaining_accounts 中每个挂单结算方对应的账户数：基础代币账户、报价代币账户、待领取余额账户
+pub const MAKER_ACCOUNTS_LEN: usize = 3;
+
+// 基点分母，手续费等比例参数以基点表示（10000 基点 = 100%）
+pub const BPS_DENOMINATOR: u16 = 10_000;
+
+// 成交量统计窗口长度（秒），窗口到期后的第一笔成交清零累计量并开启新窗口
+pub const VOLUME_WINDOW_SECONDS: i64 = 86_400;
+
+// Pyth 拉取式预言机（pyth-solana-receiver）程序 ID，价格账户必须由该程序拥有
+pub const PYTH_RECEIVER_PROGRAM_ID: Pubkey = pubkey!("rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ");
+
+// Pyth 价格账户（PriceUpdateV2）的 Anchor 判别符：sha256("account:PriceUpdateV2") 的前 8 字节
+const PYTH_PRICE_UPDATE_DISCRIMINATOR: [u8; 8] = [34, 241, 35, 99, 157, 126, 244, 205];
+
 // 定义 orderbook 程序模块
 #[program]
 pub mod orderbook {
     use super::*;
 
     // 初始化订单簿，设置基础代币、报价代币及初始订单数据
-    pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
-        let orderbook = &mut ctx.accounts.orderbook;
-        orderbook.base_mint = ctx.accounts.base_mint.key(); // 设置基础代币公钥
-        orderbook.quote_mint = ctx.accounts.quote_mint.key(); // 设置报价代币公钥
-        orderbook.bids = Vec::new(); // 初始化买单列表
-        orderbook.asks = Vec::new(); // 初始化卖单列表
-        orderbook.order_id_counter = 0; // 初始化订单 ID 计数器
-        Ok(())
+    #[allow(clippy::too_many_arguments)]
+    pub fn initialize(
+        ctx: Context<Initialize>,
+        min_fill_notional: u64, // 单笔成交的最小报价代币金额
+        execution_price_mode: ExecutionPriceMode, // 成交价格模式
+        maker_fee_bps: u16, // maker 手续费（基点）
+        taker_fee_bps: u16, // taker 手续费（基点）
+        min_base_quantity: u64, // 单笔订单的最小基础代币数量
+        price_tick: u64, // 价格最小变动单位
+        native_leg: NativeLeg, // 以原生 SOL 结算的一方（该方代币必须为 wSOL）
+        price_is_scaled: bool, // 价格是否按每个完整基础代币计价（报价金额除以 10^base_decimals）
+        max_orders_per_owner: u16, // 每个拥有者在订单簿上的挂单数量上限（0 表示不限制）
+    ) -> Result<()> {
+        init_orderbook(
+            &mut ctx.accounts.orderbook,
+            &ctx.accounts.base_mint,
+            &ctx.accounts.quote_mint,
+            ctx.accounts.payer.key(),
+            ctx.accounts.base_vault.key(),
+            ctx.accounts.quote_vault.key(),
+            min_fill_notional,
+            execution_price_mode,
+            maker_fee_bps,
+            taker_fee_bps,
+            min_base_quantity,
+            price_tick,
+            native_leg,
+            price_is_scaled,
+            max_orders_per_owner,
+        )
+    }
+
+    // 使用预先创建的关联代币账户（ATA）作为金库初始化订单簿，其余参数与 initialize 相同；
+    // 金库必须是订单簿 PDA 在对应代币上的标准 ATA，手续费金库仍为 PDA
+    #[allow(clippy::too_many_arguments)]
+    pub fn initialize_with_vaults(
+        ctx: Context<InitializeWithVaults>,
+        min_fill_notional: u64, // 单笔成交的最小报价代币金额
+        execution_price_mode: ExecutionPriceMode, // 成交价格模式
+        maker_fee_bps: u16, // maker 手续费（基点）
+        taker_fee_bps: u16, // taker 手续费（基点）
+        min_base_quantity: u64, // 单笔订单的最小基础代币数量
+        price_tick: u64, // 价格最小变动单位
+        native_leg: NativeLeg, // 以原生 SOL 结算的一方（该方代币必须为 wSOL）
+        price_is_scaled: bool, // 价格是否按每个完整基础代币计价（报价金额除以 10^base_decimals）
+        max_orders_per_owner: u16, // 每个拥有者在订单簿上的挂单数量上限（0 表示不限制）
+    ) -> Result<()> {
+        init_orderbook(
+            &mut ctx.accounts.orderbook,
+            &ctx.accounts.base_mint,
+            &ctx.accounts.quote_mint,
+            ctx.accounts.payer.key(),
+            ctx.accounts.base_vault.key(),
+            ctx.accounts.quote_vault.key(),
+            min_fill_notional,
+            execution_price_mode,
+            maker_fee_bps,
+            taker_fee_bps,
+            min_base_quantity,
+            price_tick,
+            native_leg,
+            price_is_scaled,
+            max_orders_per_owner,
+        )
     }
 
     // 下单函数，处理买入或卖出订单
+    #[allow(clippy::too_many_arguments)]
     pub fn place_order<'info>(
         ctx: Context<'_, '_, 'info, 'info, PlaceOrder<'info>>,
         side: Side,    // 订单方向（买/卖）
         price: u64,    // 订单价格
         quantity: u64, // 订单数量
-    ) -> Result<()> {
+        referral_code: Option<String>, // 可选推荐码，用于解析推荐人
+        oco_group_id: u64, // OCO 组 ID（0 表示不属于任何组）
+        order_type: OrderType, // 订单类型（限价/市价）
+        max_quote: u64, // 市价买单愿意支付的报价代币上限（其他订单忽略）
+        time_in_force: TimeInForce, // 订单有效方式（GTC/IOC/FOK）
+        post_only: bool, // 只做 maker：会与对手盘交叉时拒绝下单
+        expiry_ts: i64, // 订单过期时间戳（0 表示永不过期）
+        self_trade_prevention: SelfTradePrevention, // 自成交防范方式
+        worst_price: u64, // 滑点保护：可接受的最差成交价格（0 表示不限制）
+        defer_matching: bool, // 只锁定资金并挂单，不在下单时撮合，交叉部分由 match_orders 撮合
+        display_quantity: u64, // 冰山单每次显示的数量（0 表示全部显示）
+        quote_budget: bool, // 买单的 quantity 表示报价代币预算，逐笔按 maker 价格换算买入数量直到预算用完
+        settlement_authority: Option<Pubkey>, // 挂单成交所得的接收方（None 表示订单拥有者）
+        rest_remainder: bool, // GTC 限价单未成交的部分是否挂单（false 时按限价撮合后退还剩余锁定资金）
+        must_improve: bool, // post-only 订单必须优于同方向当前最优价，与最优价持平时拒绝
+    ) -> Result<PlaceOrderResult> {
+        // 市场暂停时拒绝下单，必须在任何状态修改和资金锁定之前检查
+        require!(!ctx.accounts.orderbook.paused, DexError::MarketPaused);
+        init_claimable(ctx.accounts, ctx.bumps.claimable);
+
+        // 拒绝零数量订单，以及零价格的限价单（零价格买单不锁定任何报价代币却能成交）；
+        // 市价单不使用价格参数，止损限价单的价格在 place_stop_order 中校验
+        require!(quantity > 0, DexError::InvalidOrderParams);
+        require!(
+            price > 0 || order_type != OrderType::Limit,
+            DexError::InvalidOrderParams
+        );
+        // 按报价代币预算买入只支持买入的限价单与市价单；FOK 需要确定的基础代币数量，不支持
+        require!(
+            !quote_budget
+                || (side == Side::Buy
+                    && matches!(order_type, OrderType::Limit | OrderType::Market)
+                    && time_in_force != TimeInForce::FillOrKill),
+            DexError::InvalidOrderParams
+        );
+        // 按报价代币预算买入时，预算作为报价上限，基础代币数量不设上限（由预算约束）
+        let (quantity, max_quote) = if quote_budget {
+            (u64::MAX, quantity)
+        } else {
+            (quantity, max_quote)
+        };
+
+        // 原生 SOL 市场上挂单在原生 SOL 一方的所得只转入拥有者的托管 wSOL 账户，不支持指定结算方
+        require!(
+            settlement_authority.is_none() || ctx.accounts.orderbook.native_leg == NativeLeg::None,
+            DexError::InvalidOrderParams
+        );
+
+        // 止损限价单不参与撮合：锁定资金后存入止损单列表，等待 trigger_stops 触发
+        if let OrderType::StopLimit { trigger_price, limit_price } = order_type {
+            // 止损单触发后由曲柄代为结算，与挂单一样需要托管 wSOL 账户
+            require_native_escrow(
+                &ctx.accounts.orderbook,
+                ctx.accounts.native_escrow.as_ref(),
+                ctx.accounts.claimable.key(),
+            )?;
+            // 止损单不挂到订单簿上，也没有成交
+            place_stop_order(ctx, side, trigger_price, limit_price, quantity)?;
+            return Ok(PlaceOrderResult::default());
+        }
+
+        // 解析推荐码对应的推荐人（未提供推荐码时为 None）
+        let referrer = resolve_referrer(referral_code.as_deref(), ctx.accounts.referral.as_ref())?;
+
         let orderbook = &mut ctx.accounts.orderbook; // 可变引用订单簿
         let owner = &ctx.accounts.owner; // 订单拥有者
         let token_program = &ctx.accounts.token_program; // 代币程序
 
-        // 创建 taker 订单，初始化订单信息
+        // 验证订单数量与价格精度，必须在锁定资金之前完成；
+        // 按报价代币预算买入的限价单以预算按限价可买入的数量校验
+        let base_quantity = match (quote_budget, order_type) {
+            (true, OrderType::Limit) => base_for_quote(max_quote, price, orderbook.price_scale())?,
+            _ => quantity,
+        };
+        require!(base_quantity >= orderbook.min_base_quantity, DexError::OrderTooSmall);
+        require!(
+            price.checked_rem(orderbook.price_tick) == Some(0),
+            DexError::InvalidTickSize
+        );
+        // 限价单的名义金额不得低于下限（市价单没有价格，由单笔最小成交额约束），
+        // 且价格不得偏离预言机价格带
+        if order_type == OrderType::Limit {
+            require_min_notional(orderbook, price, base_quantity)?;
+            require_within_oracle_band(orderbook, ctx.accounts.oracle.as_deref(), price)?;
+        }
+
+        // 创建 taker 订单，初始化订单信息；每个订单在进入时分配 ID，
+        // 成交事件据此关联 taker 订单，剩余部分挂单时沿用同一 ID
+        orderbook.order_id_counter += 1; // 增加订单 ID
         let mut taker_order = Order {
             owner: owner.key(),
             price,
             quantity,
-            order_id: 0,
+            order_id: orderbook.order_id_counter,
+            frozen: false,
+            oco_group_id,
+            expiry_ts,
+            locked_quote: 0,
+            locked_base: 0,
+            display_size: display_quantity,
+            display_quantity: 0,
+            settlement_authority,
+            original_quantity: quantity,
         };
+        // 当前时间，用于判断挂单是否过期
+        let now = Clock::get()?.unix_timestamp;
 
-        // 1. 锁定资金
-        match side {
+        // 只有 GTC 限价单的剩余部分会挂到订单簿上（且未要求不挂单），其余订单未成交部分直接退款
+        let rests_remainder = rest_remainder
+            && order_type == OrderType::Limit
+            && time_in_force == TimeInForce::GoodTillCancel;
+
+        // 集合竞价模式下所有订单都只挂单，交叉部分由 run_auction 按统一价格撮合
+        let defer_matching = defer_matching || orderbook.auction_mode;
+
+        // 延迟撮合的订单必须能够挂单
+        require!(
+            !defer_matching || rests_remainder,
+            DexError::InvalidDeferredOrder
+        );
+        if rests_remainder {
+            require_native_escrow(
+                orderbook,
+                ctx.accounts.native_escrow.as_ref(),
+                ctx.accounts.claimable.key(),
+            )?;
+        }
+
+        // FOK 订单：在任何代币转移之前，只读遍历对手盘确认能全部成交，否则直接拒绝
+        if time_in_force == TimeInForce::FillOrKill {
+            let estimate = estimate_fills(
+                orderbook,
+                &side,
+                &taker_order,
+                order_type,
+                max_quote,
+                quote_budget,
+                self_trade_prevention,
+                worst_price,
+                now,
+            )?;
+            require!(estimate.quantity == quantity, DexError::FillOrKillUnsatisfied);
+        }
+
+        // post-only 订单：价格与对手盘最优价交叉时拒绝，此检查必须在锁定资金之前
+        if post_only {
+            let would_cross = match side {
+                Side::Buy => orderbook
+                    .asks
+                    .iter()
+                    .map(|order| order.price)
+                    .min()
+                    .is_some_and(|best_ask_price| price >= best_ask_price),
+                Side::Sell => orderbook
+                    .bids
+                    .iter()
+                    .map(|order| order.price)
+                    .max()
+                    .is_some_and(|best_bid_price| price <= best_bid_price),
+            };
+            require!(!would_cross, DexError::PostOnlyWouldCross);
+        }
+
+        // 要求改善最优价的 post-only 订单：价格必须严格优于同方向当前最优价，
+        // 不能排在同价挂单之后（同方向没有挂单时不限制）
+        if must_improve {
+            require!(post_only, DexError::InvalidOrderParams);
+            let improves = match side {
+                Side::Buy => orderbook
+                    .bids
+                    .iter()
+                    .map(|order| order.price)
+                    .max()
+                    .is_none_or(|best_bid_price| price > best_bid_price),
+                Side::Sell => orderbook
+                    .asks
+                    .iter()
+                    .map(|order| order.price)
+                    .min()
+                    .is_none_or(|best_ask_price| price < best_ask_price),
+            };
+            require!(improves, DexError::MustImprove);
+        }
+
+        // 1. 锁定资金：按金库实际收到的数量入账（带转账手续费的 Token-2022 代币到账会少于转出数量）
+        let quote_locked = match side {
             Side::Buy => {
-                // 计算买入订单需锁定的报价代币总量
-                let total_quote_to_lock = taker_order
-                    .price
-                    .checked_mul(taker_order.quantity)
-                    .ok_or(DexError::CalculationError)?;
+                // 计算买入订单需锁定的报价代币总量（按报价预算买入时恰好锁定预算）
+                let total_quote_to_lock = if quote_budget {
+                    max_quote
+                } else {
+                    quote_to_lock(
+                        order_type,
+                        price,
+                        quantity,
+                        max_quote,
+                        orderbook.price_scale(),
+                    )?
+                };
+                if orderbook.native_leg == NativeLeg::Quote {
+                    wrap_native(
+                        &ctx.accounts.system_program,
+                        token_program,
+                        owner,
+                        &ctx.accounts.owner_quote_token_account,
+                        total_quote_to_lock,
+                    )?;
+                }
                 // 执行代币转移，从用户账户到报价金库
-                token::transfer(
-                    CpiContext::new(
-                        token_program.to_account_info(),
-                        Transfer {
-                            from: ctx.accounts.owner_quote_token_account.to_account_info(),
-                            to: ctx.accounts.quote_vault.to_account_info(),
-                            authority: owner.to_account_info(),
-                        },
-                    ),
+                transfer_to_vault(
+                    token_program,
+                    &ctx.accounts.owner_quote_token_account,
+                    &mut ctx.accounts.quote_vault,
+                    &ctx.accounts.quote_mint,
+                    owner.to_account_info(),
                     total_quote_to_lock,
-                )?;
+                )?
             }
             Side::Sell => {
-                // 执行代币转移，从用户账户到基础金库
-                token::transfer(
-                    CpiContext::new(
-                        token_program.to_account_info(),
-                        Transfer {
-                            from: ctx.accounts.owner_base_token_account.to_account_info(),
-                            to: ctx.accounts.base_vault.to_account_info(),
-                            authority: owner.to_account_info(),
-                        },
-                    ),
+                if orderbook.native_leg == NativeLeg::Base {
+                    wrap_native(
+                        &ctx.accounts.system_program,
+                        token_program,
+                        owner,
+                        &ctx.accounts.owner_base_token_account,
+                        taker_order.quantity,
+                    )?;
+                }
+                // 执行代币转移，从用户账户到基础金库，卖单数量以实际到账数量为准
+                taker_order.quantity = transfer_to_vault(
+                    token_program,
+                    &ctx.accounts.owner_base_token_account,
+                    &mut ctx.accounts.base_vault,
+                    &ctx.accounts.base_mint,
+                    owner.to_account_info(),
                     taker_order.quantity,
                 )?;
+                0
             }
-        }
+        };
+        // 买单按实际锁定的报价代币确定可成交数量（限价单）或预算（市价单）
+        let max_quote = match (&side, order_type) {
+            (Side::Buy, OrderType::Limit | OrderType::StopLimit { .. }) if !quote_budget => {
+                let affordable_quantity =
+                    base_for_quote(quote_locked, price, orderbook.price_scale())?;
+                taker_order.quantity = taker_order.quantity.min(affordable_quantity);
+                max_quote
+            }
+            (Side::Buy, _) => quote_locked,
+            (Side::Sell, _) => max_quote,
+        };
+        let quantity = taker_order.quantity; // 实际入账的下单数量
 
         // 设置订单簿种子和签名者
         let base_mint_key = orderbook.base_mint;
@@ -99,198 +410,348 @@ pub mod orderbook {
         ];
         let signer = &[&orderbook_seeds[..]];
 
+        // maker 账户按撮合顺序依次传入，整个撮合过程共用同一个迭代器
+        let mut maker_accounts_iter = ctx.remaining_accounts.iter().peekable();
+
+        // 撮合前按撮合规则预先遍历对手盘，确认传入的 maker 账户足够（每个 maker 一组账户），
+        // 不足时直接报错，而不是在撮合中途因缺少账户失败
+        if !(post_only || defer_matching) {
+            let estimate = estimate_fills(
+                orderbook,
+                &side,
+                &taker_order,
+                order_type,
+                max_quote,
+                quote_budget,
+                self_trade_prevention,
+                worst_price,
+                now,
+            )?;
+            require!(
+                maker_accounts_iter.len() >= MAKER_ACCOUNTS_LEN * estimate.makers,
+                DexError::InsufficientMakerAccounts
+            );
+        }
+
         // 2. 核心撮合逻辑
-        match side {
-            Side::Buy => {
-                // 循环处理买单撮合
-                while taker_order.quantity > 0 {
-                    // 获取最佳卖单价格
-                    let best_ask_price = match orderbook.asks.last() {
-                        Some(order) => order.price,
-                        None => break, // 无卖单，退出
-                    };
+        let summary = match_order(
+            orderbook,
+            &MatchAccounts {
+                token_program,
+                base_mint: &ctx.accounts.base_mint,
+                quote_mint: &ctx.accounts.quote_mint,
+                base_vault: &ctx.accounts.base_vault,
+                quote_vault: &ctx.accounts.quote_vault,
+                base_fee_vault: &ctx.accounts.base_fee_vault,
+                quote_fee_vault: &ctx.accounts.quote_fee_vault,
+                taker_base_token_account: &ctx.accounts.owner_base_token_account,
+                taker_quote_token_account: &ctx.accounts.owner_quote_token_account,
+            },
+            signer,
+            &mut maker_accounts_iter,
+            &mut taker_order,
+            &side,
+            order_type,
+            max_quote,
+            quote_budget,
+            quote_locked,
+            rests_remainder,
+            post_only || defer_matching,
+            self_trade_prevention,
+            worst_price,
+            referrer,
+            now,
+            usize::MAX,
+        )?;
 
-                    // 如果买单价格低于最佳卖单价格，退出
-                    if taker_order.price < best_ask_price {
-                        break;
-                    }
+        // taker 订单属于 OCO 组且已有成交时，撤销同组其余挂单并退款给 taker
+        // （按报价预算买入的订单撮合后数量已按剩余预算换算，以成交笔数判断）
+        let filled = if quote_budget {
+            summary.fills > 0
+        } else {
+            taker_order.quantity < quantity
+        };
+        if taker_order.oco_group_id != 0 && filled {
+            let (quote_refund, base_refund) = cancel_oco_group(
+                orderbook,
+                &mut Vec::new(),
+                &side,
+                taker_order.owner,
+                taker_order.oco_group_id,
+            )?;
+            transfer_from_vault(
+                token_program,
+                &ctx.accounts.quote_vault,
+                &ctx.accounts.quote_mint,
+                ctx.accounts.owner_quote_token_account.to_account_info(),
+                orderbook.to_account_info(),
+                signer,
+                quote_refund,
+            )?;
+            transfer_from_vault(
+                token_program,
+                &ctx.accounts.base_vault,
+                &ctx.accounts.base_mint,
+                ctx.accounts.owner_base_token_account.to_account_info(),
+                orderbook.to_account_info(),
+                signer,
+                base_refund,
+            )?;
+        }
 
-                    // 弹出最佳卖单进行撮合
-                    let mut maker_order = orderbook.asks.pop().unwrap();
-                    let maker_accounts =
-                        get_next_maker_accounts(&mut ctx.remaining_accounts.iter().peekable())?;
-
-                    // 验证 maker 账户所有者匹配
-                    require_keys_eq!(
-                        maker_accounts.owner_token_account.owner,
-                        maker_order.owner,
-                        DexError::MakerAccountMismatch
-                    );
-
-                    // 计算交易数量（取最小值）
-                    let trade_quantity = taker_order.quantity.min(maker_order.quantity);
-                    let trade_price = maker_order.price;
-                    // 计算报价代币转移总量
-                    let total_quote_transfer = trade_price
-                        .checked_mul(trade_quantity)
-                        .ok_or(DexError::CalculationError)?;
+        // FOK 订单必须全部成交（撮合中 OCO 撤单可能减少流动性，此处再次确认）
+        if time_in_force == TimeInForce::FillOrKill {
+            require!(taker_order.quantity == 0, DexError::FillOrKillUnsatisfied);
+        }
 
-                    // 转移基础代币给 taker
-                    token::transfer(
-                        CpiContext::new_with_signer(
-                            token_program.to_account_info(),
-                            Transfer {
-                                from: ctx.accounts.base_vault.to_account_info(),
-                                to: ctx.accounts.owner_base_token_account.to_account_info(),
-                                authority: orderbook.to_account_info(),
-                            },
-                            signer,
-                        ),
-                        trade_quantity,
-                    )?;
+        // 不挂单的卖单（市价单、IOC）未成交的部分直接退还基础代币
+        if !rests_remainder && side == Side::Sell {
+            transfer_from_vault(
+                token_program,
+                &ctx.accounts.base_vault,
+                &ctx.accounts.base_mint,
+                ctx.accounts.owner_base_token_account.to_account_info(),
+                orderbook.to_account_info(),
+                signer,
+                taker_order.quantity,
+            )?;
+        }
 
-                    // 转移报价代币给 maker
-                    token::transfer(
-                        CpiContext::new_with_signer(
-                            token_program.to_account_info(),
-                            Transfer {
-                                from: ctx.accounts.quote_vault.to_account_info(),
-                                to: maker_accounts.quote_token_account.to_account_info(),
-                                authority: orderbook.to_account_info(),
-                            },
-                            signer,
-                        ),
-                        total_quote_transfer,
-                    )?;
+        // 3. 添加剩余订单到订单簿（仅 GTC 限价单），拥有者的挂单数量不得超过上限
+        let resting_order_id = if rests_remainder && taker_order.quantity > 0 {
+            require_owner_capacity(orderbook, taker_order.owner)?;
+            rest_order(orderbook, &side, taker_order)?;
+            taker_order.order_id
+        } else {
+            0
+        };
 
-                    // 触发交易事件
-                    emit!(TradeEvent {
-                        taker: owner.key(),
-                        maker: maker_order.owner,
-                        base_mint: base_mint_key,
-                        quote_mint: quote_mint_key,
-                        quantity: trade_quantity,
-                        price: trade_price,
-                    });
-
-                    // 更新订单数量
-                    taker_order.quantity -= trade_quantity;
-                    maker_order.quantity -= trade_quantity;
-
-                    // 如果 maker 订单仍有剩余，重新加入订单簿
-                    if maker_order.quantity > 0 {
-                        orderbook.asks.push(maker_order);
-                    }
-                }
-            }
-            Side::Sell => {
-                // 循环处理卖单撮合
-                while taker_order.quantity > 0 {
-                    // 获取最佳买单价格
-                    let best_bid_price = match orderbook.bids.last() {
-                        Some(order) => order.price,
-                        None => break, // 无买单，退出
-                    };
+        // 原生 SOL 一方的成交所得与退款解包为 SOL
+        unwrap_native(
+            orderbook.native_leg,
+            token_program,
+            owner,
+            &ctx.accounts.owner_base_token_account,
+            &ctx.accounts.owner_quote_token_account,
+        )?;
 
-                    // 如果卖单价格高于最佳买单价格，退出
-                    if taker_order.price > best_bid_price {
-                        break;
-                    }
+        // 撮合结果作为返回数据，CPI 调用方无需解析事件即可同步获取
+        Ok(PlaceOrderResult {
+            filled_quantity: summary.filled_quantity,
+            average_fill_price: summary.average_price(),
+            resting_order_id,
+            quote_spent: summary.quote_spent,
+            quote_received: summary.quote_received,
+        })
+    }
 
-                    // 弹出最佳买单进行撮合
-                    let mut maker_order = orderbook.bids.pop().unwrap();
-                    let maker_accounts =
-                        get_next_maker_accounts(&mut ctx.remaining_accounts.iter().peekable())?;
-
-                    // 验证 maker 账户所有者匹配
-                    require_keys_eq!(
-                        maker_accounts.owner_token_account.owner,
-                        maker_order.owner,
-                        DexError::MakerAccountMismatch
-                    );
-
-                    // 计算交易数量（取最小值）
-                    let trade_quantity = taker_order.quantity.min(maker_order.quantity);
-                    let trade_price = maker_order.price;
-                    // 计算报价代币转移总量
-                    let total_quote_transfer = trade_price
-                        .checked_mul(trade_quantity)
-                        .ok_or(DexError::CalculationError)?;
+    // 批量下单：按顺序处理多个 GTC 限价单，每个订单依次撮合并挂出剩余部分。
+    // 所有买单的报价代币和所有卖单的基础代币各自合计后一次性锁定；
+    // maker 账户按整个批次的撮合顺序依次传入，前面订单已使用的账户不会被后面的订单重复读取
+    pub fn place_orders<'info>(
+        ctx: Context<'_, '_, 'info, 'info, PlaceOrder<'info>>,
+        orders: Vec<OrderRequest>, // 待下的订单，按顺序处理
+    ) -> Result<()> {
+        // 市场暂停时拒绝下单，必须在任何状态修改和资金锁定之前检查
+        require!(!ctx.accounts.orderbook.paused, DexError::MarketPaused);
+        init_claimable(ctx.accounts, ctx.bumps.claimable);
 
-                    // 转移基础代币给 maker
-                    token::transfer(
-                        CpiContext::new_with_signer(
-                            token_program.to_account_info(),
-                            Transfer {
-                                from: ctx.accounts.base_vault.to_account_info(),
-                                to: maker_accounts.owner_token_account.to_account_info(),
-                                authority: orderbook.to_account_info(),
-                            },
-                            signer,
-                        ),
-                        trade_quantity,
-                    )?;
+        let orderbook = &mut ctx.accounts.orderbook; // 可变引用订单簿
+        let owner = &ctx.accounts.owner; // 订单拥有者
+        let token_program = &ctx.accounts.token_program; // 代币程序
 
-                    // 转移报价代币给 taker
-                    token::transfer(
-                        CpiContext::new_with_signer(
-                            token_program.to_account_info(),
-                            Transfer {
-                                from: ctx.accounts.quote_vault.to_account_info(),
-                                to: ctx.accounts.owner_quote_token_account.to_account_info(),
-                                authority: orderbook.to_account_info(),
-                            },
-                            signer,
-                        ),
-                        total_quote_transfer,
+        // 验证每个订单的数量与价格精度，并按方向合计需锁定的资金
+        let mut total_quote: u64 = 0;
+        let mut total_base: u64 = 0;
+        for request in &orders {
+            require!(
+                request.price > 0 && request.quantity > 0,
+                DexError::InvalidOrderParams
+            );
+            require!(request.quantity >= orderbook.min_base_quantity, DexError::OrderTooSmall);
+            require!(
+                request.price.checked_rem(orderbook.price_tick) == Some(0),
+                DexError::InvalidTickSize
+            );
+            require_min_notional(orderbook, request.price, request.quantity)?;
+            require_within_oracle_band(orderbook, ctx.accounts.oracle.as_deref(), request.price)?;
+            match request.side {
+                Side::Buy => {
+                    let quote = quote_to_lock(
+                        OrderType::Limit,
+                        request.price,
+                        request.quantity,
+                        0,
+                        orderbook.price_scale(),
                     )?;
-
-                    // 触发交易事件
-                    emit!(TradeEvent {
-                        taker: owner.key(),
-                        maker: maker_order.owner,
-                        base_mint: base_mint_key,
-                        quote_mint: quote_mint_key,
-                        quantity: trade_quantity,
-                        price: trade_price,
-                    });
-
-                    // 更新订单数量
-                    taker_order.quantity -= trade_quantity;
-                    maker_order.quantity -= trade_quantity;
-
-                    // 如果 maker 订单仍有剩余，重新加入订单簿
-                    if maker_order.quantity > 0 {
-                        orderbook.bids.push(maker_order);
-                    }
+                    total_quote = total_quote
+                        .checked_add(quote)
+                        .ok_or(DexError::CalculationError)?;
+                }
+                Side::Sell => {
+                    total_base = total_base
+                        .checked_add(request.quantity)
+                        .ok_or(DexError::CalculationError)?;
                 }
             }
         }
 
-        // 3. 添加剩余订单到订单簿
-        if taker_order.quantity > 0 {
+        // 1. 一次性锁定资金，金库必须收到全额（各订单的锁定金额按限价分别计算）
+        if total_quote > 0 {
+            if orderbook.native_leg == NativeLeg::Quote {
+                wrap_native(
+                    &ctx.accounts.system_program,
+                    token_program,
+                    owner,
+                    &ctx.accounts.owner_quote_token_account,
+                    total_quote,
+                )?;
+            }
+            let quote_locked = transfer_to_vault(
+                token_program,
+                &ctx.accounts.owner_quote_token_account,
+                &mut ctx.accounts.quote_vault,
+                &ctx.accounts.quote_mint,
+                owner.to_account_info(),
+                total_quote,
+            )?;
+            require!(quote_locked == total_quote, DexError::TransferAmountShortfall);
+        }
+        if total_base > 0 {
+            if orderbook.native_leg == NativeLeg::Base {
+                wrap_native(
+                    &ctx.accounts.system_program,
+                    token_program,
+                    owner,
+                    &ctx.accounts.owner_base_token_account,
+                    total_base,
+                )?;
+            }
+            let base_locked = transfer_to_vault(
+                token_program,
+                &ctx.accounts.owner_base_token_account,
+                &mut ctx.accounts.base_vault,
+                &ctx.accounts.base_mint,
+                owner.to_account_info(),
+                total_base,
+            )?;
+            require!(base_locked == total_base, DexError::TransferAmountShortfall);
+        }
+
+        // 设置订单簿种子和签名者
+        let base_mint_key = orderbook.base_mint;
+        let quote_mint_key = orderbook.quote_mint;
+        let orderbook_seeds = &[
+            b"orderbook".as_ref(),
+            base_mint_key.as_ref(),
+            quote_mint_key.as_ref(),
+            &[ctx.bumps.orderbook],
+        ];
+        let signer = &[&orderbook_seeds[..]];
+        let now = Clock::get()?.unix_timestamp;
+
+        // 批量订单都是 GTC 限价单，剩余部分会挂单
+        require_native_escrow(
+            orderbook,
+            ctx.accounts.native_escrow.as_ref(),
+            ctx.accounts.claimable.key(),
+        )?;
+
+        // maker 账户在整个批次中共用同一个迭代器
+        let mut maker_accounts_iter = ctx.remaining_accounts.iter().peekable();
+        // 集合竞价模式下只挂单，不撮合
+        let skip_matching = orderbook.auction_mode;
+
+        for request in orders {
             orderbook.order_id_counter += 1; // 增加订单 ID
-            let new_maker_order = Order {
-                owner: taker_order.owner,
-                price: taker_order.price,
-                quantity: taker_order.quantity,
+            let mut taker_order = Order {
+                owner: owner.key(),
+                price: request.price,
+                quantity: request.quantity,
                 order_id: orderbook.order_id_counter,
-            };
-            match side {
-                Side::Buy => orderbook.bids.push(new_maker_order), // 添加到买单列表
-                Side::Sell => orderbook.asks.push(new_maker_order), // 添加到卖单列表
-            };
-        }
+                frozen: false,
+                oco_group_id: 0,
+                expiry_ts: 0,
+                locked_quote: 0,
+                locked_base: 0,
+                display_size: 0,
+                display_quantity: 0,
+                settlement_authority: None,
+                original_quantity: request.quantity,
+            }
+            .with_locked_funds(&request.side, orderbook.price_scale())?;
+            let quote_locked = total_locked(&[taker_order], &request.side)?;
 
-        // 4. 重新排序订单簿，买单按价格降序，卖单按价格升序
-        orderbook.bids.sort_by(|a, b| b.price.cmp(&a.price));
-        orderbook.asks.sort_by(|a, b| a.price.cmp(&b.price));
+            // 按当前订单簿预估该订单需要的 maker 账户，剩余账户不足时直接报错
+            if !skip_matching {
+                let estimate = estimate_fills(
+                    orderbook,
+                    &request.side,
+                    &taker_order,
+                    OrderType::Limit,
+                    0,
+                    false,
+                    SelfTradePrevention::CancelResting,
+                    0,
+                    now,
+                )?;
+                require!(
+                    maker_accounts_iter.len() >= MAKER_ACCOUNTS_LEN * estimate.makers,
+                    DexError::InsufficientMakerAccounts
+                );
+            }
 
-        Ok(())
+            // 2. 撮合
+            match_order(
+                orderbook,
+                &MatchAccounts {
+                    token_program,
+                    base_mint: &ctx.accounts.base_mint,
+                    quote_mint: &ctx.accounts.quote_mint,
+                    base_vault: &ctx.accounts.base_vault,
+                    quote_vault: &ctx.accounts.quote_vault,
+                    base_fee_vault: &ctx.accounts.base_fee_vault,
+                    quote_fee_vault: &ctx.accounts.quote_fee_vault,
+                    taker_base_token_account: &ctx.accounts.owner_base_token_account,
+                    taker_quote_token_account: &ctx.accounts.owner_quote_token_account,
+                },
+                signer,
+                &mut maker_accounts_iter,
+                &mut taker_order,
+                &request.side,
+                OrderType::Limit,
+                0,
+                false,
+                quote_locked,
+                true,
+                skip_matching,
+                SelfTradePrevention::CancelResting,
+                0,
+                None,
+                now,
+                usize::MAX,
+            )?;
+
+            // 3. 剩余部分挂到订单簿，拥有者的挂单数量不得超过上限
+            if taker_order.quantity > 0 {
+                require_owner_capacity(orderbook, taker_order.owner)?;
+                rest_order(orderbook, &request.side, taker_order)?;
+            }
+        }
+
+        // 原生 SOL 一方的成交所得与退款解包为 SOL
+        unwrap_native(
+            orderbook.native_leg,
+            token_program,
+            owner,
+            &ctx.accounts.owner_base_token_account,
+            &ctx.accounts.owner_quote_token_account,
+        )
     }
 
-    // 取消订单，退还锁定资金
-    pub fn cancel_order(ctx: Context<CancelOrder>, order_id: u64) -> Result<()> {
+    // 取消订单，退还锁定资金。side 指明订单所在方向，只在该方向的挂单和止损单中查找，
+    // 方向与订单不符时返回 OrderNotFound
+    pub fn cancel_order(ctx: Context<CancelOrder>, order_id: u64, side: Side) -> Result<()> {
         let orderbook = &mut ctx.accounts.orderbook; // 可变引用订单簿
         let owner = &ctx.accounts.owner; // 订单拥有者
 
@@ -304,7 +765,10 @@ pub mod orderbook {
         let signer = &[&orderbook_seeds[..]];
 
         // 查找并取消买单
-        if let Some(index) = orderbook.bids.iter().position(|o| o.order_id == order_id) {
+        if let Some(index) = (side == Side::Buy)
+            .then(|| orderbook.bids.iter().position(|o| o.order_id == order_id))
+            .flatten()
+        {
             let order_to_cancel = &orderbook.bids[index];
             // 验证订单拥有者
             require!(
@@ -312,32 +776,51 @@ pub mod orderbook {
                 DexError::OrderNotOwned
             );
 
-            // 计算需退还的报价代币总量
-            let total_quote_amount = order_to_cancel
-                .price
-                .checked_mul(order_to_cancel.quantity)
-                .ok_or(DexError::CalculationError)?;
+            // 退还该买单实际锁定的报价代币
+            let total_quote_amount = order_to_cancel.locked_quote;
 
             // 退还报价代币
-            token::transfer(
+            token_interface::transfer_checked(
                 CpiContext::new_with_signer(
                     ctx.accounts.token_program.to_account_info(),
-                    Transfer {
+                    TransferChecked {
                         from: ctx.accounts.quote_vault.to_account_info(),
+                        mint: ctx.accounts.quote_mint.to_account_info(),
                         to: ctx.accounts.owner_quote_token_account.to_account_info(),
                         authority: orderbook.to_account_info(),
                     },
                     signer,
                 ),
                 total_quote_amount,
+                ctx.accounts.quote_mint.decimals,
             )?;
 
+            // 触发撤单事件
+            emit!(OrderCanceledEvent {
+                order_id,
+                owner: owner.key(),
+                side: Side::Buy,
+                quantity: order_to_cancel.quantity,
+                refund_mint: orderbook.quote_mint,
+                refund_amount: total_quote_amount,
+            });
+
             orderbook.bids.remove(index); // 从买单列表移除
-            return Ok(());
+            // 原生 SOL 一方的退款解包为 SOL
+            return unwrap_native(
+                orderbook.native_leg,
+                &ctx.accounts.token_program,
+                owner,
+                &ctx.accounts.owner_base_token_account,
+                &ctx.accounts.owner_quote_token_account,
+            );
         }
 
         // 查找并取消卖单
-        if let Some(index) = orderbook.asks.iter().position(|o| o.order_id == order_id) {
+        if let Some(index) = (side == Side::Sell)
+            .then(|| orderbook.asks.iter().position(|o| o.order_id == order_id))
+            .flatten()
+        {
             let order_to_cancel = &orderbook.asks[index];
             // 验证订单拥有者
             require!(
@@ -345,165 +828,4515 @@ pub mod orderbook {
                 DexError::OrderNotOwned
             );
 
-            // 退还基础代币
-            token::transfer(
+            // 退还该卖单实际锁定的基础代币
+            token_interface::transfer_checked(
                 CpiContext::new_with_signer(
                     ctx.accounts.token_program.to_account_info(),
-                    Transfer {
+                    TransferChecked {
                         from: ctx.accounts.base_vault.to_account_info(),
+                        mint: ctx.accounts.base_mint.to_account_info(),
                         to: ctx.accounts.owner_base_token_account.to_account_info(),
                         authority: orderbook.to_account_info(),
                     },
                     signer,
                 ),
-                order_to_cancel.quantity,
+                order_to_cancel.locked_base,
+                ctx.accounts.base_mint.decimals,
             )?;
 
+            // 触发撤单事件
+            emit!(OrderCanceledEvent {
+                order_id,
+                owner: owner.key(),
+                side: Side::Sell,
+                quantity: order_to_cancel.quantity,
+                refund_mint: orderbook.base_mint,
+                refund_amount: order_to_cancel.locked_base,
+            });
+
             orderbook.asks.remove(index); // 从卖单列表移除
-            return Ok(());
+            // 原生 SOL 一方的退款解包为 SOL
+            return unwrap_native(
+                orderbook.native_leg,
+                &ctx.accounts.token_program,
+                owner,
+                &ctx.accounts.owner_base_token_account,
+                &ctx.accounts.owner_quote_token_account,
+            );
+        }
+
+        // 查找并取消未触发的止损单
+        if let Some(index) = orderbook
+            .stops
+            .iter()
+            .position(|o| o.order_id == order_id && o.side == side)
+        {
+            let stop_order = orderbook.stops[index].clone();
+            // 验证订单拥有者
+            require!(stop_order.owner == owner.key(), DexError::OrderNotOwned);
+
+            // 退还锁定资金：买入止损退还报价代币，卖出止损退还基础代币
+            let refund_amount = total_locked(
+                &[stop_order.to_order(orderbook.price_scale())?],
+                &stop_order.side,
+            )?;
+            let (vault, mint, owner_token_account) = match stop_order.side {
+                Side::Buy => (
+                    &ctx.accounts.quote_vault,
+                    &ctx.accounts.quote_mint,
+                    ctx.accounts.owner_quote_token_account.to_account_info(),
+                ),
+                Side::Sell => (
+                    &ctx.accounts.base_vault,
+                    &ctx.accounts.base_mint,
+                    ctx.accounts.owner_base_token_account.to_account_info(),
+                ),
+            };
+            transfer_from_vault(
+                &ctx.accounts.token_program,
+                vault,
+                mint,
+                owner_token_account,
+                orderbook.to_account_info(),
+                signer,
+                refund_amount,
+            )?;
+
+            // 触发撤单事件
+            emit!(OrderCanceledEvent {
+                order_id,
+                owner: owner.key(),
+                refund_mint: orderbook.locked_mint(&stop_order.side),
+                side: stop_order.side,
+                quantity: stop_order.quantity,
+                refund_amount,
+            });
+
+            orderbook.stops.remove(index); // 从止损单列表移除
+            // 原生 SOL 一方的退款解包为 SOL
+            return unwrap_native(
+                orderbook.native_leg,
+                &ctx.accounts.token_program,
+                owner,
+                &ctx.accounts.owner_base_token_account,
+                &ctx.accounts.owner_quote_token_account,
+            );
         }
 
         // 订单未找到，返回错误
         Err(DexError::OrderNotFound.into())
     }
-}
 
-// 从 remaining_accounts 获取 maker 账户信息
-fn get_next_maker_accounts<'info>(
-    iter: &mut Peekable<Iter<'info, AccountInfo<'info>>>,
-) -> Result<MakerAccounts<'info>> {
-    let owner_token_account_info = next_account_info(iter)?; // 获取下一个账户
-    let quote_token_account_info = next_account_info(iter)?; // 获取下一个账户
+    // 部分撤单：把挂单数量减少 reduce_by 并退还对应的锁定资金（买单按价格 × 数量退还报价代币，
+    // 卖单退还等量基础代币），剩余部分保留订单 ID 和排队位置；reduce_by 等于剩余数量时与完整撤单相同
+    pub fn cancel_partial(ctx: Context<CancelOrder>, order_id: u64, reduce_by: u64) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook; // 可变引用订单簿
+        let owner = ctx.accounts.owner.key(); // 订单拥有者
+        require!(reduce_by > 0, DexError::InvalidOrderParams);
 
-    // 手动反序列化为 TokenAccount
-    let owner_token_account = Account::try_from(owner_token_account_info)?;
-    let quote_token_account = Account::try_from(quote_token_account_info)?;
+        // 查找挂单所在方向
+        let (side, index) = if let Some(index) =
+            orderbook.bids.iter().position(|o| o.order_id == order_id)
+        {
+            (Side::Buy, index)
+        } else if let Some(index) =
+            orderbook.asks.iter().position(|o| o.order_id == order_id)
+        {
+            (Side::Sell, index)
+        } else {
+            return Err(DexError::OrderNotFound.into());
+        };
 
-    // 返回 maker 账户结构体
-    Ok(MakerAccounts {
-        owner_token_account,
-        quote_token_account,
-    })
-}
+        let min_base_quantity = orderbook.min_base_quantity;
+        let price_scale = orderbook.price_scale();
+        let orders = match side {
+            Side::Buy => &mut orderbook.bids,
+            Side::Sell => &mut orderbook.asks,
+        };
+        let order = &mut orders[index];
+        // 验证订单拥有者与减少的数量
+        require!(order.owner == owner, DexError::OrderNotOwned);
+        require!(reduce_by <= order.quantity, DexError::ReduceExceedsQuantity);
+        // 剩余部分仍需满足最小下单数量
+        let full_cancel = reduce_by == order.quantity;
+        require!(
+            full_cancel
+                || order
+                    .quantity
+                    .checked_sub(reduce_by)
+                    .ok_or(DexError::CalculationError)?
+                    >= min_base_quantity,
+            DexError::OrderTooSmall
+        );
 
-// 定义 maker 账户结构体，包含基础和报价代币账户
-struct MakerAccounts<'info> {
-    owner_token_account: Account<'info, TokenAccount>,
-    quote_token_account: Account<'info, TokenAccount>,
-}
+        // 减少数量并释放对应的锁定资金，全部撤销时移除挂单
+        let refund_amount = order.reduce(&side, reduce_by, price_scale)?;
+        order.update_display(0);
+        let remaining_quantity = order.quantity;
+        if full_cancel {
+            orders.remove(index);
+        }
 
-// 定义初始化指令的账户结构体
-#[derive(Accounts)]
-pub struct Initialize<'info> {
-    #[account(
-        init,
-        payer = payer,
-        space = 8 + 32 + 32 + 8 + 4 + (56 * 50) + 4 + (56 * 50), // 分配空间
-        seeds = [b"orderbook".as_ref(), base_mint.key().as_ref(), quote_mint.key().as_ref()],
-        bump
-    )]
-    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
-    pub base_mint: Account<'info, Mint>,  // 基础代币
-    pub quote_mint: Account<'info, Mint>, // 报价代币
-    #[account(
-        init,
-        payer = payer,
-        token::mint = base_mint,
-        token::authority = orderbook,
-        seeds = [b"base_vault".as_ref(), orderbook.key().as_ref()],
-        bump
-    )]
-    pub base_vault: Account<'info, TokenAccount>, // 基础代币金库
-    #[account(
-        init,
-        payer = payer,
-        token::mint = quote_mint,
-        token::authority = orderbook,
-        seeds = [b"quote_vault".as_ref(), orderbook.key().as_ref()],
-        bump
-    )]
-    pub quote_vault: Account<'info, TokenAccount>, // 报价代币金库
-    #[account(mut)]
-    pub payer: Signer<'info>, // 支付者
+        // 设置订单簿种子和签名者
+        let orderbook_seeds = &[
+            b"orderbook".as_ref(),
+            orderbook.base_mint.as_ref(),
+            orderbook.quote_mint.as_ref(),
+            &[ctx.bumps.orderbook],
+        ];
+        let signer = &[&orderbook_seeds[..]];
 
-    pub system_program: Program<'info, System>, // 系统程序
-    pub token_program: Program<'info, Token>, // 代币程序
-    //链上内置的“租金数据”，主要用于创建新账户时 → 计算租金豁免额度 → 防止新建账户被回收。
-    pub rent: Sysvar<'info, Rent>,        // 租金系统变量
-}
+        // 退还释放的锁定资金：买单退还报价代币，卖单退还基础代币
+        let (vault, mint, owner_token_account) = match side {
+            Side::Buy => (
+                &ctx.accounts.quote_vault,
+                &ctx.accounts.quote_mint,
+                ctx.accounts.owner_quote_token_account.to_account_info(),
+            ),
+            Side::Sell => (
+                &ctx.accounts.base_vault,
+                &ctx.accounts.base_mint,
+                ctx.accounts.owner_base_token_account.to_account_info(),
+            ),
+        };
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            vault,
+            mint,
+            owner_token_account,
+            orderbook.to_account_info(),
+            signer,
+            refund_amount,
+        )?;
 
-// 定义下单指令的账户结构体
-#[derive(Accounts)]
-pub struct PlaceOrder<'info> {
-    #[account(
-        mut,
-        seeds = [b"orderbook".as_ref(), orderbook.base_mint.as_ref(), orderbook.quote_mint.as_ref()],
-        bump,
-    )]
-    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
-    #[account(mut)]
-    pub owner: Signer<'info>, // 订单拥有者
-    #[account(
-        mut,
-        constraint = owner_base_token_account.mint == orderbook.base_mint,
-        constraint = owner_base_token_account.owner == owner.key()
-    )]
-    pub owner_base_token_account: Account<'info, TokenAccount>, // 用户基础代币账户
-    #[account(
-        mut,
-        constraint = owner_quote_token_account.mint == orderbook.quote_mint,
-        constraint = owner_quote_token_account.owner == owner.key()
-    )]
-    pub owner_quote_token_account: Account<'info, TokenAccount>, // 用户报价代币账户
-    #[account(
-        mut,
-        seeds = [b"base_vault".as_ref(), orderbook.key().as_ref()],
-        bump
-    )]
-    pub base_vault: Account<'info, TokenAccount>, // 基础代币金库
-    #[account(
-        mut,
+        // 全部撤销时触发撤单事件，部分撤销时触发减量事件，使链下索引同步挂单的剩余数量
+        if full_cancel {
+            emit!(OrderCanceledEvent {
+                order_id,
+                owner,
+                refund_mint: orderbook.locked_mint(&side),
+                side,
+                quantity: reduce_by,
+                refund_amount,
+            });
+        } else {
+            emit!(OrderReducedEvent {
+                order_id,
+                owner,
+                refund_mint: orderbook.locked_mint(&side),
+                side,
+                reduced_quantity: reduce_by,
+                remaining_quantity,
+                refund_amount,
+            });
+        }
+
+        // 原生 SOL 一方的退款解包为 SOL
+        unwrap_native(
+            orderbook.native_leg,
+            &ctx.accounts.token_program,
+            &ctx.accounts.owner,
+            &ctx.accounts.owner_base_token_account,
+            &ctx.accounts.owner_quote_token_account,
+        )
+    }
+
+    // 取消调用者在买卖两侧的全部挂单（包括未触发的止损单），并一次性退还锁定资金
+    pub fn cancel_all_orders(ctx: Context<CancelOrder>) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook; // 可变引用订单簿
+        let owner = ctx.accounts.owner.key(); // 订单拥有者
+
+        // 用 retain 一次性移除，避免边遍历边删除导致的下标错位
+        let mut cancelled_bids = drain_orders(&mut orderbook.bids, |order| order.owner == owner);
+        let mut cancelled_asks = drain_orders(&mut orderbook.asks, |order| order.owner == owner);
+        // 止损单按方向并入，与挂单一起退款
+        let (cancelled_stops, remaining_stops): (Vec<StopOrder>, Vec<StopOrder>) = orderbook
+            .stops
+            .drain(..)
+            .partition(|stop_order| stop_order.owner == owner);
+        orderbook.stops = remaining_stops;
+        let price_scale = orderbook.price_scale();
+        for stop_order in cancelled_stops {
+            match stop_order.side {
+                Side::Buy => cancelled_bids.push(stop_order.to_order(price_scale)?),
+                Side::Sell => cancelled_asks.push(stop_order.to_order(price_scale)?),
+            }
+        }
+
+        // 设置订单簿种子和签名者
+        let orderbook_seeds = &[
+            b"orderbook".as_ref(),
+            orderbook.base_mint.as_ref(),
+            orderbook.quote_mint.as_ref(),
+            &[ctx.bumps.orderbook],
+        ];
+        let signer = &[&orderbook_seeds[..]];
+
+        // 退还买单锁定的报价代币和卖单锁定的基础代币
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.quote_vault,
+            &ctx.accounts.quote_mint,
+            ctx.accounts.owner_quote_token_account.to_account_info(),
+            orderbook.to_account_info(),
+            signer,
+            total_locked(&cancelled_bids, &Side::Buy)?,
+        )?;
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.base_vault,
+            &ctx.accounts.base_mint,
+            ctx.accounts.owner_base_token_account.to_account_info(),
+            orderbook.to_account_info(),
+            signer,
+            total_locked(&cancelled_asks, &Side::Sell)?,
+        )?;
+
+        // 为每个被取消的挂单触发撤单事件
+        for (side, cancelled) in [(Side::Buy, &cancelled_bids), (Side::Sell, &cancelled_asks)] {
+            for order in cancelled {
+                emit!(OrderCanceledEvent {
+                    order_id: order.order_id,
+                    owner,
+                    side: side.clone(),
+                    quantity: order.quantity,
+                    refund_mint: orderbook.locked_mint(&side),
+                    refund_amount: total_locked(&[*order], &side)?,
+                });
+            }
+        }
+
+        // 原生 SOL 一方的退款解包为 SOL
+        unwrap_native(
+            orderbook.native_leg,
+            &ctx.accounts.token_program,
+            &ctx.accounts.owner,
+            &ctx.accounts.owner_base_token_account,
+            &ctx.accounts.owner_quote_token_account,
+        )
+    }
+
+    // 修改挂单的价格和数量，按锁定资金的差额从用户账户补充或退还给用户。
+    // 第一版不支持修改后重新撮合：新价格会与对手盘交叉时直接拒绝（ModifyWouldCross）。
+    // 只修改数量时保留原排队位置；修改价格时按新价格重新插入（排在同价订单之后）
+    pub fn modify_order(
+        ctx: Context<ModifyOrder>,
+        order_id: u64,     // 要修改的订单 ID
+        new_price: u64,    // 新价格
+        new_quantity: u64, // 新数量
+    ) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook; // 可变引用订单簿
+        let owner = &ctx.accounts.owner; // 订单拥有者
+
+        // 市场暂停时不允许改单（撤单不受影响）
+        require!(!orderbook.paused, DexError::MarketPaused);
+
+        // 新的数量与价格同样不能为零，且需满足最小下单数量与价格精度
+        require!(
+            new_price > 0 && new_quantity > 0,
+            DexError::InvalidOrderParams
+        );
+        require!(new_quantity >= orderbook.min_base_quantity, DexError::OrderTooSmall);
+        require!(
+            new_price.checked_rem(orderbook.price_tick) == Some(0),
+            DexError::InvalidTickSize
+        );
+        require_min_notional(orderbook, new_price, new_quantity)?;
+        require_within_oracle_band(orderbook, ctx.accounts.oracle.as_deref(), new_price)?;
+
+        // 查找订单所在方向及位置
+        let (side, index) =
+            if let Some(index) = orderbook.bids.iter().position(|o| o.order_id == order_id) {
+                (Side::Buy, index)
+            } else if let Some(index) = orderbook.asks.iter().position(|o| o.order_id == order_id) {
+                (Side::Sell, index)
+            } else {
+                return Err(DexError::OrderNotFound.into());
+            };
+        let old_order = match side {
+            Side::Buy => orderbook.bids[index],
+            Side::Sell => orderbook.asks[index],
+        };
+        // 验证订单拥有者
+        require!(old_order.owner == owner.key(), DexError::OrderNotOwned);
+
+        // 新价格不能与对手盘最优价交叉
+        let would_cross = match side {
+            Side::Buy => orderbook
+                .asks
+                .first()
+                .is_some_and(|best_ask| new_price >= best_ask.price),
+            Side::Sell => orderbook
+                .bids
+                .first()
+                .is_some_and(|best_bid| new_price <= best_bid.price),
+        };
+        // 集合竞价模式下挂单本就允许交叉
+        require!(
+            !would_cross || orderbook.auction_mode,
+            DexError::ModifyWouldCross
+        );
+
+        // 计算修改前后需锁定的资金：买单为报价代币，卖单为基础代币；
+        // 下单时的数量随剩余数量同步调整，保留修改前已成交的部分
+        let original_quantity = old_order
+            .original_quantity
+            .checked_sub(old_order.quantity)
+            .and_then(|filled| filled.checked_add(new_quantity))
+            .ok_or(DexError::CalculationError)?;
+        let mut new_order = Order {
+            price: new_price,
+            quantity: new_quantity,
+            original_quantity,
+            ..old_order
+        }
+        .with_locked_funds(&side, orderbook.price_scale())?;
+        new_order.update_display(0);
+        let old_locked = total_locked(&[old_order], &side)?;
+        let new_locked = total_locked(&[new_order], &side)?;
+        let (vault, owner_token_account, mint) = match side {
+            Side::Buy => (
+                &mut ctx.accounts.quote_vault,
+                &ctx.accounts.owner_quote_token_account,
+                &ctx.accounts.quote_mint,
+            ),
+            Side::Sell => (
+                &mut ctx.accounts.base_vault,
+                &ctx.accounts.owner_base_token_account,
+                &ctx.accounts.base_mint,
+            ),
+        };
+
+        if new_locked > old_locked {
+            // 锁定资金增加：从用户账户转入差额，带转账手续费的代币到账不足时拒绝修改
+            let deposit = new_locked
+                .checked_sub(old_locked)
+                .ok_or(DexError::CalculationError)?;
+            let received = transfer_to_vault(
+                &ctx.accounts.token_program,
+                owner_token_account,
+                vault,
+                mint,
+                owner.to_account_info(),
+                deposit,
+            )?;
+            require!(received == deposit, DexError::TransferAmountShortfall);
+        } else {
+            // 锁定资金减少：从金库退还差额
+            let orderbook_seeds = &[
+                b"orderbook".as_ref(),
+                orderbook.base_mint.as_ref(),
+                orderbook.quote_mint.as_ref(),
+                &[ctx.bumps.orderbook],
+            ];
+            let signer = &[&orderbook_seeds[..]];
+            transfer_from_vault(
+                &ctx.accounts.token_program,
+                vault,
+                mint,
+                owner_token_account.to_account_info(),
+                orderbook.to_account_info(),
+                signer,
+                old_locked
+                    .checked_sub(new_locked)
+                    .ok_or(DexError::CalculationError)?,
+            )?;
+        }
+
+        // 更新挂单：价格不变时原地修改，价格变化时重新插入
+        let orders = match side {
+            Side::Buy => &mut orderbook.bids,
+            Side::Sell => &mut orderbook.asks,
+        };
+        if new_price == old_order.price {
+            orders[index] = new_order;
+        } else {
+            orders.remove(index);
+            // 重新插入视同新挂单，同样受每个拥有者的挂单数量上限约束
+            require_owner_capacity(orderbook, owner.key())?;
+            let orders = match side {
+                Side::Buy => &mut orderbook.bids,
+                Side::Sell => &mut orderbook.asks,
+            };
+            insert_sorted(orders, &side, new_order);
+        }
+
+        // 触发改单事件，链下据此同步挂单的价格与数量（价格变化时排队位置重置）
+        emit!(OrderModifiedEvent {
+            order_id,
+            owner: owner.key(),
+            side,
+            old_price: old_order.price,
+            new_price,
+            old_quantity: old_order.quantity,
+            new_quantity,
+        });
+        Ok(())
+    }
+
+    // 注册推荐码，将短代码映射到推荐人公钥（PDA 地址由代码派生，保证唯一）
+    pub fn register_referral(ctx: Context<RegisterReferral>, code: String) -> Result<()> {
+        // 验证推荐码非空且不超过最大长度
+        require!(
+            !code.is_empty() && code.len() <= MAX_REFERRAL_CODE_LEN,
+            DexError::InvalidReferralCode
+        );
+
+        let referral = &mut ctx.accounts.referral;
+        referral.code = code; // 保存推荐码
+        referral.referrer = ctx.accounts.referrer.key(); // 保存推荐人公钥
+        Ok(())
+    }
+
+    // 冻结指定订单（仅管理员），冻结期间订单不参与撮合，但拥有者仍可取消
+    pub fn freeze_order(ctx: Context<FreezeOrder>, order_id: u64) -> Result<()> {
+        set_order_frozen(ctx, order_id, true)
+    }
+
+    // 解冻指定订单（仅管理员），恢复其撮合资格
+    pub fn unfreeze_order(ctx: Context<FreezeOrder>, order_id: u64) -> Result<()> {
+        set_order_frozen(ctx, order_id, false)
+    }
+
+    // 暂停或恢复交易（仅管理员）：暂停期间拒绝下单、改单和触发止损单，撤单不受影响
+    pub fn set_paused(ctx: Context<SetPaused>, paused: bool) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        // 验证调用者为管理员
+        require_keys_eq!(
+            ctx.accounts.admin.key(),
+            orderbook.admin,
+            DexError::Unauthorized
+        );
+        orderbook.paused = paused;
+
+        // 记录管理员操作
+        let kind = if paused {
+            AdminActionKind::Pause
+        } else {
+            AdminActionKind::Unpause
+        };
+        log_admin_action(orderbook, ctx.accounts.admin.key(), kind, 0)
+    }
+
+    // 设置手续费的舍入方式（仅管理员）：决定按基点计算的手续费如何舍入；
+    // 返佣与报价金额换算的舍入方向固定，不受此设置影响
+    pub fn set_fee_rounding(ctx: Context<SetFeeRounding>, mode: RoundingMode) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        // 验证调用者为管理员
+        require_keys_eq!(
+            ctx.accounts.admin.key(),
+            orderbook.admin,
+            DexError::Unauthorized
+        );
+        orderbook.fee_rounding = mode;
+
+        // 记录管理员操作
+        log_admin_action(
+            orderbook,
+            ctx.accounts.admin.key(),
+            AdminActionKind::SetFeeRounding,
+            mode as u64,
+        )
+    }
+
+    // 切换集合竞价模式（仅管理员）：开启后下单只锁定资金并挂单，交叉挂单由 run_auction
+    // 按统一清算价批量撮合，match_orders 不再可用；关闭后恢复连续撮合
+    pub fn set_auction_mode(ctx: Context<SetAuctionMode>, enabled: bool) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        // 验证调用者为管理员
+        require_keys_eq!(
+            ctx.accounts.admin.key(),
+            orderbook.admin,
+            DexError::Unauthorized
+        );
+        orderbook.auction_mode = enabled;
+
+        // 记录管理员操作
+        log_admin_action(
+            orderbook,
+            ctx.accounts.admin.key(),
+            AdminActionKind::SetAuctionMode,
+            enabled as u64,
+        )
+    }
+
+    // 设置 maker 返佣比例（仅管理员）：此后 maker 每笔成交按成交额的 rebate_bps 累计返佣
+    pub fn set_rebate_bps(ctx: Context<SetRebateBps>, rebate_bps: u16) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        // 验证调用者为管理员
+        require_keys_eq!(
+            ctx.accounts.admin.key(),
+            orderbook.admin,
+            DexError::Unauthorized
+        );
+        require!(rebate_bps <= BPS_DENOMINATOR, DexError::InvalidFeeBps);
+        orderbook.rebate_bps = rebate_bps;
+
+        // 记录管理员操作
+        log_admin_action(
+            orderbook,
+            ctx.accounts.admin.key(),
+            AdminActionKind::SetRebateBps,
+            rebate_bps as u64,
+        )
+    }
+
+    // 设置订单名义金额下限（仅管理员）：此后限价单的 价格 × 数量 必须不低于该报价代币金额，
+    // 0 表示不限制；已挂单不受影响
+    pub fn set_min_quote_notional(
+        ctx: Context<SetMinQuoteNotional>,
+        min_quote_notional: u64,
+    ) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        // 验证调用者为管理员
+        require_keys_eq!(
+            ctx.accounts.admin.key(),
+            orderbook.admin,
+            DexError::Unauthorized
+        );
+        orderbook.min_quote_notional = min_quote_notional;
+
+        // 记录管理员操作
+        log_admin_action(
+            orderbook,
+            ctx.accounts.admin.key(),
+            AdminActionKind::SetMinQuoteNotional,
+            min_quote_notional,
+        )
+    }
+
+    // 设置预言机价格带（仅管理员）：配置 Pyth 价格账户后，限价单价格偏离预言机价格超过
+    // max_deviation_bps 时拒绝下单，预言机价格超过 max_age 秒未更新时同样拒绝；oracle 为 None 时关闭检查
+    pub fn set_oracle(
+        ctx: Context<SetOracle>,
+        oracle: Option<Pubkey>, // Pyth 价格账户（None 表示不使用预言机）
+        max_deviation_bps: u16, // 允许偏离预言机价格的最大基点数
+        max_age: u32,           // 预言机价格的最大有效时长（秒）
+    ) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        // 验证调用者为管理员
+        require_keys_eq!(
+            ctx.accounts.admin.key(),
+            orderbook.admin,
+            DexError::Unauthorized
+        );
+        require!(
+            max_deviation_bps <= BPS_DENOMINATOR,
+            DexError::InvalidFeeBps
+        );
+        orderbook.oracle = oracle;
+        orderbook.max_deviation_bps = max_deviation_bps;
+        orderbook.oracle_max_age = max_age;
+
+        // 记录管理员操作
+        log_admin_action(
+            orderbook,
+            ctx.accounts.admin.key(),
+            AdminActionKind::SetOracle,
+            max_deviation_bps as u64,
+        )
+    }
+
+    // 领取累计的 maker 返佣：从报价代币手续费金库转给 maker 并清零其待领取余额账户中的返佣，
+    // 返佣只从已收取的手续费中支付，手续费金库余额不足时报错
+    pub fn claim_rebate(ctx: Context<ClaimRebate>) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        let claimable = &mut ctx.accounts.claimable;
+
+        let amount = claimable.rebate;
+        if amount == 0 {
+            return Ok(()); // 没有累计返佣
+        }
+        require!(
+            ctx.accounts.quote_fee_vault.amount >= amount,
+            DexError::InsufficientRebatePool
+        );
+
+        // 设置订单簿种子和签名者
+        let orderbook_seeds = &[
+            b"orderbook".as_ref(),
+            orderbook.base_mint.as_ref(),
+            orderbook.quote_mint.as_ref(),
+            &[ctx.bumps.orderbook],
+        ];
+        let signer = &[&orderbook_seeds[..]];
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.quote_fee_vault,
+            &ctx.accounts.quote_mint,
+            ctx.accounts.owner_quote_token_account.to_account_info(),
+            orderbook.to_account_info(),
+            signer,
+            amount,
+        )?;
+
+        // 清零累计，并从订单簿的未领取返佣总额中扣除
+        claimable.rebate = 0;
+        orderbook.rebates_owed = orderbook
+            .rebates_owed
+            .checked_sub(amount)
+            .ok_or(DexError::CalculationError)?;
+        Ok(())
+    }
+
+    // 领取原生 SOL 一方的挂单所得：把托管 wSOL 账户的全部余额转入 owner 的临时 wSOL 账户并关闭后者，
+    // 以 SOL 返还 owner；托管账户本身保留，供其挂单继续接收成交所得与退款
+    pub fn claim_native(ctx: Context<ClaimNative>) -> Result<()> {
+        let orderbook = &ctx.accounts.orderbook;
+        let native_mint = match orderbook.native_leg {
+            NativeLeg::None => return err!(DexError::InvalidNativeEscrow),
+            NativeLeg::Base => orderbook.base_mint,
+            NativeLeg::Quote => orderbook.quote_mint,
+        };
+        require_keys_eq!(
+            ctx.accounts.native_mint.key(),
+            native_mint,
+            DexError::InvalidNativeEscrow
+        );
+
+        // 托管账户的 authority 是待领取余额账户，以其 PDA 签名转出
+        let orderbook_key = orderbook.key();
+        let owner_key = ctx.accounts.owner.key();
+        let claimable_seeds = &[
+            b"claimable".as_ref(),
+            orderbook_key.as_ref(),
+            owner_key.as_ref(),
+            &[ctx.accounts.claimable.bump],
+        ];
+        let signer = &[&claimable_seeds[..]];
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.native_escrow,
+            &ctx.accounts.native_mint,
+            ctx.accounts.owner_wsol_account.to_account_info(),
+            ctx.accounts.claimable.to_account_info(),
+            signer,
+            ctx.accounts.native_escrow.amount,
+        )?;
+
+        // 关闭临时 wSOL 账户，余额连同租金以 SOL 返还 owner
+        token_interface::close_account(CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.owner_wsol_account.to_account_info(),
+                destination: ctx.accounts.owner.to_account_info(),
+                authority: ctx.accounts.owner.to_account_info(),
+            },
+        ))
+    }
+
+    // 领取因代币账户被关闭而撤销的挂单资金：从金库转给拥有者并清零其待领取余额账户中的对应余额
+    pub fn claim_balance(ctx: Context<ClaimBalance>) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        let claimable = &mut ctx.accounts.claimable;
+        let (base_amount, quote_amount) = (claimable.base, claimable.quote);
+
+        // 设置订单簿种子和签名者
+        let orderbook_seeds = &[
+            b"orderbook".as_ref(),
+            orderbook.base_mint.as_ref(),
+            orderbook.quote_mint.as_ref(),
+            &[ctx.bumps.orderbook],
+        ];
+        let signer = &[&orderbook_seeds[..]];
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.base_vault,
+            &ctx.accounts.base_mint,
+            ctx.accounts.owner_base_token_account.to_account_info(),
+            orderbook.to_account_info(),
+            signer,
+            base_amount,
+        )?;
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.quote_vault,
+            &ctx.accounts.quote_mint,
+            ctx.accounts.owner_quote_token_account.to_account_info(),
+            orderbook.to_account_info(),
+            signer,
+            quote_amount,
+        )?;
+
+        // 清零余额，并从订单簿的待领取总额中扣除
+        claimable.base = 0;
+        claimable.quote = 0;
+        orderbook.claimable_base = orderbook
+            .claimable_base
+            .checked_sub(base_amount)
+            .ok_or(DexError::CalculationError)?;
+        orderbook.claimable_quote = orderbook
+            .claimable_quote
+            .checked_sub(quote_amount)
+            .ok_or(DexError::CalculationError)?;
+        Ok(())
+    }
+
+    // 结算曲柄（无需许可）：将待结算的成交叶子提交为新的 Merkle 根
+    pub fn settle_fills(ctx: Context<SettleFills>) -> Result<()> {
+        commit_fill_batch(&mut ctx.accounts.orderbook);
+        Ok(())
+    }
+
+    // 验证某笔成交包含在指定批次的 Merkle 根中，只能验证最近 MAX_FILL_ROOTS 个批次
+    pub fn verify_fill(
+        ctx: Context<VerifyFill>,
+        batch_id: u64,        // 成交所在的批次编号（见 FillBatchSettledEvent）
+        proof: Vec<[u8; 32]>, // 从叶子到根路径上的兄弟节点哈希
+        fill: FillRecord,     // 待验证的成交记录
+    ) -> Result<()> {
+        let root = ctx
+            .accounts
+            .orderbook
+            .fill_root(batch_id)
+            .ok_or(DexError::UnknownFillBatch)?;
+        let computed_root = proof
+            .iter()
+            .fold(fill.leaf_hash()?, |node, sibling| hash_merkle_pair(&node, sibling));
+        require!(computed_root == root, DexError::InvalidFillProof);
+        Ok(())
+    }
+
+    // 提取手续费金库中的全部手续费（仅管理员）
+    pub fn withdraw_fees(ctx: Context<WithdrawFees>) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        // 验证调用者为管理员
+        require_keys_eq!(
+            ctx.accounts.admin.key(),
+            orderbook.admin,
+            DexError::Unauthorized
+        );
+
+        // 设置订单簿种子和签名者
+        let orderbook_seeds = &[
+            b"orderbook".as_ref(),
+            orderbook.base_mint.as_ref(),
+            orderbook.quote_mint.as_ref(),
+            &[ctx.bumps.orderbook],
+        ];
+        let signer = &[&orderbook_seeds[..]];
+
+        let base_fees = ctx.accounts.base_fee_vault.amount;
+        // 保留尚未领取的 maker 返佣，只提取其余报价代币手续费
+        let quote_fees = ctx
+            .accounts
+            .quote_fee_vault
+            .amount
+            .saturating_sub(orderbook.rebates_owed);
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.base_fee_vault,
+            &ctx.accounts.base_mint,
+            ctx.accounts.admin_base_token_account.to_account_info(),
+            orderbook.to_account_info(),
+            signer,
+            base_fees,
+        )?;
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.quote_fee_vault,
+            &ctx.accounts.quote_mint,
+            ctx.accounts.admin_quote_token_account.to_account_info(),
+            orderbook.to_account_info(),
+            signer,
+            quote_fees,
+        )?;
+
+        // 记录管理员操作
+        let admin = ctx.accounts.admin.key();
+        log_admin_action(orderbook, admin, AdminActionKind::WithdrawBaseFees, base_fees)?;
+        log_admin_action(orderbook, admin, AdminActionKind::WithdrawQuoteFees, quote_fees)
+    }
+
+    // 提取金库中超出挂单锁定资金的余额（仅管理员）：舍入残留或直接转入金库的代币不属于任何挂单，
+    // 只提取超出部分，金库余额永远不会低于挂单与止损单锁定的资金；两个金库都没有盈余时报错
+    pub fn sweep_surplus(ctx: Context<SweepSurplus>) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        // 验证调用者为管理员
+        require_keys_eq!(
+            ctx.accounts.admin.key(),
+            orderbook.admin,
+            DexError::Unauthorized
+        );
+
+        // 计算两个金库的盈余：余额减去挂单与止损单锁定的资金
+        let (base_owed, quote_owed) = vault_obligations(orderbook)?;
+        let base_surplus = ctx.accounts.base_vault.amount.saturating_sub(base_owed);
+        let quote_surplus = ctx.accounts.quote_vault.amount.saturating_sub(quote_owed);
+        require!(base_surplus > 0 || quote_surplus > 0, DexError::NoSurplus);
+
+        // 设置订单簿种子和签名者
+        let orderbook_seeds = &[
+            b"orderbook".as_ref(),
+            orderbook.base_mint.as_ref(),
+            orderbook.quote_mint.as_ref(),
+            &[ctx.bumps.orderbook],
+        ];
+        let signer = &[&orderbook_seeds[..]];
+
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.base_vault,
+            &ctx.accounts.base_mint,
+            ctx.accounts.admin_base_token_account.to_account_info(),
+            orderbook.to_account_info(),
+            signer,
+            base_surplus,
+        )?;
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            &ctx.accounts.quote_vault,
+            &ctx.accounts.quote_mint,
+            ctx.accounts.admin_quote_token_account.to_account_info(),
+            orderbook.to_account_info(),
+            signer,
+            quote_surplus,
+        )?;
+
+        // 记录管理员操作
+        let admin = ctx.accounts.admin.key();
+        log_admin_action(
+            orderbook,
+            admin,
+            AdminActionKind::SweepBaseSurplus,
+            base_surplus,
+        )?;
+        log_admin_action(
+            orderbook,
+            admin,
+            AdminActionKind::SweepQuoteSurplus,
+            quote_surplus,
+        )
+    }
+
+    // 强制撤销任意挂单（仅管理员）：用于 maker 代币账户被冻结等导致挂单无法成交的情况。
+    // 锁定资金退还到订单拥有者的代币账户；该账户已被冻结时转入管理员控制的托管账户
+    pub fn force_cancel(ctx: Context<ForceCancel>, order_id: u64) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        // 验证调用者为管理员
+        require_keys_eq!(
+            ctx.accounts.admin.key(),
+            orderbook.admin,
+            DexError::Unauthorized
+        );
+
+        // 查找挂单所在方向并移除
+        let (side, index) =
+            if let Some(index) = orderbook.bids.iter().position(|o| o.order_id == order_id) {
+                (Side::Buy, index)
+            } else if let Some(index) = orderbook.asks.iter().position(|o| o.order_id == order_id) {
+                (Side::Sell, index)
+            } else {
+                return Err(DexError::OrderNotFound.into());
+            };
+        let order = match side {
+            Side::Buy => orderbook.bids.remove(index),
+            Side::Sell => orderbook.asks.remove(index),
+        };
+
+        // 退款账户必须属于订单拥有者，且为挂单锁定的代币（买单为报价代币，卖单为基础代币）
+        let (vault, mint) = match side {
+            Side::Buy => (&ctx.accounts.quote_vault, &ctx.accounts.quote_mint),
+            Side::Sell => (&ctx.accounts.base_vault, &ctx.accounts.base_mint),
+        };
+        let owner_token_account = &ctx.accounts.owner_token_account;
+        require_keys_eq!(
+            owner_token_account.owner,
+            order.owner,
+            DexError::MakerAccountMismatch
+        );
+        require_keys_eq!(
+            owner_token_account.mint,
+            mint.key(),
+            DexError::MakerMintMismatch
+        );
+        // 拥有者的代币账户已被冻结时，退款转入托管账户
+        let escrowed = owner_token_account.is_frozen();
+        let refund_account = if escrowed {
+            let escrow_token_account = &ctx.accounts.escrow_token_account;
+            require_keys_eq!(
+                escrow_token_account.mint,
+                mint.key(),
+                DexError::MakerMintMismatch
+            );
+            escrow_token_account
+        } else {
+            owner_token_account
+        };
+
+        // 设置订单簿种子和签名者
+        let orderbook_seeds = &[
+            b"orderbook".as_ref(),
+            orderbook.base_mint.as_ref(),
+            orderbook.quote_mint.as_ref(),
+            &[ctx.bumps.orderbook],
+        ];
+        let signer = &[&orderbook_seeds[..]];
+
+        let refund_amount = total_locked(&[order], &side)?;
+        transfer_from_vault(
+            &ctx.accounts.token_program,
+            vault,
+            mint,
+            refund_account.to_account_info(),
+            orderbook.to_account_info(),
+            signer,
+            refund_amount,
+        )?;
+
+        // 触发强制撤单事件并记录管理员操作
+        let admin = ctx.accounts.admin.key();
+        emit!(OrderForceCanceledEvent {
+            order_id,
+            owner: order.owner,
+            side,
+            refund_amount,
+            refund_account: refund_account.key(),
+            escrowed,
+            admin,
+        });
+        log_admin_action(orderbook, admin, AdminActionKind::ForceCancel, order_id)
+    }
+
+    // 清理过期挂单（无需许可）：移除过期的买单和卖单，并把锁定资金退还给订单的结算方
+    // （设置了结算方的挂单退还到结算方的代币账户，与成交所得的去向一致，否则退还给拥有者）。
+    // 结算方的代币账户按清理顺序（先买单后卖单，各自按订单簿顺序）通过 remaining_accounts 传入，
+    // 每个过期挂单对应一组基础/报价代币账户及拥有者的待领取余额账户；账户用完时停止，剩余过期挂单留待下次清理
+    pub fn reap_expired<'info>(ctx: Context<'_, '_, 'info, 'info, ReapExpired<'info>>) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        let now = Clock::get()?.unix_timestamp;
+
+        // 设置订单簿种子和签名者
+        let base_mint_key = orderbook.base_mint;
+        let quote_mint_key = orderbook.quote_mint;
+        let orderbook_seeds = &[
+            b"orderbook".as_ref(),
+            base_mint_key.as_ref(),
+            quote_mint_key.as_ref(),
+            &[ctx.bumps.orderbook],
+        ];
+        let signer = &[&orderbook_seeds[..]];
+
+        let mut maker_accounts_iter = ctx.remaining_accounts.iter().peekable();
+        for side in [Side::Buy, Side::Sell] {
+            while maker_accounts_iter.peek().is_some() {
+                let orders = match side {
+                    Side::Buy => &mut orderbook.bids,
+                    Side::Sell => &mut orderbook.asks,
+                };
+                let Some(index) = orders.iter().position(|order| order.is_expired(now)) else {
+                    break; // 该方向已无过期挂单
+                };
+                let expired_order = orders.remove(index);
+
+                let maker_accounts = match get_next_maker_accounts(
+                    &mut maker_accounts_iter,
+                    orderbook,
+                    expired_order.settlement_owner(),
+                    expired_order.owner,
+                )? {
+                    MakerSlot::Open(maker_accounts) => *maker_accounts,
+                    MakerSlot::Closed(mut claimable) => {
+                        cancel_stale_order(
+                            orderbook,
+                            &mut claimable,
+                            side.clone(),
+                            &expired_order,
+                        )?;
+                        continue;
+                    }
+                };
+
+                // 买单退还报价代币，卖单退还基础代币
+                let (vault, mint, refund_account) = match side {
+                    Side::Buy => (
+                        &ctx.accounts.quote_vault,
+                        &ctx.accounts.quote_mint,
+                        maker_accounts.quote_token_account.to_account_info(),
+                    ),
+                    Side::Sell => (
+                        &ctx.accounts.base_vault,
+                        &ctx.accounts.base_mint,
+                        maker_accounts.owner_token_account.to_account_info(),
+                    ),
+                };
+                let refund_amount = total_locked(&[expired_order], &side)?;
+                transfer_from_vault(
+                    &ctx.accounts.token_program,
+                    vault,
+                    mint,
+                    refund_account,
+                    orderbook.to_account_info(),
+                    signer,
+                    refund_amount,
+                )?;
+
+                // 触发撤单事件，使链下索引同步移除该挂单
+                emit!(OrderCanceledEvent {
+                    order_id: expired_order.order_id,
+                    owner: expired_order.owner,
+                    side: side.clone(),
+                    quantity: expired_order.quantity,
+                    refund_mint: mint.key(),
+                    refund_amount,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // 触发止损单（无需许可）：最新成交价触及触发价的止损单按下单顺序转为限价单并立即撮合，
+    // 未成交部分按限价挂到订单簿。remaining_accounts 先传入被触发止损单拥有者的一组账户
+    // （基础/报价代币账户及待领取余额账户），再传入该订单撮合所需的 maker 账户，依此类推；账户用完时停止，其余已触发的止损单留待下次处理。
+    // 撮合产生的新成交价可能继续触发其他止损单
+    pub fn trigger_stops<'info>(ctx: Context<'_, '_, 'info, 'info, TriggerStops<'info>>) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        let now = Clock::get()?.unix_timestamp;
+
+        // 市场暂停时止损单不会被触发
+        require!(!orderbook.paused, DexError::MarketPaused);
+
+        // 设置订单簿种子和签名者
+        let base_mint_key = orderbook.base_mint;
+        let quote_mint_key = orderbook.quote_mint;
+        let orderbook_seeds = &[
+            b"orderbook".as_ref(),
+            base_mint_key.as_ref(),
+            quote_mint_key.as_ref(),
+            &[ctx.bumps.orderbook],
+        ];
+        let signer = &[&orderbook_seeds[..]];
+
+        let mut accounts_iter = ctx.remaining_accounts.iter().peekable();
+        while accounts_iter.peek().is_some() {
+            let last_trade_price = orderbook.last_trade_price;
+            let Some(index) = orderbook
+                .stops
+                .iter()
+                .position(|stop_order| stop_order.is_triggered(last_trade_price))
+            else {
+                break; // 没有已触发的止损单
+            };
+            let stop_order = orderbook.stops.remove(index);
+
+            // 止损单拥有者的代币账户，接收成交所得与退款
+            let mut owner_accounts = match get_next_maker_accounts(
+                &mut accounts_iter,
+                orderbook,
+                stop_order.owner,
+                stop_order.owner,
+            )? {
+                MakerSlot::Open(owner_accounts) => *owner_accounts,
+                MakerSlot::Closed(mut claimable) => {
+                    // 拥有者的代币账户已被关闭：撤销止损单，锁定资金记入待领取余额
+                    let order = stop_order.to_order(orderbook.price_scale())?;
+                    cancel_stale_order(orderbook, &mut claimable, stop_order.side, &order)?;
+                    continue;
+                }
+            };
+
+            // 限价在触发时同样不得偏离预言机价格带：偏离时撤销该止损单、锁定资金记入待领取余额，
+            // 继续处理其余止损单，避免一个止损单阻塞 trigger_stops（预言机账户无效等错误仍拒绝）
+            match require_within_oracle_band(
+                orderbook,
+                ctx.accounts.oracle.as_deref(),
+                stop_order.limit_price,
+            ) {
+                Err(err) if err == DexError::PriceOutOfBand.into() => {
+                    let order = stop_order.to_order(orderbook.price_scale())?;
+                    cancel_stale_order(
+                        orderbook,
+                        &mut owner_accounts.claimable,
+                        stop_order.side,
+                        &order,
+                    )?;
+                    continue;
+                }
+                result => result?,
+            }
+
+            // 触发止损单事件
+            emit!(StopOrderTriggeredEvent {
+                order_id: stop_order.order_id,
+                owner: stop_order.owner,
+                last_trade_price,
+            });
+
+            // 以限价单撮合（集合竞价模式下只挂单），资金在下单时已按限价锁定，无需再次存入
+            let mut taker_order = stop_order.to_order(orderbook.price_scale())?;
+            let quote_locked = total_locked(&[taker_order], &stop_order.side)?;
+            match_order(
+                orderbook,
+                &MatchAccounts {
+                    token_program: &ctx.accounts.token_program,
+                    base_mint: &ctx.accounts.base_mint,
+                    quote_mint: &ctx.accounts.quote_mint,
+                    base_vault: &ctx.accounts.base_vault,
+                    quote_vault: &ctx.accounts.quote_vault,
+                    base_fee_vault: &ctx.accounts.base_fee_vault,
+                    quote_fee_vault: &ctx.accounts.quote_fee_vault,
+                    taker_base_token_account: &owner_accounts.owner_token_account,
+                    taker_quote_token_account: &owner_accounts.quote_token_account,
+                },
+                signer,
+                &mut accounts_iter,
+                &mut taker_order,
+                &stop_order.side,
+                OrderType::Limit,
+                0,
+                false,
+                quote_locked,
+                true,
+                orderbook.auction_mode,
+                SelfTradePrevention::CancelResting,
+                0,
+                None,
+                now,
+                usize::MAX,
+            )?;
+
+            // 未成交部分沿用止损单 ID 挂到订单簿，受每个拥有者的挂单数量上限约束：拥有者已达上限时
+            // 撤销剩余部分、锁定资金记入待领取余额，继续处理其余止损单
+            if taker_order.quantity > 0 {
+                match require_owner_capacity(orderbook, taker_order.owner) {
+                    Err(err) if err == DexError::TooManyOpenOrders.into() => {
+                        let order = taker_order
+                            .with_locked_funds(&stop_order.side, orderbook.price_scale())?;
+                        cancel_stale_order(
+                            orderbook,
+                            &mut owner_accounts.claimable,
+                            stop_order.side,
+                            &order,
+                        )?;
+                    }
+                    result => {
+                        result?;
+                        rest_order(orderbook, &stop_order.side, taker_order)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // 检查订单簿是否交叉（无需许可）：订单簿健康时不做任何操作；最佳买价 ≥ 最佳卖价时触发
+    // BookCrossedEvent 记录两侧最佳挂单，max_fills > 0 时再按 match_orders 的规则
+    // 使用传入的账户撮合交叉挂单（remaining_accounts 的顺序与 match_orders 相同）
+    pub fn validate_book<'info>(
+        ctx: Context<'_, '_, 'info, 'info, MatchOrders<'info>>,
+        max_fills: u8, // 最多撮合的成交笔数（0 表示只检查）
+    ) -> Result<()> {
+        let orderbook = &ctx.accounts.orderbook;
+        // 集合竞价模式下两次竞价之间订单簿交叉是正常状态
+        if orderbook.auction_mode {
+            return Ok(());
+        }
+        let (Some(best_bid), Some(best_ask)) = (orderbook.bids.first(), orderbook.asks.first())
+        else {
+            return Ok(()); // 一侧为空，不存在交叉
+        };
+        if best_bid.price < best_ask.price {
+            return Ok(()); // 订单簿健康
+        }
+        emit!(BookCrossedEvent {
+            best_bid_order_id: best_bid.order_id,
+            best_bid_price: best_bid.price,
+            best_ask_order_id: best_ask.order_id,
+            best_ask_price: best_ask.price,
+        });
+        if max_fills == 0 {
+            return Ok(());
+        }
+        match_orders(ctx, max_fills)
+    }
+
+    // 撮合交叉挂单（无需许可）：订单簿交叉（最佳买价 ≥ 最佳卖价）时，取两侧最佳挂单中较新的一个
+    // （订单 ID 较大）作为 taker 与对手盘成交一笔，最多处理 max_fills 笔，订单簿不再交叉时正常结束。
+    // 每笔成交的 remaining_accounts 先传入 taker 挂单结算方的一组账户，再传入 maker 账户
+    pub fn match_orders<'info>(
+        ctx: Context<'_, '_, 'info, 'info, MatchOrders<'info>>,
+        max_fills: u8, // 本次最多处理的成交笔数
+    ) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        let now = Clock::get()?.unix_timestamp;
+
+        // 市场暂停时不撮合；集合竞价模式下交叉挂单只能由 run_auction 撮合
+        require!(!orderbook.paused, DexError::MarketPaused);
+        require!(!orderbook.auction_mode, DexError::AuctionModeActive);
+
+        // 设置订单簿种子和签名者
+        let base_mint_key = orderbook.base_mint;
+        let quote_mint_key = orderbook.quote_mint;
+        let orderbook_seeds = &[
+            b"orderbook".as_ref(),
+            base_mint_key.as_ref(),
+            quote_mint_key.as_ref(),
+            &[ctx.bumps.orderbook],
+        ];
+        let signer = &[&orderbook_seeds[..]];
+
+        let mut accounts_iter = ctx.remaining_accounts.iter().peekable();
+        for _ in 0..max_fills {
+            let (Some(best_bid), Some(best_ask)) = (orderbook.bids.first(), orderbook.asks.first())
+            else {
+                break; // 一侧为空，不存在交叉
+            };
+            if best_bid.price < best_ask.price {
+                break; // 订单簿不再交叉
+            }
+            // 较新的挂单作为 taker，按价格时间优先以较早挂单为 maker
+            let side = if best_bid.order_id > best_ask.order_id {
+                Side::Buy
+            } else {
+                Side::Sell
+            };
+            let taker_head = match side {
+                Side::Buy => best_bid,
+                Side::Sell => best_ask,
+            };
+            // 已冻结或已过期的挂单不作为 taker，留待解冻或 reap_expired 处理
+            if taker_head.frozen || taker_head.is_expired(now) {
+                break;
+            }
+            let mut taker_order = match side {
+                Side::Buy => orderbook.bids.remove(0),
+                Side::Sell => orderbook.asks.remove(0),
+            };
+            let quantity = taker_order.quantity;
+
+            // taker 挂单拥有者的代币账户，接收成交所得与退款
+            let owner_accounts = match get_next_maker_accounts(
+                &mut accounts_iter,
+                orderbook,
+                taker_order.settlement_owner(),
+                taker_order.owner,
+            )? {
+                MakerSlot::Open(owner_accounts) => *owner_accounts,
+                MakerSlot::Closed(mut claimable) => {
+                    // 结算方的代币账户已被关闭：撤销该挂单，继续检查下一对最佳挂单
+                    cancel_stale_order(orderbook, &mut claimable, side, &taker_order)?;
+                    continue;
+                }
+            };
+            let match_accounts = MatchAccounts {
+                token_program: &ctx.accounts.token_program,
+                base_mint: &ctx.accounts.base_mint,
+                quote_mint: &ctx.accounts.quote_mint,
+                base_vault: &ctx.accounts.base_vault,
+                quote_vault: &ctx.accounts.quote_vault,
+                base_fee_vault: &ctx.accounts.base_fee_vault,
+                quote_fee_vault: &ctx.accounts.quote_fee_vault,
+                taker_base_token_account: &owner_accounts.owner_token_account,
+                taker_quote_token_account: &owner_accounts.quote_token_account,
+            };
+
+            // 与对手盘成交一笔，taker 挂单的锁定资金在挂单时已记录
+            let quote_locked = total_locked(&[taker_order], &side)?;
+            let summary = match_order(
+                orderbook,
+                &match_accounts,
+                signer,
+                &mut accounts_iter,
+                &mut taker_order,
+                &side,
+                OrderType::Limit,
+                0,
+                false,
+                quote_locked,
+                true,
+                false,
+                SelfTradePrevention::CancelResting,
+                0,
+                None,
+                now,
+                1,
+            )?;
+
+            if taker_order.quantity < quantity {
+                // taker 挂单同样是订单簿上的挂单，触发其成交事件
+                emit!(OrderFilledEvent {
+                    order_id: taker_order.order_id,
+                    owner: taker_order.owner,
+                    side: side.clone(),
+                    fill_quantity: quantity
+                        .checked_sub(taker_order.quantity)
+                        .ok_or(DexError::CalculationError)?,
+                    remaining_quantity: taker_order.quantity,
+                    full_fill: taker_order.quantity == 0,
+                });
+
+                // taker 挂单属于 OCO 组时，撤销同组其余挂单并退款
+                if taker_order.oco_group_id != 0 {
+                    let (quote_refund, base_refund) = cancel_oco_group(
+                        orderbook,
+                        &mut Vec::new(),
+                        &side,
+                        taker_order.owner,
+                        taker_order.oco_group_id,
+                    )?;
+                    transfer_from_vault(
+                        &ctx.accounts.token_program,
+                        &ctx.accounts.quote_vault,
+                        &ctx.accounts.quote_mint,
+                        owner_accounts.quote_token_account.to_account_info(),
+                        orderbook.to_account_info(),
+                        signer,
+                        quote_refund,
+                    )?;
+                    transfer_from_vault(
+                        &ctx.accounts.token_program,
+                        &ctx.accounts.base_vault,
+                        &ctx.accounts.base_mint,
+                        owner_accounts.owner_token_account.to_account_info(),
+                        orderbook.to_account_info(),
+                        signer,
+                        base_refund,
+                    )?;
+                }
+            }
+
+            // 剩余部分放回原位（仍是该方向的最佳挂单），锁定资金按剩余数量更新
+            if taker_order.quantity > 0 {
+                let mut taker_order =
+                    taker_order.with_locked_funds(&side, orderbook.price_scale())?;
+                taker_order.update_display(0);
+                match side {
+                    Side::Buy => orderbook.bids.insert(0, taker_order),
+                    Side::Sell => orderbook.asks.insert(0, taker_order),
+                }
+            }
+
+            // 交叉的对手盘挂单都无法成交（如被冻结或成交额过小）时停止
+            if summary.fills == 0 {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // 运行集合竞价（无需许可，仅集合竞价模式）：在当前订单簿上求出使成交量最大的统一清算价，
+    // 按价格时间优先依次配对价格不差于清算价的买卖挂单，全部以清算价成交，最多处理 max_fills 笔。
+    // 两笔挂单中较新的一方（订单 ID 较大）视为 taker，按 taker/maker 费率收费并为 maker 累计返佣。
+    // 每笔成交的 remaining_accounts 依次传入买单结算方、卖单结算方的一组账户
+    pub fn run_auction<'info>(
+        ctx: Context<'_, '_, 'info, 'info, MatchOrders<'info>>,
+        max_fills: u8, // 本次最多处理的成交笔数
+    ) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        let now = Clock::get()?.unix_timestamp;
+
+        // 市场暂停时不撮合，连续撮合模式下不运行竞价
+        require!(!orderbook.paused, DexError::MarketPaused);
+        require!(orderbook.auction_mode, DexError::AuctionModeInactive);
+
+        // 订单簿不交叉时没有可成交的数量
+        let Some((clearing_price, volume)) = auction_clearing_price(orderbook, now)? else {
+            return Ok(());
+        };
+
+        // 设置订单簿种子和签名者
+        let base_mint_key = orderbook.base_mint;
+        let quote_mint_key = orderbook.quote_mint;
+        let orderbook_seeds = &[
+            b"orderbook".as_ref(),
+            base_mint_key.as_ref(),
+            quote_mint_key.as_ref(),
+            &[ctx.bumps.orderbook],
+        ];
+        let signer = &[&orderbook_seeds[..]];
+        let token_program = &ctx.accounts.token_program;
+        let price_scale = orderbook.price_scale();
+
+        let mut accounts_iter = ctx.remaining_accounts.iter().peekable();
+        // 被跳过的挂单（已冻结或已过期），竞价结束后按原顺序放回订单簿
+        let mut skipped_bids = Vec::new();
+        let mut skipped_asks = Vec::new();
+        let mut remaining = volume;
+        let mut fills: u32 = 0; // 已完成的成交笔数
+        while remaining > 0 && fills < max_fills as u32 {
+            // 取出两侧下一个可参与竞价的挂单，价格差于清算价时停止
+            let Some(mut bid) = next_auction_order(&mut orderbook.bids, &mut skipped_bids, now)
+            else {
+                break;
+            };
+            if bid.price < clearing_price {
+                orderbook.bids.insert(0, bid);
+                break;
+            }
+            let Some(mut ask) = next_auction_order(&mut orderbook.asks, &mut skipped_asks, now)
+            else {
+                orderbook.bids.insert(0, bid);
+                break;
+            };
+            let trade_quantity = bid.quantity.min(ask.quantity).min(remaining);
+            let trade_quote = quote_amount(clearing_price, trade_quantity, price_scale)?;
+            if ask.price > clearing_price || trade_quote == 0 {
+                orderbook.bids.insert(0, bid);
+                orderbook.asks.insert(0, ask);
+                break;
+            }
+
+            // 买卖双方结算方的代币账户
+            let buyer_slot = get_next_maker_accounts(
+                &mut accounts_iter,
+                orderbook,
+                bid.settlement_owner(),
+                bid.owner,
+            )?;
+            let seller_slot = get_next_maker_accounts(
+                &mut accounts_iter,
+                orderbook,
+                ask.settlement_owner(),
+                ask.owner,
+            )?;
+            let (mut buyer_accounts, mut seller_accounts) = match (buyer_slot, seller_slot) {
+                (MakerSlot::Open(buyer), MakerSlot::Open(seller)) => (*buyer, *seller),
+                (buyer_slot, seller_slot) => {
+                    // 任一方结算方的代币账户已被关闭：撤销该方挂单，另一方放回头部后继续竞价
+                    for (side, order, slot) in
+                        [(Side::Buy, bid, buyer_slot), (Side::Sell, ask, seller_slot)]
+                    {
+                        match slot {
+                            MakerSlot::Closed(mut claimable) => {
+                                cancel_stale_order(orderbook, &mut claimable, side, &order)?
+                            }
+                            MakerSlot::Open(_) => match side {
+                                Side::Buy => orderbook.bids.insert(0, order),
+                                Side::Sell => orderbook.asks.insert(0, order),
+                            },
+                        }
+                    }
+                    continue;
+                }
+            };
+
+            // 较新的挂单为 taker：买方手续费从收到的基础代币中扣除，卖方手续费从收到的报价代币中扣除
+            let buyer_is_taker = bid.order_id > ask.order_id;
+            let (buyer_fee_bps, seller_fee_bps) = if buyer_is_taker {
+                (orderbook.taker_fee_bps, orderbook.maker_fee_bps)
+            } else {
+                (orderbook.maker_fee_bps, orderbook.taker_fee_bps)
+            };
+            let buyer_fee = orderbook.fee_amount(trade_quantity, buyer_fee_bps)?;
+            let seller_fee = orderbook.fee_amount(trade_quote, seller_fee_bps)?;
+            let buyer_receives = trade_quantity
+                .checked_sub(buyer_fee)
+                .ok_or(DexError::CalculationError)?;
+            let seller_receives = trade_quote
+                .checked_sub(seller_fee)
+                .ok_or(DexError::CalculationError)?;
+
+            // 释放双方的锁定资金；买单按自身价格锁定，高出清算价的部分退还买方
+            let released_quote = bid.reduce(&Side::Buy, trade_quantity, price_scale)?;
+            let buyer_refund = released_quote
+                .checked_sub(trade_quote)
+                .ok_or(DexError::CalculationError)?;
+            ask.reduce(&Side::Sell, trade_quantity, price_scale)?;
+            require_vault_liquidity(&ctx.accounts.base_vault, trade_quantity)?;
+            require_vault_liquidity(&ctx.accounts.quote_vault, released_quote)?;
+
+            // 基础代币转给买方，报价代币转给卖方，手续费转入手续费金库
+            for (vault, mint, to, amount) in [
+                (
+                    &ctx.accounts.base_vault,
+                    &ctx.accounts.base_mint,
+                    buyer_accounts.owner_token_account.to_account_info(),
+                    buyer_receives,
+                ),
+                (
+                    &ctx.accounts.base_vault,
+                    &ctx.accounts.base_mint,
+                    ctx.accounts.base_fee_vault.to_account_info(),
+                    buyer_fee,
+                ),
+                (
+                    &ctx.accounts.quote_vault,
+                    &ctx.accounts.quote_mint,
+                    buyer_accounts.quote_token_account.to_account_info(),
+                    buyer_refund,
+                ),
+                (
+                    &ctx.accounts.quote_vault,
+                    &ctx.accounts.quote_mint,
+                    seller_accounts.quote_token_account.to_account_info(),
+                    seller_receives,
+                ),
+                (
+                    &ctx.accounts.quote_vault,
+                    &ctx.accounts.quote_mint,
+                    ctx.accounts.quote_fee_vault.to_account_info(),
+                    seller_fee,
+                ),
+            ] {
+                transfer_from_vault(
+                    token_program,
+                    vault,
+                    mint,
+                    to,
+                    orderbook.to_account_info(),
+                    signer,
+                    amount,
+                )?;
+            }
+
+            // maker 按成交额累计返佣，并记录成交
+            let (taker, maker, maker_claimable) = if buyer_is_taker {
+                (&bid, &ask, &mut seller_accounts.claimable)
+            } else {
+                (&ask, &bid, &mut buyer_accounts.claimable)
+            };
+            accrue_rebate(orderbook, maker_claimable, trade_quote)?;
+            record_volume(orderbook, trade_quantity, trade_quote)?;
+            let sequence_number = record_fill(
+                orderbook,
+                taker.owner,
+                maker.owner,
+                clearing_price,
+                trade_quantity,
+            )?;
+            emit!(TradeEvent {
+                taker: taker.owner,
+                maker: maker.owner,
+                base_mint: base_mint_key,
+                quote_mint: quote_mint_key,
+                quantity: trade_quantity,
+                price: clearing_price,
+                referrer: None,
+                sequence_number,
+                taker_order_id: taker.order_id,
+                maker_order_id: maker.order_id,
+            });
+
+            // 双方都是挂单：触发成交事件，OCO 组的其余挂单撤销并退款，剩余部分放回头部
+            for (side, mut order, accounts) in [
+                (Side::Buy, bid, &buyer_accounts),
+                (Side::Sell, ask, &seller_accounts),
+            ] {
+                let refilled = order.update_display(trade_quantity);
+                emit!(OrderFilledEvent {
+                    order_id: order.order_id,
+                    owner: order.owner,
+                    side: side.clone(),
+                    fill_quantity: trade_quantity,
+                    remaining_quantity: order.quantity,
+                    full_fill: order.quantity == 0,
+                });
+                if order.oco_group_id != 0 {
+                    let skipped_orders = match side {
+                        Side::Buy => &mut skipped_bids,
+                        Side::Sell => &mut skipped_asks,
+                    };
+                    let (quote_refund, base_refund) = cancel_oco_group(
+                        orderbook,
+                        skipped_orders,
+                        &side,
+                        order.owner,
+                        order.oco_group_id,
+                    )?;
+                    transfer_from_vault(
+                        token_program,
+                        &ctx.accounts.quote_vault,
+                        &ctx.accounts.quote_mint,
+                        accounts.quote_token_account.to_account_info(),
+                        orderbook.to_account_info(),
+                        signer,
+                        quote_refund,
+                    )?;
+                    transfer_from_vault(
+                        token_program,
+                        &ctx.accounts.base_vault,
+                        &ctx.accounts.base_mint,
+                        accounts.owner_token_account.to_account_info(),
+                        orderbook.to_account_info(),
+                        signer,
+                        base_refund,
+                    )?;
+                }
+                if refilled {
+                    requeue_iceberg(orderbook, &side, order);
+                } else if order.quantity > 0 {
+                    match side {
+                        Side::Buy => orderbook.bids.insert(0, order),
+                        Side::Sell => orderbook.asks.insert(0, order),
+                    }
+                }
+            }
+
+            remaining = remaining
+                .checked_sub(trade_quantity)
+                .ok_or(DexError::CalculationError)?;
+            fills += 1;
+        }
+        // 被跳过的挂单按原顺序放回头部
+        orderbook.bids.splice(0..0, skipped_bids);
+        orderbook.asks.splice(0..0, skipped_asks);
+
+        emit!(AuctionRunEvent {
+            clearing_price,
+            volume: volume
+                .checked_sub(remaining)
+                .ok_or(DexError::CalculationError)?,
+            fills,
+        });
+        Ok(())
+    }
+
+    // 扩容订单簿（无需许可）：每一方向增加 additional_orders 个挂单位置，
+    // 账户通过 realloc 扩大，新增租金由 payer 支付
+    pub fn grow_book(ctx: Context<GrowBook>, additional_orders: u32) -> Result<()> {
+        let orderbook = &mut ctx.accounts.orderbook;
+        orderbook.max_orders = orderbook
+            .max_orders
+            .checked_add(additional_orders)
+            .ok_or(DexError::CalculationError)?;
+        Ok(())
+    }
+
+    // 迁移订单簿账户到当前版本：订单簿以 UncheckedAccount 传入，按数据长度识别首个版本的旧布局
+    // （没有管理员，只能由程序升级权限迁移并成为管理员），其余账户按版本号迁移（仅管理员）。
+    // 账户按当前布局重新分配空间（新增租金由调用者支付）后重写，已是当前版本时不做任何修改，可重复调用
+    pub fn migrate(ctx: Context<Migrate>) -> Result<()> {
+        let info = ctx.accounts.orderbook.to_account_info();
+        let admin = ctx.accounts.admin.key();
+        let base_mint = ctx.accounts.base_mint.key();
+        let quote_mint = ctx.accounts.quote_mint.key();
+
+        // 订单簿必须由本程序拥有，判别符为 Orderbook，且地址为两个代币对应的 PDA
+        require_keys_eq!(*info.owner, crate::ID, DexError::InvalidMigrationAccount);
+        let (expected_key, _) = Pubkey::find_program_address(
+            &[
+                b"orderbook".as_ref(),
+                base_mint.as_ref(),
+                quote_mint.as_ref(),
+            ],
+            &crate::ID,
+        );
+        require_keys_eq!(info.key(), expected_key, DexError::InvalidMigrationAccount);
+
+        let mut orderbook = {
+            let data = info.try_borrow_data()?;
+            require!(
+                data.len() >= 8 + 32 + 32
+                    && &data[..8] == Orderbook::DISCRIMINATOR
+                    && data[8..40] == base_mint.to_bytes()
+                    && data[40..72] == quote_mint.to_bytes(),
+                DexError::InvalidMigrationAccount
+            );
+
+            if data.len() == LegacyOrderbook::SPACE {
+                // 首个版本的布局：只有代币对、挂单列表与订单 ID 计数器。旧账户没有管理员，
+                // 调用者迁移后成为管理员，因此只允许程序升级权限调用，防止他人抢先迁移
+                let program_data = ctx
+                    .accounts
+                    .program_data
+                    .as_ref()
+                    .ok_or(DexError::Unauthorized)?;
+                require!(
+                    program_data.upgrade_authority_address == Some(admin),
+                    DexError::Unauthorized
+                );
+                LegacyOrderbook::deserialize(&mut &data[8..])
+                    .map_err(|_| DexError::InvalidMigrationAccount)?
+                    .into_current(
+                        info.key(),
+                        admin,
+                        ctx.accounts.base_mint.decimals,
+                        ctx.accounts.quote_mint.decimals,
+                    )?
+            } else {
+                let mut orderbook = Orderbook::try_deserialize(&mut &data[..])?;
+                // 验证调用者为管理员
+                require_keys_eq!(admin, orderbook.admin, DexError::Unauthorized);
+                if orderbook.version == ORDERBOOK_VERSION {
+                    return Ok(());
+                }
+
+                // 版本 0：引入版本号之前创建的订单簿只支持 PDA 金库，补写金库地址
+                if orderbook.version == 0 {
+                    if orderbook.base_vault == Pubkey::default() {
+                        orderbook.base_vault = vault_pda(b"base_vault", &info.key());
+                    }
+                    if orderbook.quote_vault == Pubkey::default() {
+                        orderbook.quote_vault = vault_pda(b"quote_vault", &info.key());
+                    }
+                }
+                orderbook
+            }
+        };
+        orderbook.version = ORDERBOOK_VERSION;
+
+        // 记录管理员操作
+        log_admin_action(
+            &mut orderbook,
+            admin,
+            AdminActionKind::Migrate,
+            ORDERBOOK_VERSION as u64,
+        )?;
+
+        // 按当前布局所需空间扩容，补足租金豁免所需的 lamports
+        let space = Orderbook::space(orderbook.max_orders);
+        if info.data_len() < space {
+            let shortfall = Rent::get()?
+                .minimum_balance(space)
+                .saturating_sub(info.lamports());
+            if shortfall > 0 {
+                system_program::transfer(
+                    CpiContext::new(
+                        ctx.accounts.system_program.to_account_info(),
+                        system_program::Transfer {
+                            from: ctx.accounts.admin.to_account_info(),
+                            to: info.clone(),
+                        },
+                    ),
+                    shortfall,
+                )?;
+            }
+            info.resize(space)?;
+        }
+
+        // 以当前布局（含判别符）重写账户数据
+        let mut data = info.try_borrow_mut_data()?;
+        orderbook.try_serialize(&mut &mut data[..])?;
+        Ok(())
+    }
+
+    // 关闭订单簿（仅管理员）：订单簿上没有任何挂单和止损单时，关闭四个金库并关闭订单簿账户，
+    // 租金全部退还给管理员。金库必须为空（手续费需先通过 withdraw_fees 提取）
+    pub fn close_orderbook(ctx: Context<CloseOrderbook>) -> Result<()> {
+        let orderbook = &ctx.accounts.orderbook;
+        // 验证调用者为管理员
+        require_keys_eq!(
+            ctx.accounts.admin.key(),
+            orderbook.admin,
+            DexError::Unauthorized
+        );
+        // 仍有挂单时拒绝关闭，避免用户锁定的资金无法取回
+        require!(
+            orderbook.bids.is_empty() && orderbook.asks.is_empty() && orderbook.stops.is_empty(),
+            DexError::BookNotEmpty
+        );
+
+        // 设置订单簿种子和签名者
+        let orderbook_seeds = &[
+            b"orderbook".as_ref(),
+            orderbook.base_mint.as_ref(),
+            orderbook.quote_mint.as_ref(),
+            &[ctx.bumps.orderbook],
+        ];
+        let signer = &[&orderbook_seeds[..]];
+
+        // 确认金库余额为零后关闭，租金退还给管理员
+        for vault in [
+            &ctx.accounts.base_vault,
+            &ctx.accounts.quote_vault,
+            &ctx.accounts.base_fee_vault,
+            &ctx.accounts.quote_fee_vault,
+        ] {
+            require!(vault.amount == 0, DexError::VaultNotEmpty);
+            token_interface::close_account(CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                CloseAccount {
+                    account: vault.to_account_info(),
+                    destination: ctx.accounts.admin.to_account_info(),
+                    authority: orderbook.to_account_info(),
+                },
+                signer,
+            ))?;
+        }
+        // 订单簿账户由 close = admin 约束在指令结束时关闭
+        Ok(())
+    }
+
+    // 只读查询管理员操作日志，按时间从早到晚返回
+    pub fn get_admin_log(ctx: Context<GetAdminLog>) -> Result<Vec<AdminAction>> {
+        Ok(ctx.accounts.orderbook.admin_log.clone())
+    }
+
+    // 只读查询订单簿账户的当前大小、租金豁免最低余额及当前余额，便于规划扩容与关闭
+    pub fn get_account_info(ctx: Context<GetAccountInfo>) -> Result<OrderbookAccountInfo> {
+        let account_info = ctx.accounts.orderbook.to_account_info();
+        let data_len = account_info.data_len();
+        Ok(OrderbookAccountInfo {
+            data_len: data_len as u64,
+            rent_exempt_minimum: Rent::get()?.minimum_balance(data_len),
+            lamports: account_info.lamports(),
+        })
+    }
+
+    // 只读查询订单簿深度：返回买卖两侧最优的 levels 个价位，同价订单数量合并为一个价位
+    pub fn get_order_book_depth(
+        ctx: Context<GetOrderBookDepth>,
+        levels: u8, // 每一方向返回的价位数量（超过 MAX_DEPTH_LEVELS 时按上限返回）
+    ) -> Result<OrderBookDepth> {
+        let orderbook = &ctx.accounts.orderbook;
+        let levels = (levels as usize).min(MAX_DEPTH_LEVELS);
+        Ok(OrderBookDepth {
+            bids: aggregate_levels(&orderbook.bids, levels)?,
+            asks: aggregate_levels(&orderbook.asks, levels)?,
+        })
+    }
+
+    // 只读查询最优买卖价：买单按价格降序、卖单按价格升序排列，最优价位于各自首位；
+    // 某一方向为空时对应字段返回 0
+    pub fn peek_best_bid_ask(ctx: Context<PeekBestBidAsk>) -> Result<TopOfBook> {
+        let orderbook = &ctx.accounts.orderbook;
+        let (bid_price, bid_qty) = orderbook
+            .bids
+            .first()
+            .map_or((0, 0), |order| (order.price, order.matchable_quantity()));
+        let (ask_price, ask_qty) = orderbook
+            .asks
+            .first()
+            .map_or((0, 0), |order| (order.price, order.matchable_quantity()));
+        Ok(TopOfBook {
+            bid_price,
+            bid_qty,
+            ask_price,
+            ask_qty,
+        })
+    }
+
+    // 只读模拟一个限价单：按撮合规则遍历对手盘，返回逐笔成交、剩余挂单数量以及成交的基础/报价代币总额；
+    // 不转移代币、不修改订单簿，因此不需要 maker 的代币账户。模拟订单没有拥有者，不考虑自成交
+    pub fn simulate_order(
+        ctx: Context<SimulateOrder>,
+        side: Side,    // 订单方向（买/卖）
+        price: u64,    // 订单价格
+        quantity: u64, // 订单数量
+    ) -> Result<OrderSimulation> {
+        require!(price > 0 && quantity > 0, DexError::InvalidOrderParams);
+        let orderbook = &ctx.accounts.orderbook;
+        let taker_order = Order {
+            owner: Pubkey::default(),
+            price,
+            quantity,
+            order_id: 0,
+            frozen: false,
+            oco_group_id: 0,
+            expiry_ts: 0,
+            locked_quote: 0,
+            locked_base: 0,
+            display_size: 0,
+            display_quantity: 0,
+            settlement_authority: None,
+            original_quantity: quantity,
+        };
+        let mut estimate = estimate_fills(
+            orderbook,
+            &side,
+            &taker_order,
+            OrderType::Limit,
+            0,
+            false,
+            SelfTradePrevention::CancelResting,
+            0,
+            Clock::get()?.unix_timestamp,
+        )?;
+        // 逐笔成交按上限截断，总额仍包含全部成交
+        estimate.fills.truncate(MAX_SIMULATED_FILLS);
+        Ok(OrderSimulation {
+            fills: estimate.fills,
+            filled_quantity: estimate.quantity,
+            resting_quantity: quantity
+                .checked_sub(estimate.quantity)
+                .ok_or(DexError::CalculationError)?,
+            quote_amount: estimate.quote,
+        })
+    }
+}
+
+// 校验初始化参数并写入订单簿初始状态，initialize 与 initialize_with_vaults 共用
+#[allow(clippy::too_many_arguments)]
+fn init_orderbook(
+    orderbook: &mut Orderbook,
+    base_mint: &InterfaceAccount<Mint>, // 基础代币
+    quote_mint: &InterfaceAccount<Mint>, // 报价代币
+    admin: Pubkey, // 管理员
+    base_vault: Pubkey, // 基础代币金库
+    quote_vault: Pubkey, // 报价代币金库
+    min_fill_notional: u64, // 单笔成交的最小报价代币金额
+    execution_price_mode: ExecutionPriceMode, // 成交价格模式
+    maker_fee_bps: u16, // maker 手续费（基点）
+    taker_fee_bps: u16, // taker 手续费（基点）
+    min_base_quantity: u64, // 单笔订单的最小基础代币数量
+    price_tick: u64, // 价格最小变动单位
+    native_leg: NativeLeg, // 以原生 SOL 结算的一方（该方代币必须为 wSOL）
+    price_is_scaled: bool, // 价格是否按每个完整基础代币计价（报价金额除以 10^base_decimals）
+    max_orders_per_owner: u16, // 每个拥有者在订单簿上的挂单数量上限（0 表示不限制）
+) -> Result<()> {
+    // 基础代币与报价代币不能相同
+    require_keys_neq!(base_mint.key(), quote_mint.key(), DexError::IdenticalMints);
+    // 验证手续费不超过 100%
+    require!(
+        maker_fee_bps <= BPS_DENOMINATOR && taker_fee_bps <= BPS_DENOMINATOR,
+        DexError::InvalidFeeBps
+    );
+    // 价格最小变动单位必须为正数
+    require!(price_tick > 0, DexError::InvalidTickSize);
+    // 标记为原生 SOL 的一方必须是 wSOL
+    match native_leg {
+        NativeLeg::None => {}
+        NativeLeg::Base => require!(
+            is_native_mint(&base_mint.key()),
+            DexError::InvalidNativeMint
+        ),
+        NativeLeg::Quote => require!(
+            is_native_mint(&quote_mint.key()),
+            DexError::InvalidNativeMint
+        ),
+    }
+    // 启用价格缩放时 10^base_decimals 必须能以 u64 表示
+    let base_decimals = base_mint.decimals;
+    require!(
+        !price_is_scaled || 10u64.checked_pow(base_decimals as u32).is_some(),
+        DexError::UnsupportedDecimals
+    );
+
+    orderbook.base_mint = base_mint.key(); // 设置基础代币公钥
+    orderbook.quote_mint = quote_mint.key(); // 设置报价代币公钥
+    orderbook.bids = Vec::new(); // 初始化买单列表
+    orderbook.asks = Vec::new(); // 初始化卖单列表
+    orderbook.order_id_counter = 0; // 初始化订单 ID 计数器
+    orderbook.min_fill_notional = min_fill_notional; // 设置单笔最小成交额
+    orderbook.admin = admin; // 设置管理员为支付者
+    orderbook.execution_price_mode = execution_price_mode; // 设置成交价格模式
+    orderbook.maker_fee_bps = maker_fee_bps; // 设置 maker 手续费
+    orderbook.taker_fee_bps = taker_fee_bps; // 设置 taker 手续费
+    orderbook.min_base_quantity = min_base_quantity; // 设置最小下单数量
+    orderbook.price_tick = price_tick; // 设置价格最小变动单位
+    orderbook.max_orders = INITIAL_MAX_ORDERS; // 设置每一方向的挂单容量
+    orderbook.stops = Vec::new(); // 初始化止损单列表
+    orderbook.last_trade_price = 0; // 尚无成交
+    orderbook.paused = false; // 初始为正常交易状态
+    orderbook.rebate_bps = 0; // 默认不返佣
+    orderbook.rebates_owed = 0; // 尚无未领取的返佣
+    orderbook.claimable_base = 0; // 尚无待领取的撤单资金
+    orderbook.claimable_quote = 0;
+    orderbook.native_leg = native_leg; // 设置原生 SOL 结算的一方
+    orderbook.base_decimals = base_decimals; // 记录基础代币精度
+    orderbook.quote_decimals = quote_mint.decimals; // 记录报价代币精度
+    orderbook.price_is_scaled = price_is_scaled; // 设置价格是否按完整基础代币计价
+    orderbook.min_quote_notional = 0; // 默认不限制订单名义金额
+    orderbook.oracle = None; // 默认不使用预言机
+    orderbook.max_deviation_bps = 0;
+    orderbook.oracle_max_age = 0;
+    orderbook.rolling_volume_base = 0; // 窗口起点为 0，第一笔成交时开启成交量统计窗口
+    orderbook.rolling_volume_quote = 0;
+    orderbook.volume_window_start = 0;
+    orderbook.auction_mode = false; // 默认连续撮合
+    orderbook.max_orders_per_owner = max_orders_per_owner; // 设置每个拥有者的挂单数量上限
+    orderbook.fee_rounding = RoundingMode::Ceil; // 默认手续费向上取整，舍入偏向协议
+    orderbook.base_vault = base_vault; // 记录金库地址，后续指令按地址校验金库
+    orderbook.quote_vault = quote_vault;
+    orderbook.version = ORDERBOOK_VERSION; // 新建的订单簿直接使用当前布局
+    Ok(())
+}
+
+// 将已排序的挂单按价格合并为价位，返回最优的 levels 个价位
+fn aggregate_levels(orders: &[Order], levels: usize) -> Result<Vec<PriceLevel>> {
+    let mut aggregated: Vec<PriceLevel> = Vec::new();
+    for order in orders {
+        match aggregated.last_mut() {
+            Some(level) if level.price == order.price => {
+                level.quantity = level
+                    .quantity
+                    .checked_add(order.matchable_quantity())
+                    .ok_or(DexError::CalculationError)?;
+            }
+            _ => {
+                if aggregated.len() == levels {
+                    break;
+                }
+                aggregated.push(PriceLevel {
+                    price: order.price,
+                    quantity: order.matchable_quantity(),
+                });
+            }
+        }
+    }
+    Ok(aggregated)
+}
+
+// 设置订单冻结状态，买卖两侧均查找
+fn set_order_frozen(ctx: Context<FreezeOrder>, order_id: u64, frozen: bool) -> Result<()> {
+    let orderbook: &mut Orderbook = &mut ctx.accounts.orderbook;
+    // 验证调用者为管理员
+    require_keys_eq!(
+        ctx.accounts.admin.key(),
+        orderbook.admin,
+        DexError::Unauthorized
+    );
+
+    let order = orderbook
+        .bids
+        .iter_mut()
+        .chain(orderbook.asks.iter_mut())
+        .find(|o| o.order_id == order_id)
+        .ok_or(DexError::OrderNotFound)?;
+    order.frozen = frozen;
+
+    // 记录管理员操作
+    let kind = if frozen {
+        AdminActionKind::FreezeOrder
+    } else {
+        AdminActionKind::UnfreezeOrder
+    };
+    log_admin_action(orderbook, ctx.accounts.admin.key(), kind, order_id)
+}
+
+// 校验订单名义金额不低于订单簿设置的下限：价格 × 数量以 u128 计算，与 下限 × 价格缩放因子 比较，
+// 避免乘积溢出或缩放时的向下取整影响校验结果
+fn require_min_notional(orderbook: &Orderbook, price: u64, quantity: u64) -> Result<()> {
+    let notional = (price as u128) * (quantity as u128);
+    let minimum = (orderbook.min_quote_notional as u128) * (orderbook.price_scale() as u128);
+    require!(notional >= minimum, DexError::NotionalTooSmall);
+    Ok(())
+}
+
+// Pyth 价格账户（PriceUpdateV2）开头部分的布局，与 pyth-solana-receiver-sdk 一致，
+// 只反序列化到发布时间为止
+#[derive(AnchorDeserialize)]
+struct PythPriceUpdate {
+    _write_authority: Pubkey,
+    verification_level: PythVerificationLevel,
+    _feed_id: [u8; 32],
+    price: i64,
+    _conf: u64,
+    exponent: i32,
+    publish_time: i64,
+}
+
+// Pyth 价格的验证级别：只接受经过完整 Wormhole 签名验证的价格
+#[derive(AnchorDeserialize, PartialEq, Eq)]
+enum PythVerificationLevel {
+    Partial { num_signatures: u8 },
+    Full,
+}
+
+// 校验限价不偏离预言机价格超过 max_deviation_bps（未配置预言机时不检查）。
+// 预言机价格 price × 10^exponent 表示每个完整基础代币的报价代币数量，按代币精度换算到订单簿的价格单位后比较
+fn require_within_oracle_band(
+    orderbook: &Orderbook,
+    oracle: Option<&AccountInfo>,
+    price: u64,
+) -> Result<()> {
+    let Some(oracle_key) = orderbook.oracle else {
+        return Ok(());
+    };
+    // 必须传入订单簿配置的、由 Pyth 程序拥有的价格账户
+    let oracle = oracle.ok_or(DexError::InvalidOracle)?;
+    require_keys_eq!(oracle.key(), oracle_key, DexError::InvalidOracle);
+    require_keys_eq!(
+        *oracle.owner,
+        PYTH_RECEIVER_PROGRAM_ID,
+        DexError::InvalidOracle
+    );
+    let data = oracle.try_borrow_data()?;
+    require!(
+        data.len() > 8 && data[..8] == PYTH_PRICE_UPDATE_DISCRIMINATOR,
+        DexError::InvalidOracle
+    );
+    let update =
+        PythPriceUpdate::deserialize(&mut &data[8..]).map_err(|_| DexError::InvalidOracle)?;
+    require!(
+        update.verification_level == PythVerificationLevel::Full && update.price > 0,
+        DexError::InvalidOracle
+    );
+    // 预言机价格过旧时拒绝
+    let now = Clock::get()?.unix_timestamp;
+    require!(
+        now.saturating_sub(update.publish_time) <= orderbook.oracle_max_age as i64,
+        DexError::StaleOracle
+    );
+
+    // 换算指数：exponent + quote_decimals - base_decimals（价格按完整基础代币计价时不减基础代币精度）
+    let base_decimals = if orderbook.price_is_scaled {
+        0
+    } else {
+        orderbook.base_decimals as i32
+    };
+    let exponent = update.exponent + orderbook.quote_decimals as i32 - base_decimals;
+    let scale = 10u128
+        .checked_pow(exponent.unsigned_abs())
+        .ok_or(DexError::CalculationError)?;
+    // 指数为负时放大订单价格，为正时放大预言机价格，避免除法带来的精度损失
+    let (order_value, reference) = if exponent < 0 {
+        (
+            (price as u128).checked_mul(scale),
+            Some(update.price as u128),
+        )
+    } else {
+        (
+            Some(price as u128),
+            (update.price as u128).checked_mul(scale),
+        )
+    };
+    let (order_value, reference) = order_value
+        .zip(reference)
+        .ok_or(DexError::CalculationError)?;
+    // 偏离比例 = |订单价格 - 参考价| / 参考价，不得超过 max_deviation_bps
+    let deviation = order_value
+        .abs_diff(reference)
+        .checked_mul(BPS_DENOMINATOR as u128)
+        .ok_or(DexError::CalculationError)?;
+    let allowed = reference
+        .checked_mul(orderbook.max_deviation_bps as u128)
+        .ok_or(DexError::CalculationError)?;
+    require!(deviation <= allowed, DexError::PriceOutOfBand);
+    Ok(())
+}
+
+// 推导订单簿的 PDA 金库地址（seed 为 base_vault 或 quote_vault）
+fn vault_pda(seed: &[u8], orderbook_key: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[seed, orderbook_key.as_ref()], &crate::ID).0
+}
+
+// 向管理员操作日志追加一条记录，日志已满时丢弃最早的记录
+fn log_admin_action(
+    orderbook: &mut Orderbook,
+    actor: Pubkey,
+    kind: AdminActionKind,
+    param: u64,
+) -> Result<()> {
+    if orderbook.admin_log.len() >= MAX_ADMIN_LOG_ENTRIES {
+        orderbook.admin_log.remove(0);
+    }
+    orderbook.admin_log.push(AdminAction {
+        actor,
+        kind,
+        timestamp: Clock::get()?.unix_timestamp,
+        param,
+    });
+    Ok(())
+}
+
+// 按成交额为 maker 累计返佣：记入 maker 的待领取余额账户，并计入订单簿的未领取返佣总额
+fn accrue_rebate(
+    orderbook: &mut Orderbook,
+    claimable: &mut Account<ClaimableBalance>,
+    quote_volume: u64,
+) -> Result<()> {
+    // 返佣从手续费金库支付，固定向下取整
+    let rebate = apply_bps(
+        quote_volume as u128,
+        orderbook.rebate_bps,
+        RoundingMode::Floor,
+    )?;
+    if rebate == 0 {
+        return Ok(());
+    }
+    // 同一拥有者的待领取余额账户可能已在本指令中通过另一份副本更新，修改前重新读取
+    claimable.reload()?;
+    claimable.rebate = claimable
+        .rebate
+        .checked_add(rebate)
+        .ok_or(DexError::CalculationError)?;
+    orderbook.rebates_owed = orderbook
+        .rebates_owed
+        .checked_add(rebate)
+        .ok_or(DexError::CalculationError)?;
+    // remaining_accounts 中的账户不会在指令结束时自动写回，修改后立即持久化
+    claimable.exit(&crate::ID)
+}
+
+// 为一笔成交分配序列号并记录其 Merkle 叶子，返回序列号；
+// 待结算叶子已满时先自动提交当前批次，保证撮合不会因结算滞后而阻塞
+fn record_fill(
+    orderbook: &mut Orderbook,
+    taker: Pubkey,
+    maker: Pubkey,
+    price: u64,
+    quantity: u64,
+) -> Result<u64> {
+    orderbook.sequence_number = orderbook
+        .sequence_number
+        .checked_add(1)
+        .ok_or(DexError::CalculationError)?;
+    let fill = FillRecord {
+        sequence_number: orderbook.sequence_number,
+        taker,
+        maker,
+        price,
+        quantity,
+    };
+    if orderbook.pending_fills.len() >= MAX_PENDING_FILLS {
+        commit_fill_batch(orderbook);
+    }
+    orderbook.pending_fills.push(fill.leaf_hash()?);
+    orderbook.last_trade_price = price; // 更新最新成交价，供止损单触发判断
+    Ok(orderbook.sequence_number)
+}
+
+// 累计成交量。统计采用滚动（tumbling）窗口而非真正的滑动窗口：距窗口起点超过
+// VOLUME_WINDOW_SECONDS 后的第一笔成交清零累计量，并以当前时间作为新窗口起点。
+// 因此累计量只代表当前窗口开始以来的成交量，窗口刚重置时会明显低于真实的 24 小时成交量，
+// 且窗口期内无成交时累计量不会自动清零（读取方需结合 volume_window_start 判断）
+fn record_volume(orderbook: &mut Orderbook, quantity: u64, quote_volume: u64) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    if now.saturating_sub(orderbook.volume_window_start) > VOLUME_WINDOW_SECONDS {
+        orderbook.rolling_volume_base = 0;
+        orderbook.rolling_volume_quote = 0;
+        orderbook.volume_window_start = now;
+    }
+    // 统计数据溢出时饱和，不影响成交
+    orderbook.rolling_volume_base = orderbook.rolling_volume_base.saturating_add(quantity);
+    orderbook.rolling_volume_quote = orderbook.rolling_volume_quote.saturating_add(quote_volume);
+    Ok(())
+}
+
+// 将待结算叶子提交为新的 Merkle 根并清空缓冲区（无待结算叶子时不做任何事）
+fn commit_fill_batch(orderbook: &mut Orderbook) {
+    if orderbook.pending_fills.is_empty() {
+        return;
+    }
+    let root = merkle_root(&orderbook.pending_fills);
+    orderbook.fill_batch_id += 1;
+    // 按批次编号写入循环缓冲区，覆盖 MAX_FILL_ROOTS 个批次之前的根
+    orderbook.fill_roots[orderbook.fill_batch_id as usize % MAX_FILL_ROOTS] = root;
+    emit!(FillBatchSettledEvent {
+        batch_id: orderbook.fill_batch_id,
+        root,
+        fill_count: orderbook.pending_fills.len() as u32,
+        last_sequence_number: orderbook.sequence_number,
+    });
+    orderbook.pending_fills.clear();
+}
+
+// 计算内部节点哈希：两个子节点排序后拼接（前缀 0x01），证明时无需携带左右位置
+fn hash_merkle_pair(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let (left, right) = if a <= b { (a, b) } else { (b, a) };
+    hashv(&[&[1u8], left, right]).to_bytes()
+}
+
+// 自底向上计算 Merkle 根，奇数个节点时最后一个节点直接晋升到上一层
+fn merkle_root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => hash_merkle_pair(left, right),
+                [single] => *single,
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    level.first().copied().unwrap_or_default()
+}
+
+// 止损限价单下单：按限价校验并锁定资金（与限价挂单的锁定方式相同），存入止损单列表等待触发
+fn place_stop_order<'info>(
+    ctx: Context<'_, '_, 'info, 'info, PlaceOrder<'info>>,
+    side: Side,
+    trigger_price: u64,
+    limit_price: u64,
+    quantity: u64,
+) -> Result<()> {
+    let orderbook = &mut ctx.accounts.orderbook; // 可变引用订单簿
+    let owner = &ctx.accounts.owner; // 订单拥有者
+    let token_program = &ctx.accounts.token_program; // 代币程序
+
+    // 验证订单数量、限价与触发价精度，以及止损单容量
+    require!(limit_price > 0, DexError::InvalidOrderParams);
+    require!(quantity >= orderbook.min_base_quantity, DexError::OrderTooSmall);
+    require!(
+        limit_price.checked_rem(orderbook.price_tick) == Some(0),
+        DexError::InvalidTickSize
+    );
+    require_min_notional(orderbook, limit_price, quantity)?;
+    require_within_oracle_band(orderbook, ctx.accounts.oracle.as_deref(), limit_price)?;
+    require!(
+        trigger_price > 0 && trigger_price.checked_rem(orderbook.price_tick) == Some(0),
+        DexError::InvalidTriggerPrice
+    );
+    require!(
+        orderbook.stops.len() < MAX_STOP_ORDERS,
+        DexError::TooManyStopOrders
+    );
+
+    // 设置订单簿种子和签名者
+    let base_mint_key = orderbook.base_mint;
+    let quote_mint_key = orderbook.quote_mint;
+    let orderbook_seeds = &[
+        b"orderbook".as_ref(),
+        base_mint_key.as_ref(),
+        quote_mint_key.as_ref(),
+        &[ctx.bumps.orderbook],
+    ];
+    let signer = &[&orderbook_seeds[..]];
+
+    // 锁定资金：按金库实际收到的数量入账
+    let quantity = match side {
+        Side::Buy => {
+            let total_quote_to_lock = quote_to_lock(
+                OrderType::Limit,
+                limit_price,
+                quantity,
+                0,
+                orderbook.price_scale(),
+            )?;
+            if orderbook.native_leg == NativeLeg::Quote {
+                wrap_native(
+                    &ctx.accounts.system_program,
+                    token_program,
+                    owner,
+                    &ctx.accounts.owner_quote_token_account,
+                    total_quote_to_lock,
+                )?;
+            }
+            let quote_locked = transfer_to_vault(
+                token_program,
+                &ctx.accounts.owner_quote_token_account,
+                &mut ctx.accounts.quote_vault,
+                &ctx.accounts.quote_mint,
+                owner.to_account_info(),
+                total_quote_to_lock,
+            )?;
+            // 按实际锁定的报价代币确定数量，退还不足一个单位的零头
+            let quantity = quantity.min(base_for_quote(
+                quote_locked,
+                limit_price,
+                orderbook.price_scale(),
+            )?);
+            transfer_from_vault(
+                token_program,
+                &ctx.accounts.quote_vault,
+                &ctx.accounts.quote_mint,
+                ctx.accounts.owner_quote_token_account.to_account_info(),
+                orderbook.to_account_info(),
+                signer,
+                quote_locked
+                    .checked_sub(locked_quote_amount(
+                        limit_price,
+                        quantity,
+                        orderbook.price_scale(),
+                    )?)
+                    .ok_or(DexError::CalculationError)?,
+            )?;
+            quantity
+        }
+        Side::Sell => {
+            if orderbook.native_leg == NativeLeg::Base {
+                wrap_native(
+                    &ctx.accounts.system_program,
+                    token_program,
+                    owner,
+                    &ctx.accounts.owner_base_token_account,
+                    quantity,
+                )?;
+            }
+            transfer_to_vault(
+                token_program,
+                &ctx.accounts.owner_base_token_account,
+                &mut ctx.accounts.base_vault,
+                &ctx.accounts.base_mint,
+                owner.to_account_info(),
+                quantity,
+            )?
+        }
+    };
+
+    orderbook.order_id_counter += 1; // 增加订单 ID
+    let stop_order = StopOrder {
+        owner: owner.key(),
+        side,
+        trigger_price,
+        limit_price,
+        quantity,
+        order_id: orderbook.order_id_counter,
+    };
+
+    // 触发止损单下单事件
+    emit!(StopOrderPlacedEvent {
+        order_id: stop_order.order_id,
+        owner: stop_order.owner,
+        side: stop_order.side.clone(),
+        trigger_price,
+        limit_price,
+        quantity,
+    });
+
+    orderbook.stops.push(stop_order);
+
+    // 原生 SOL 一方的退款解包为 SOL
+    unwrap_native(
+        orderbook.native_leg,
+        token_program,
+        owner,
+        &ctx.accounts.owner_base_token_account,
+        &ctx.accounts.owner_quote_token_account,
+    )
+}
+
+// 撮合 taker 订单与对手盘（从最佳价格开始依次成交，最多 max_fills 笔）并完成代币结算，
+// taker_order.quantity 随成交递减，返回本次撮合的汇总结果；
+// 买单撮合结束后退还多锁定的报价代币，剩余部分需挂单（rests_remainder）时保留按限价计算的锁定资金
+#[allow(clippy::too_many_arguments)]
+fn match_order<'info>(
+    orderbook: &mut Account<'info, Orderbook>,
+    accounts: &MatchAccounts<'_, 'info>,
+    signer: &[&[&[u8]]],
+    maker_accounts_iter: &mut Peekable<Iter<'info, AccountInfo<'info>>>,
+    taker_order: &mut Order,
+    side: &Side,
+    order_type: OrderType,
+    max_quote: u64,
+    quote_budget: bool,
+    quote_locked: u64,
+    rests_remainder: bool,
+    skip_matching: bool,
+    self_trade_prevention: SelfTradePrevention,
+    worst_price: u64,
+    referrer: Option<Pubkey>,
+    now: i64,
+    max_fills: usize,
+) -> Result<MatchSummary> {
+    let token_program = accounts.token_program; // 代币程序
+    let base_mint_key = orderbook.base_mint;
+    let quote_mint_key = orderbook.quote_mint;
+    let price_scale = orderbook.price_scale(); // 价格缩放因子
+    // 被跳过的挂单（已冻结或单笔成交额过小），撮合结束后放回订单簿
+    let mut skipped_orders = Vec::new();
+    // 已完成的成交汇总
+    let mut summary = MatchSummary::default();
+
+    // maker 挂单所在的方向（与 taker 相反）
+    let maker_side = side.opposite();
+    // 买方手续费从收到的基础代币中扣除，卖方手续费从收到的报价代币中扣除
+    let (buyer_fee_bps, seller_fee_bps) = match side {
+        Side::Buy => (orderbook.taker_fee_bps, orderbook.maker_fee_bps),
+        Side::Sell => (orderbook.maker_fee_bps, orderbook.taker_fee_bps),
+    };
+
+    // 循环撮合（post-only 或延迟撮合的订单跳过撮合直接挂单）
+    while !skip_matching && taker_order.quantity > 0 && summary.fills < max_fills {
+        // 获取对手盘最佳价格（买单按价格降序、卖单按价格升序，最佳价位于列表头部）
+        let best_price = match side.opposing_book(orderbook).first() {
+            Some(order) => order.price,
+            None => break, // 对手盘为空，退出
+        };
+
+        // 限价单价格与最佳价不再交叉时退出（市价单不受价格限制）
+        if order_type == OrderType::Limit && !side.crosses(taker_order.price, best_price) {
+            break;
+        }
+
+        // 滑点保护：最佳价劣于可接受的最差价格时停止撮合，剩余部分按订单类型挂单或退还
+        if worst_price != 0 && !side.crosses(worst_price, best_price) {
+            break;
+        }
+
+        // 取出对手盘最佳挂单进行撮合
+        let mut maker_order = side.opposing_book(orderbook).remove(0);
+
+        // 已过期的挂单不参与撮合：从订单簿移除，并向订单的结算方退还锁定的资金（与 reap_expired 相同）
+        // （结算方的代币账户已被关闭时记入拥有者的待领取余额）；
+        // 防御性检查：数量为 0 的挂单无法成交，同样移除并退还其残留的锁定资金，避免撮合循环无法推进
+        if maker_order.is_expired(now) || maker_order.quantity == 0 {
+            let maker_accounts = match get_next_maker_accounts(
+                maker_accounts_iter,
+                orderbook,
+                maker_order.settlement_owner(),
+                maker_order.owner,
+            )? {
+                MakerSlot::Open(maker_accounts) => *maker_accounts,
+                MakerSlot::Closed(mut claimable) => {
+                    cancel_stale_order(
+                        orderbook,
+                        &mut claimable,
+                        maker_side.clone(),
+                        &maker_order,
+                    )?;
+                    continue;
+                }
+            };
+            let (vault, mint) = accounts.locked_vault(&maker_side);
+            let refund_amount = total_locked(&[maker_order], &maker_side)?;
+            transfer_from_vault(
+                token_program,
+                vault,
+                mint,
+                maker_accounts.locked_account(&maker_side),
+                orderbook.to_account_info(),
+                signer,
+                refund_amount,
+            )?;
+            emit!(OrderCanceledEvent {
+                order_id: maker_order.order_id,
+                owner: maker_order.owner,
+                side: maker_side.clone(),
+                quantity: maker_order.quantity,
+                refund_mint: mint.key(),
+                refund_amount,
+            });
+            continue;
+        }
+
+        // 被冻结的挂单不参与撮合
+        if maker_order.frozen {
+            skipped_orders.push(maker_order);
+            continue;
+        }
+
+        // 自成交防范：不与自己的挂单成交，撤销（或部分撤销）该挂单并将其锁定资金退还给 taker，
+        // 然后继续与下一个最佳挂单撮合，不产生交易事件
+        if maker_order.owner == taker_order.owner {
+            let cancel_quantity = match self_trade_prevention {
+                SelfTradePrevention::CancelResting => maker_order.quantity,
+                SelfTradePrevention::DecrementAndCancel => {
+                    // taker 同步减少：卖单立即退还对应的基础代币，
+                    // 买单对应的报价代币在撮合结束后随多锁定部分一并退还
+                    let decrement = taker_order.quantity.min(maker_order.quantity);
+                    taker_order.quantity = taker_order
+                        .quantity
+                        .checked_sub(decrement)
+                        .ok_or(DexError::CalculationError)?;
+                    if !side.is_buy() {
+                        transfer_from_vault(
+                            token_program,
+                            accounts.base_vault,
+                            accounts.base_mint,
+                            accounts.taker_base_token_account.to_account_info(),
+                            orderbook.to_account_info(),
+                            signer,
+                            decrement,
+                        )?;
+                    }
+                    decrement
+                }
+            };
+            let released = maker_order.reduce(&maker_side, cancel_quantity, price_scale)?;
+            maker_order.update_display(0);
+            let (vault, mint) = accounts.locked_vault(&maker_side);
+            transfer_from_vault(
+                token_program,
+                vault,
+                mint,
+                accounts.taker_account(&maker_side),
+                orderbook.to_account_info(),
+                signer,
+                released,
+            )?;
+            if maker_order.quantity > 0 {
+                side.opposing_book(orderbook).insert(0, maker_order);
+            } else {
+                // 挂单被全部撤销，触发撤单事件（退款转入 taker 即挂单拥有者的账户）
+                emit!(OrderCanceledEvent {
+                    order_id: maker_order.order_id,
+                    owner: maker_order.owner,
+                    side: maker_side.clone(),
+                    quantity: cancel_quantity,
+                    refund_mint: mint.key(),
+                    refund_amount: released,
+                });
+            }
+            continue;
+        }
+
+        // 计算成交价格（市价单没有限价，按 maker 价格成交）
+        let trade_price = match order_type {
+            OrderType::Limit | OrderType::StopLimit { .. } => orderbook
+                .execution_price_mode
+                .execution_price(maker_order.price, taker_order.price),
+            OrderType::Market => maker_order.price,
+        };
+        // 计算交易数量（取最小值，冰山单只能成交当前显示的部分）
+        let mut trade_quantity = taker_order.quantity.min(maker_order.matchable_quantity());
+        // 市价买单（以及按报价预算买入的订单）的成交数量还受剩余报价预算限制，
+        // 预算不足以买入一个单位时停止撮合
+        if side.is_buy() && (order_type == OrderType::Market || quote_budget) {
+            let budget = max_quote
+                .checked_sub(summary.quote_spent)
+                .ok_or(DexError::CalculationError)?;
+            let affordable_quantity = base_for_quote(budget, trade_price, price_scale)?;
+            trade_quantity = trade_quantity.min(affordable_quantity);
+            if trade_quantity == 0 {
+                side.opposing_book(orderbook).insert(0, maker_order);
+                break;
+            }
+        }
+        // 计算报价代币转移总量
+        let total_quote_transfer = quote_amount(trade_price, trade_quantity, price_scale)?;
+
+        // 单笔成交额低于下限（启用价格缩放时报价金额向下取整为 0 也视为过小）时跳过该挂单，
+        // 使其继续挂在订单簿上
+        if total_quote_transfer < orderbook.min_fill_notional.max(1) {
+            skipped_orders.push(maker_order);
+            continue;
+        }
+
+        // maker 结算方的代币账户已被关闭时撤销该挂单（锁定资金记入拥有者的待领取余额），
+        // 继续与下一个挂单撮合
+        let mut maker_accounts = match get_next_maker_accounts(
+            maker_accounts_iter,
+            orderbook,
+            maker_order.settlement_owner(),
+            maker_order.owner,
+        )? {
+            MakerSlot::Open(maker_accounts) => *maker_accounts,
+            MakerSlot::Closed(mut claimable) => {
+                cancel_stale_order(orderbook, &mut claimable, maker_side.clone(), &maker_order)?;
+                continue;
+            }
+        };
+
+        // 计算手续费
+        let buyer_fee = orderbook.fee_amount(trade_quantity, buyer_fee_bps)?;
+        let seller_fee = orderbook.fee_amount(total_quote_transfer, seller_fee_bps)?;
+        let buyer_receives = trade_quantity
+            .checked_sub(buyer_fee)
+            .ok_or(DexError::CalculationError)?;
+        let seller_receives = total_quote_transfer
+            .checked_sub(seller_fee)
+            .ok_or(DexError::CalculationError)?;
+
+        // 释放 maker 挂单本次成交对应的锁定资金；maker 买单按自身价格锁定，
+        // 成交价更低时（中间价或 taker 价格模式）将释放部分超出支付的差额退还 maker
+        let released = maker_order.reduce(&maker_side, trade_quantity, price_scale)?;
+        let refilled = maker_order.update_display(trade_quantity);
+        let maker_quote_refund = match maker_side {
+            Side::Buy => released
+                .checked_sub(total_quote_transfer)
+                .ok_or(DexError::CalculationError)?,
+            Side::Sell => 0,
+        };
+
+        // maker 锁定资金所在的金库必须足以支付本次成交（含手续费与退款），否则在转账前报错
+        require_vault_liquidity(accounts.locked_vault(&maker_side).0, released)?;
+
+        // 基础代币转给买方，报价代币转给卖方，手续费转入手续费金库，价格改善的差额退还 maker 买单
+        let (buyer_base_account, seller_quote_account) = match side {
+            Side::Buy => (
+                accounts.taker_base_token_account.to_account_info(),
+                maker_accounts.quote_token_account.to_account_info(),
+            ),
+            Side::Sell => (
+                maker_accounts.owner_token_account.to_account_info(),
+                accounts.taker_quote_token_account.to_account_info(),
+            ),
+        };
+        for (vault, mint, to, amount) in [
+            (
+                accounts.base_vault,
+                accounts.base_mint,
+                buyer_base_account,
+                buyer_receives,
+            ),
+            (
+                accounts.base_vault,
+                accounts.base_mint,
+                accounts.base_fee_vault.to_account_info(),
+                buyer_fee,
+            ),
+            (
+                accounts.quote_vault,
+                accounts.quote_mint,
+                seller_quote_account,
+                seller_receives,
+            ),
+            (
+                accounts.quote_vault,
+                accounts.quote_mint,
+                accounts.quote_fee_vault.to_account_info(),
+                seller_fee,
+            ),
+            (
+                accounts.quote_vault,
+                accounts.quote_mint,
+                maker_accounts.quote_token_account.to_account_info(),
+                maker_quote_refund,
+            ),
+        ] {
+            transfer_from_vault(
+                token_program,
+                vault,
+                mint,
+                to,
+                orderbook.to_account_info(),
+                signer,
+                amount,
+            )?;
+        }
+
+        // maker 按成交额累计返佣，通过 claim_rebate 领取
+        accrue_rebate(
+            orderbook,
+            &mut maker_accounts.claimable,
+            total_quote_transfer,
+        )?;
+        record_volume(orderbook, trade_quantity, total_quote_transfer)?;
+
+        // 记录成交叶子，供后续批量提交为 Merkle 根
+        let sequence_number = record_fill(
+            orderbook,
+            taker_order.owner,
+            maker_order.owner,
+            trade_price,
+            trade_quantity,
+        )?;
+
+        // 触发交易事件
+        emit!(TradeEvent {
+            taker: taker_order.owner,
+            maker: maker_order.owner,
+            base_mint: base_mint_key,
+            quote_mint: quote_mint_key,
+            quantity: trade_quantity,
+            price: trade_price,
+            referrer,
+            sequence_number,
+            taker_order_id: taker_order.order_id,
+            maker_order_id: maker_order.order_id,
+        });
+
+        // 更新 taker 订单数量（maker 挂单已在释放锁定资金时更新）
+        taker_order.quantity = taker_order
+            .quantity
+            .checked_sub(trade_quantity)
+            .ok_or(DexError::CalculationError)?;
+
+        // 触发 maker 订单成交事件，区分全部成交与部分成交
+        emit!(OrderFilledEvent {
+            order_id: maker_order.order_id,
+            owner: maker_order.owner,
+            side: maker_side.clone(),
+            fill_quantity: trade_quantity,
+            remaining_quantity: maker_order.quantity,
+            full_fill: maker_order.quantity == 0,
+        });
+        summary.record(trade_price, trade_quantity)?;
+        match side {
+            Side::Buy => {
+                summary.quote_spent = summary
+                    .quote_spent
+                    .checked_add(total_quote_transfer)
+                    .ok_or(DexError::CalculationError)?
+            }
+            Side::Sell => {
+                summary.quote_received = summary
+                    .quote_received
+                    .checked_add(seller_receives)
+                    .ok_or(DexError::CalculationError)?
+            }
+        }
+
+        // maker 订单属于 OCO 组时，撤销同组其余挂单并将锁定资金退还给 maker
+        if maker_order.oco_group_id != 0 {
+            let (quote_refund, base_refund) = cancel_oco_group(
+                orderbook,
+                &mut skipped_orders,
+                &maker_side,
+                maker_order.owner,
+                maker_order.oco_group_id,
+            )?;
+            transfer_from_vault(
+                token_program,
+                accounts.quote_vault,
+                accounts.quote_mint,
+                maker_accounts.quote_token_account.to_account_info(),
+                orderbook.to_account_info(),
+                signer,
+                quote_refund,
+            )?;
+            transfer_from_vault(
+                token_program,
+                accounts.base_vault,
+                accounts.base_mint,
+                maker_accounts.owner_token_account.to_account_info(),
+                orderbook.to_account_info(),
+                signer,
+                base_refund,
+            )?;
+        }
+
+        // 部分成交的挂单放回头部，保持价格时间优先；补充了显示部分的冰山单重新排队
+        if refilled {
+            requeue_iceberg(orderbook, &maker_side, maker_order);
+        } else if maker_order.quantity > 0 {
+            side.opposing_book(orderbook).insert(0, maker_order);
+        }
+    }
+    // 被跳过的挂单按原顺序放回头部
+    side.opposing_book(orderbook).splice(0..0, skipped_orders);
+
+    if side.is_buy() {
+        // 按报价预算买入的订单：剩余预算按限价换算为挂单数量，不足一个单位的零头随下方多锁定部分退还；
+        // 不挂单时剩余预算全部退还
+        if quote_budget {
+            let remaining_budget = quote_locked
+                .checked_sub(summary.quote_spent)
+                .ok_or(DexError::CalculationError)?;
+            taker_order.quantity = if rests_remainder {
+                base_for_quote(remaining_budget, taker_order.price, price_scale)?
+            } else {
+                0
+            };
+            // 按预算买入的订单下单时没有确定的数量，以已成交与挂单数量之和作为下单数量
+            taker_order.original_quantity = summary
+                .filled_quantity
+                .checked_add(taker_order.quantity)
+                .ok_or(DexError::CalculationError)?;
+        }
+
+        // 退还多锁定的报价代币（以低于限价成交的差额，或市价单未用完的预算）：
+        // 多锁定部分 = 实际锁定总额 - 实际支付 - 剩余挂单按限价需继续锁定的部分
+        let quote_kept = if rests_remainder {
+            locked_quote_amount(taker_order.price, taker_order.quantity, price_scale)?
+        } else {
+            0 // 不挂单的订单无需继续锁定
+        };
+        let quote_surplus = quote_locked
+            .checked_sub(summary.quote_spent)
+            .and_then(|unspent| unspent.checked_sub(quote_kept))
+            .ok_or(DexError::CalculationError)?;
+        transfer_from_vault(
+            token_program,
+            accounts.quote_vault,
+            accounts.quote_mint,
+            accounts.taker_quote_token_account.to_account_info(),
+            orderbook.to_account_info(),
+            signer,
+            quote_surplus,
+        )?;
+    }
+    Ok(summary)
+}
+
+// 一次撮合的汇总结果
+#[derive(Default)]
+struct MatchSummary {
+    fills: usize,         // 成交笔数
+    filled_quantity: u64, // 累计成交数量
+    notional: u128,       // 累计 成交价 × 成交数量，用于计算成交均价
+    quote_spent: u64,     // taker 买单支付给 maker 的报价代币
+    quote_received: u64,  // taker 卖单收到的报价代币（已扣除 taker 手续费）
+}
+
+impl MatchSummary {
+    // 记录一笔成交
+    fn record(&mut self, trade_price: u64, trade_quantity: u64) -> Result<()> {
+        self.fills += 1;
+        self.filled_quantity = self
+            .filled_quantity
+            .checked_add(trade_quantity)
+            .ok_or(DexError::CalculationError)?;
+        self.notional += (trade_price as u128) * (trade_quantity as u128);
+        Ok(())
+    }
+
+    // 按成交数量加权的平均成交价（向下取整，没有成交时为 0）
+    fn average_price(&self) -> u64 {
+        match self.filled_quantity {
+            0 => 0,
+            filled => (self.notional / filled as u128) as u64,
+        }
+    }
+}
+
+// 撮合所需的代币账户：代币程序、mint、金库与手续费金库，以及接收成交和退款的 taker 代币账户
+struct MatchAccounts<'a, 'info> {
+    token_program: &'a Interface<'info, TokenInterface>,
+    base_mint: &'a InterfaceAccount<'info, Mint>,
+    quote_mint: &'a InterfaceAccount<'info, Mint>,
+    base_vault: &'a InterfaceAccount<'info, TokenAccount>,
+    quote_vault: &'a InterfaceAccount<'info, TokenAccount>,
+    base_fee_vault: &'a InterfaceAccount<'info, TokenAccount>,
+    quote_fee_vault: &'a InterfaceAccount<'info, TokenAccount>,
+    taker_base_token_account: &'a InterfaceAccount<'info, TokenAccount>,
+    taker_quote_token_account: &'a InterfaceAccount<'info, TokenAccount>,
+}
+
+impl<'a, 'info> MatchAccounts<'a, 'info> {
+    // 某一方向挂单锁定的代币对应的金库与 mint：买单锁定报价代币，卖单锁定基础代币
+    fn locked_vault(
+        &self,
+        side: &Side,
+    ) -> (
+        &'a InterfaceAccount<'info, TokenAccount>,
+        &'a InterfaceAccount<'info, Mint>,
+    ) {
+        match side {
+            Side::Buy => (self.quote_vault, self.quote_mint),
+            Side::Sell => (self.base_vault, self.base_mint),
+        }
+    }
+
+    // taker 接收某一方向挂单锁定代币的账户
+    fn taker_account(&self, side: &Side) -> AccountInfo<'info> {
+        match side {
+            Side::Buy => self.taker_quote_token_account.to_account_info(),
+            Side::Sell => self.taker_base_token_account.to_account_info(),
+        }
+    }
+}
+
+// 求集合竞价的统一清算价：以两侧可参与竞价（未冻结、未过期）挂单的价格为候选价，
+// 需求量为价格不低于候选价的买单数量之和，供给量为价格不高于候选价的卖单数量之和，
+// 选取 min(需求量, 供给量) 最大的候选价；成交量相同时选供需差较小者，仍相同时选较低价格。
+// 订单簿不交叉时返回 None，否则返回 (清算价, 可成交数量)
+fn auction_clearing_price(orderbook: &Orderbook, now: i64) -> Result<Option<(u64, u64)>> {
+    let eligible = |order: &&Order| !order.frozen && !order.is_expired(now);
+    let bids: Vec<&Order> = orderbook.bids.iter().filter(eligible).collect();
+    let asks: Vec<&Order> = orderbook.asks.iter().filter(eligible).collect();
+
+    // (成交量, 供需差, 价格)
+    let mut best: Option<(u128, u128, u64)> = None;
+    for price in bids.iter().chain(asks.iter()).map(|order| order.price) {
+        // 数量之和可能超出 u64，以 u128 累计
+        let demand: u128 = bids
+            .iter()
+            .filter(|order| order.price >= price)
+            .map(|order| order.quantity as u128)
+            .sum();
+        let supply: u128 = asks
+            .iter()
+            .filter(|order| order.price <= price)
+            .map(|order| order.quantity as u128)
+            .sum();
+        let volume = demand.min(supply);
+        if volume == 0 {
+            continue;
+        }
+        let imbalance = demand.abs_diff(supply);
+        let better = match best {
+            None => true,
+            Some((best_volume, best_imbalance, best_price)) => {
+                volume > best_volume
+                    || (volume == best_volume
+                        && (imbalance < best_imbalance
+                            || (imbalance == best_imbalance && price < best_price)))
+            }
+        };
+        if better {
+            best = Some((volume, imbalance, price));
+        }
+    }
+    // 单次竞价的成交数量不会超过单侧数量之和，超出 u64 时按上限处理
+    Ok(best.map(|(volume, _, price)| (price, volume.min(u64::MAX as u128) as u64)))
+}
+
+// 从订单列表头部取出下一个可参与竞价的挂单：已冻结、已过期或数量为 0 的挂单暂存到 skipped，
+// 竞价结束后放回（数量为 0 的挂单在连续撮合时移除并退还残留的锁定资金）
+fn next_auction_order(
+    orders: &mut Vec<Order>,
+    skipped: &mut Vec<Order>,
+    now: i64,
+) -> Option<Order> {
+    while !orders.is_empty() {
+        let order = orders.remove(0);
+        if order.quantity == 0 || order.frozen || order.is_expired(now) {
+            skipped.push(order);
+        } else {
+            return Some(order);
+        }
+    }
+    None
+}
+
+// 只读遍历对手盘的估算结果
+struct FillEstimate {
+    quantity: u64,             // 可成交的数量
+    makers: usize,             // 需要传入代币账户的 maker 次数（每次成交或移除过期、空挂单各一次）
+    quote: u64,                // 成交的报价代币总额
+    fills: Vec<SimulatedFill>, // 逐笔成交的价格与数量
+}
+
+// 按撮合规则只读遍历对手盘，估算订单当前可成交的数量以及撮合时需要的 maker 账户数（不修改订单簿）
+#[allow(clippy::too_many_arguments)]
+fn estimate_fills(
+    orderbook: &Orderbook,
+    side: &Side,
+    taker_order: &Order,
+    order_type: OrderType,
+    max_quote: u64,
+    quote_budget: bool,
+    self_trade_prevention: SelfTradePrevention,
+    worst_price: u64,
+    now: i64,
+) -> Result<FillEstimate> {
+    let price = taker_order.price;
+    let quantity = taker_order.quantity;
+    let price_scale = orderbook.price_scale();
+    let opposing = match side {
+        Side::Buy => &orderbook.asks,
+        Side::Sell => &orderbook.bids,
+    };
+    let mut remaining = quantity;
+    let mut filled: u64 = 0;
+    let mut makers: usize = 0;
+    let mut quote_spent: u64 = 0;
+    let mut fills: Vec<SimulatedFill> = Vec::new();
+    // 与撮合循环一致，从最佳价格（列表头部）开始遍历
+    for maker_order in opposing.iter() {
+        if remaining == 0 {
+            break;
+        }
+        // 限价单遇到不再交叉的价格即停止
+        if order_type == OrderType::Limit && !side.crosses(price, maker_order.price) {
+            break;
+        }
+        // 超出滑点保护价格的挂单不会成交
+        if worst_price != 0 && !side.crosses(worst_price, maker_order.price) {
+            break;
+        }
+        // 已过期或数量为 0 的挂单在撮合时移除并退款给 maker，需要其代币账户
+        if maker_order.is_expired(now) || maker_order.quantity == 0 {
+            makers += 1;
+            continue;
+        }
+        // 被冻结的挂单不参与撮合
+        if maker_order.frozen {
+            continue;
+        }
+        // 自己的挂单不会成交：撤销挂单模式下跳过；递减撤销模式下 taker 数量被同步消耗
+        if maker_order.owner == taker_order.owner {
+            if self_trade_prevention == SelfTradePrevention::DecrementAndCancel {
+                remaining = remaining
+                    .checked_sub(remaining.min(maker_order.quantity))
+                    .ok_or(DexError::CalculationError)?;
+            }
+            continue;
+        }
+        let trade_price = match order_type {
+            OrderType::Limit | OrderType::StopLimit { .. } => orderbook
+                .execution_price_mode
+                .execution_price(maker_order.price, price),
+            OrderType::Market => maker_order.price,
+        };
+        // 冰山单的隐藏部分在同一价格补充后仍会成交，因此按全部剩余数量计算
+        let mut trade_quantity = remaining.min(maker_order.quantity);
+        // 市价买单（以及按报价预算买入的订单）受报价预算限制
+        if side.is_buy() && (order_type == OrderType::Market || quote_budget) {
+            let budget = max_quote
+                .checked_sub(quote_spent)
+                .ok_or(DexError::CalculationError)?;
+            let affordable_quantity = base_for_quote(budget, trade_price, price_scale)?;
+            trade_quantity = trade_quantity.min(affordable_quantity);
+            if trade_quantity == 0 {
+                break;
+            }
+        }
+        let trade_quote = quote_amount(trade_price, trade_quantity, price_scale)?;
+        // 单笔成交额过小（含向下取整为 0）的挂单会被跳过
+        if trade_quote < orderbook.min_fill_notional.max(1) {
+            continue;
+        }
+        quote_spent = quote_spent
+            .checked_add(trade_quote)
+            .ok_or(DexError::CalculationError)?;
+        remaining = remaining
+            .checked_sub(trade_quantity)
+            .ok_or(DexError::CalculationError)?;
+        filled += trade_quantity;
+        fills.push(SimulatedFill {
+            price: trade_price,
+            quantity: trade_quantity,
+        });
+        // 冰山单每个显示部分单独成交一次，超出当前显示部分的数量按 display_size 分批计数
+        makers += match trade_quantity.checked_sub(maker_order.matchable_quantity()) {
+            Some(hidden) if hidden > 0 && maker_order.display_size > 0 => {
+                1 + hidden.div_ceil(maker_order.display_size) as usize
+            }
+            _ => 1,
+        };
+    }
+    Ok(FillEstimate {
+        quantity: filled,
+        makers,
+        quote: quote_spent,
+        fills,
+    })
+}
+
+// 校验拥有者还能再挂一个单：买卖两侧的挂单数量按需扫描统计，撤单和全部成交后自然减少，
+// 无需单独维护计数。止损单触发后无法挂单的剩余部分由 trigger_stops 撤销并记入待领取余额
+fn require_owner_capacity(orderbook: &Orderbook, owner: Pubkey) -> Result<()> {
+    if orderbook.max_orders_per_owner == 0 {
+        return Ok(()); // 不限制
+    }
+    let open_orders = orderbook
+        .bids
+        .iter()
+        .chain(orderbook.asks.iter())
+        .filter(|order| order.owner == owner)
+        .count();
+    require!(
+        open_orders < orderbook.max_orders_per_owner as usize,
+        DexError::TooManyOpenOrders
+    );
+    Ok(())
+}
+
+// 将订单挂到订单簿对应方向（保持价格时间优先）并记录其锁定资金，触发挂单事件
+fn rest_order(orderbook: &mut Orderbook, side: &Side, order: Order) -> Result<()> {
+    // 挂单价格沿用下单时已按最小变动单位校验的限价，撮合只会改变数量（按预算换算的数量向下取整，
+    // 零头随多锁定的报价代币退还）；防御性检查，保证程序内部产生的挂单同样满足价格精度
+    require!(
+        order.price.checked_rem(orderbook.price_tick) == Some(0),
+        DexError::InvalidTickSize
+    );
+    // 撮合后保留的锁定资金恰好为剩余数量按限价计算的部分，记录到挂单上
+    let mut order = order.with_locked_funds(side, orderbook.price_scale())?;
+    // 冰山单挂单时显示第一部分
+    order.update_display(0);
+    let orders = match side {
+        Side::Buy => &mut orderbook.bids,
+        Side::Sell => &mut orderbook.asks,
+    };
+    // 账户空间只能容纳 max_orders 个同方向挂单，已满时拒绝挂单
+    require!(
+        orders.len() < orderbook.max_orders as usize,
+        DexError::BookFull
+    );
+    insert_sorted(orders, side, order);
+
+    // 触发挂单事件
+    emit!(OrderPlacedEvent {
+        order_id: order.order_id,
+        owner: order.owner,
+        side: side.clone(),
+        price: order.price,
+        quantity: order.quantity,
+        original_quantity: order.original_quantity,
+    });
+    Ok(())
+}
+
+// 二分查找插入位置，保持买单按价格降序、卖单按价格升序；同价订单排在已有订单之后（时间优先）
+fn insert_sorted(orders: &mut Vec<Order>, side: &Side, order: Order) {
+    let index = match side {
+        Side::Buy => orders.partition_point(|resting| resting.price >= order.price),
+        Side::Sell => orders.partition_point(|resting| resting.price <= order.price),
+    };
+    orders.insert(index, order);
+}
+
+// 冰山单补充显示部分后失去时间优先：分配新的订单 ID，排到同价挂单之后，并触发挂单事件
+fn requeue_iceberg(orderbook: &mut Orderbook, side: &Side, mut order: Order) {
+    orderbook.order_id_counter += 1; // 增加订单 ID
+    order.order_id = orderbook.order_id_counter;
+    let orders = match side {
+        Side::Buy => &mut orderbook.bids,
+        Side::Sell => &mut orderbook.asks,
+    };
+    insert_sorted(orders, side, order);
+
+    // 触发挂单事件
+    emit!(OrderPlacedEvent {
+        order_id: order.order_id,
+        owner: order.owner,
+        side: side.clone(),
+        price: order.price,
+        quantity: order.quantity,
+        original_quantity: order.original_quantity,
+    });
+}
+
+// 按基点计算 amount × bps / 10000，按指定方式舍入。手续费使用订单簿配置的舍入方式：
+// bps 不超过 10000，任何舍入方式下手续费都不超过被扣除的金额，金库收支始终平衡；
+// 从手续费金库支付的返佣固定向下取整，保证累计返佣不超过已收取的手续费
+fn apply_bps(amount: u128, bps: u16, mode: RoundingMode) -> Result<u64> {
+    let scaled = amount
+        .checked_mul(bps as u128)
+        .ok_or(DexError::CalculationError)?;
+    let result = mode.divide(scaled, BPS_DENOMINATOR as u128)?;
+    u64::try_from(result).map_err(|_| DexError::CalculationError.into())
+}
+
+// 计算价格 × 数量 / price_scale 对应的报价代币数量（成交支付与释放锁定资金），向下取整，
+// 舍入误差（每笔不足一个报价代币最小单位）由收款方承担；中间结果以 u128 计算，
+// 只有结果超出 u64 范围时才报错
+fn quote_amount(price: u64, quantity: u64, price_scale: u64) -> Result<u64> {
+    let amount =
+        RoundingMode::Floor.divide((price as u128) * (quantity as u128), price_scale as u128)?;
+    u64::try_from(amount).map_err(|_| DexError::CalculationError.into())
+}
+
+// 计算挂单按限价需锁定的报价代币，向上取整，保证锁定资金足以覆盖各笔向下取整的支付
+fn locked_quote_amount(price: u64, quantity: u64, price_scale: u64) -> Result<u64> {
+    let amount =
+        RoundingMode::Ceil.divide((price as u128) * (quantity as u128), price_scale as u128)?;
+    u64::try_from(amount).map_err(|_| DexError::CalculationError.into())
+}
+
+// 计算给定报价代币按价格可买入的基础代币数量（向下取整）
+fn base_for_quote(quote: u64, price: u64, price_scale: u64) -> Result<u64> {
+    let scaled = (quote as u128)
+        .checked_mul(price_scale as u128)
+        .ok_or(DexError::CalculationError)?;
+    let quantity = RoundingMode::Floor.divide(scaled, price as u128)?;
+    Ok(u64::try_from(quantity).unwrap_or(u64::MAX))
+}
+
+// 计算买单需锁定的报价代币总量：限价单（含止损限价单）为价格 × 数量，市价单为报价上限
+fn quote_to_lock(
+    order_type: OrderType,
+    price: u64,
+    quantity: u64,
+    max_quote: u64,
+    price_scale: u64,
+) -> Result<u64> {
+    match order_type {
+        OrderType::Limit | OrderType::StopLimit { .. } => {
+            locked_quote_amount(price, quantity, price_scale)
+        }
+        OrderType::Market => Ok(max_quote),
+    }
+}
+
+// 以订单簿 PDA 签名，从金库转出代币（数量为 0 时跳过）
+fn transfer_from_vault<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    vault: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    to: AccountInfo<'info>,
+    orderbook: AccountInfo<'info>,
+    signer: &[&[&[u8]]],
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            TransferChecked {
+                from: vault.to_account_info(),
+                mint: mint.to_account_info(),
+                to,
+                authority: orderbook,
+            },
+            signer,
+        ),
+        amount,
+        mint.decimals,
+    )
+}
+
+// 检查金库余额足以转出指定数量，不足时返回 InsufficientVaultLiquidity：
+// 直接读取账户数据以包含本指令中已发生的转账，比代币程序 CPI 内部的失败更早、更清晰地暴露记账错误
+fn require_vault_liquidity(vault: &InterfaceAccount<TokenAccount>, amount: u64) -> Result<()> {
+    let balance = anchor_spl::token::accessor::amount(&vault.to_account_info())?;
+    require!(balance >= amount, DexError::InsufficientVaultLiquidity);
+    Ok(())
+}
+
+// 从用户账户向金库转入代币，返回金库实际收到的数量：
+// 转账后重新读取金库余额，兼容带转账手续费的 Token-2022 代币（到账数量可能少于转出数量）
+fn transfer_to_vault<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    from: &InterfaceAccount<'info, TokenAccount>,
+    vault: &mut InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    authority: AccountInfo<'info>,
+    amount: u64,
+) -> Result<u64> {
+    let balance_before = vault.amount;
+    token_interface::transfer_checked(
+        CpiContext::new(
+            token_program.to_account_info(),
+            TransferChecked {
+                from: from.to_account_info(),
+                mint: mint.to_account_info(),
+                to: vault.to_account_info(),
+                authority,
+            },
+        ),
+        amount,
+        mint.decimals,
+    )?;
+    vault.reload()?;
+    vault
+        .amount
+        .checked_sub(balance_before)
+        .ok_or_else(|| DexError::CalculationError.into())
+}
+
+// 原生 SOL 一方：把 owner 的 lamports 转入其临时 wSOL 账户并同步余额，随后按普通代币存入金库
+fn wrap_native<'info>(
+    system_program: &Program<'info, System>,
+    token_program: &Interface<'info, TokenInterface>,
+    owner: &Signer<'info>,
+    wsol_account: &InterfaceAccount<'info, TokenAccount>,
+    amount: u64,
+) -> Result<()> {
+    system_program::transfer(
+        CpiContext::new(
+            system_program.to_account_info(),
+            system_program::Transfer {
+                from: owner.to_account_info(),
+                to: wsol_account.to_account_info(),
+            },
+        ),
+        amount,
+    )?;
+    token_interface::sync_native(CpiContext::new(
+        token_program.to_account_info(),
+        SyncNative {
+            account: wsol_account.to_account_info(),
+        },
+    ))
+}
+
+// 关闭 owner 在原生 SOL 一方的临时 wSOL 账户，其中全部余额（存入后剩余的部分、成交所得与退款）
+// 连同租金以 SOL 返还 owner；市场没有原生 SOL 一方时不做任何事
+fn unwrap_native<'info>(
+    native_leg: NativeLeg,
+    token_program: &Interface<'info, TokenInterface>,
+    owner: &Signer<'info>,
+    owner_base_token_account: &InterfaceAccount<'info, TokenAccount>,
+    owner_quote_token_account: &InterfaceAccount<'info, TokenAccount>,
+) -> Result<()> {
+    let wsol_account = match native_leg {
+        NativeLeg::None => return Ok(()),
+        NativeLeg::Base => owner_base_token_account,
+        NativeLeg::Quote => owner_quote_token_account,
+    };
+    token_interface::close_account(CpiContext::new(
+        token_program.to_account_info(),
+        CloseAccount {
+            account: wsol_account.to_account_info(),
+            destination: owner.to_account_info(),
+            authority: owner.to_account_info(),
+        },
+    ))
+}
+
+// 原生 SOL 市场上会挂单的订单必须传入下单者的托管 wSOL 账户：挂单在原生 SOL 一方的成交所得与退款
+// 由撮合转入该账户（authority 为下单者的待领取余额账户，只能通过 claim_native 以 SOL 领取），
+// 而不是下单时使用、指令结束即被关闭的临时 wSOL 账户
+fn require_native_escrow(
+    orderbook: &Orderbook,
+    native_escrow: Option<&InterfaceAccount<TokenAccount>>,
+    claimable: Pubkey,
+) -> Result<()> {
+    let native_mint = match orderbook.native_leg {
+        NativeLeg::None => return Ok(()),
+        NativeLeg::Base => orderbook.base_mint,
+        NativeLeg::Quote => orderbook.quote_mint,
+    };
+    let native_escrow = native_escrow.ok_or(DexError::InvalidNativeEscrow)?;
+    require_keys_eq!(
+        native_escrow.owner,
+        claimable,
+        DexError::InvalidNativeEscrow
+    );
+    require_keys_eq!(
+        native_escrow.mint,
+        native_mint,
+        DexError::InvalidNativeEscrow
+    );
+    Ok(())
+}
+
+// 判断是否为 wSOL（SPL Token 或 Token-2022 的原生代币）
+fn is_native_mint(mint: &Pubkey) -> bool {
+    *mint == anchor_spl::token::spl_token::native_mint::ID
+        || *mint == token_interface::spl_token_2022::native_mint::ID
+}
+
+// 计算一组挂单锁定的资金总量：买单为锁定的报价代币，卖单为锁定的基础代币（按订单记录的实际锁定数量）
+fn total_locked(orders: &[Order], side: &Side) -> Result<u64> {
+    orders.iter().try_fold(0u64, |total, order| {
+        let locked = match side {
+            Side::Buy => order.locked_quote,
+            Side::Sell => order.locked_base,
+        };
+        total
+            .checked_add(locked)
+            .ok_or_else(|| DexError::CalculationError.into())
+    })
+}
+
+// 计算金库应保留的资金：基础代币为卖单与卖出止损单锁定的基础代币，
+// 报价代币为买单与买入止损单锁定的报价代币，另加已撤销挂单尚未领取的资金
+fn vault_obligations(orderbook: &Orderbook) -> Result<(u64, u64)> {
+    let price_scale = orderbook.price_scale();
+    // 止损单未挂到订单簿上，按触发后挂单的方式计算其锁定资金（买入止损只锁定报价代币，卖出止损只锁定基础代币）
+    let stops = orderbook
+        .stops
+        .iter()
+        .map(|stop| stop.to_order(price_scale))
+        .collect::<Result<Vec<Order>>>()?;
+    let base_owed = total_locked(&orderbook.asks, &Side::Sell)?
+        .checked_add(total_locked(&stops, &Side::Sell)?)
+        .and_then(|owed| owed.checked_add(orderbook.claimable_base))
+        .ok_or(DexError::CalculationError)?;
+    let quote_owed = total_locked(&orderbook.bids, &Side::Buy)?
+        .checked_add(total_locked(&stops, &Side::Buy)?)
+        .and_then(|owed| owed.checked_add(orderbook.claimable_quote))
+        .ok_or(DexError::CalculationError)?;
+    Ok((base_owed, quote_owed))
+}
+
+// 从订单列表中移除满足条件的挂单并按原顺序返回，其余挂单保持原有顺序
+fn drain_orders(orders: &mut Vec<Order>, predicate: impl Fn(&Order) -> bool) -> Vec<Order> {
+    let mut removed = Vec::new();
+    orders.retain(|order| {
+        let matched = predicate(order);
+        if matched {
+            removed.push(*order);
+        }
+        !matched
+    });
+    removed
+}
+
+// 从订单列表中移除指定拥有者在某 OCO 组中的挂单并返回
+fn drain_oco_group(orders: &mut Vec<Order>, owner: Pubkey, oco_group_id: u64) -> Vec<Order> {
+    drain_orders(orders, |order| {
+        order.owner == owner && order.oco_group_id == oco_group_id
+    })
+}
+
+// 撤销指定拥有者在某 OCO 组中的全部挂单（包括撮合中暂存的被跳过挂单），
+// 返回应退还的报价代币（买单）与基础代币（卖单）数量
+fn cancel_oco_group(
+    orderbook: &mut Orderbook,
+    skipped_orders: &mut Vec<Order>,
+    skipped_side: &Side,
+    owner: Pubkey,
+    oco_group_id: u64,
+) -> Result<(u64, u64)> {
+    let mut cancelled_bids = drain_oco_group(&mut orderbook.bids, owner, oco_group_id);
+    let mut cancelled_asks = drain_oco_group(&mut orderbook.asks, owner, oco_group_id);
+    let cancelled_skipped = drain_oco_group(skipped_orders, owner, oco_group_id);
+    match skipped_side {
+        Side::Buy => cancelled_bids.extend(cancelled_skipped),
+        Side::Sell => cancelled_asks.extend(cancelled_skipped),
+    }
+    Ok((
+        total_locked(&cancelled_bids, &Side::Buy)?,
+        total_locked(&cancelled_asks, &Side::Sell)?,
+    ))
+}
+
+// 首次下单时记录待领取余额账户的归属（init_if_needed 新建的账户各字段均为 0）
+fn init_claimable(accounts: &mut PlaceOrder, bump: u8) {
+    let claimable = &mut accounts.claimable;
+    if claimable.owner == Pubkey::default() {
+        claimable.orderbook = accounts.orderbook.key();
+        claimable.owner = accounts.owner.key();
+        claimable.bump = bump;
+    }
+}
+
+// 根据推荐码查找推荐人，推荐码必须与传入的推荐码账户一致
+fn resolve_referrer(
+    code: Option<&str>,
+    referral: Option<&Account<ReferralCode>>,
+) -> Result<Option<Pubkey>> {
+    let Some(code) = code else {
+        return Ok(None); // 未使用推荐码
+    };
+    let referral = referral.ok_or(DexError::ReferralCodeNotFound)?;
+    // 推荐码账户只能由 register_referral 在代码派生的 PDA 上创建，比对代码即可确认对应关系
+    require!(referral.code == code, DexError::ReferralCodeMismatch);
+    Ok(Some(referral.referrer))
+}
+
+// 从 remaining_accounts 依次读取订单结算方的基础/报价代币账户及订单拥有者的待领取余额账户，
+// 并校验代币账户的 mint 与拥有者、待领取余额账户所属的订单簿与拥有者
+fn get_next_maker_accounts<'info>(
+    iter: &mut Peekable<Iter<'info, AccountInfo<'info>>>,
+    orderbook: &Account<'info, Orderbook>,
+    owner: Pubkey,
+    order_owner: Pubkey,
+) -> Result<MakerSlot<'info>> {
+    let owner_token_account_info = next_account_info(iter)?; // 获取下一个账户
+    let quote_token_account_info = next_account_info(iter)?; // 获取下一个账户
+    let claimable_info = next_account_info(iter)?; // 获取下一个账户
+
+    // 待领取余额账户只能由 place_order 在 ["claimable", 订单簿, 拥有者] 派生的 PDA 上创建，
+    // 比对其记录的订单簿与拥有者即可确认对应关系
+    let claimable = Account::<'info, ClaimableBalance>::try_from(claimable_info)
+        .map_err(|_| DexError::InvalidMakerAccount)?;
+    require_keys_eq!(
+        claimable.orderbook,
+        orderbook.key(),
+        DexError::MakerAccountMismatch
+    );
+    require_keys_eq!(claimable.owner, order_owner, DexError::MakerAccountMismatch);
+
+    // 两个账户都必须属于订单的结算方（未指定时为订单拥有者），成交所得和退款只会转给结算方；
+    // 原生 SOL 一方传入订单拥有者的托管 wSOL 账户（authority 为其待领取余额账户），由 claim_native 以 SOL 领取
+    let (base_authority, quote_authority) = match orderbook.native_leg {
+        NativeLeg::None => (owner, owner),
+        NativeLeg::Base => (claimable.key(), owner),
+        NativeLeg::Quote => (owner, claimable.key()),
+    };
+
+    // 结算方已关闭自己的关联代币账户时挂单失效，由调用方撤销并把锁定资金记入待领取余额账户；
+    // 托管 wSOL 账户无法被关闭，不会失效
+    if (base_authority == owner
+        && is_closed_ata(owner_token_account_info, &owner, &orderbook.base_mint))
+        || (quote_authority == owner
+            && is_closed_ata(quote_token_account_info, &owner, &orderbook.quote_mint))
+    {
+        return Ok(MakerSlot::Closed(claimable));
+    }
+
+    // 手动反序列化为 TokenAccount；其余任何无法反序列化的账户一律报错，
+    // 防止 taker 传入伪造的账户跳过挂单
+    let owner_token_account =
+        InterfaceAccount::<'info, TokenAccount>::try_from(owner_token_account_info)
+            .map_err(|_| DexError::InvalidMakerAccount)?;
+    let quote_token_account =
+        InterfaceAccount::<'info, TokenAccount>::try_from(quote_token_account_info)
+            .map_err(|_| DexError::InvalidMakerAccount)?;
+
+    // 按位置校验代币账户的 mint：第一个必须是基础代币账户，第二个必须是报价代币账户，
+    // 两个账户顺序颠倒时同样报错，防止资金转入错误的账户
+    require_keys_eq!(
+        owner_token_account.mint,
+        orderbook.base_mint,
+        DexError::MakerMintMismatch
+    );
+    require_keys_eq!(
+        quote_token_account.mint,
+        orderbook.quote_mint,
+        DexError::MakerMintMismatch
+    );
+    require_keys_eq!(
+        owner_token_account.owner,
+        base_authority,
+        DexError::MakerAccountMismatch
+    );
+    require_keys_eq!(
+        quote_token_account.owner,
+        quote_authority,
+        DexError::MakerAccountMismatch
+    );
+
+    // 返回 maker 账户结构体
+    Ok(MakerSlot::Open(Box::new(MakerAccounts {
+        owner_token_account,
+        quote_token_account,
+        claimable,
+    })))
+}
+
+// 判断账户是否为 authority 在该 mint 下已被关闭的关联代币账户（SPL Token 或 Token-2022）：
+// 账户已归还系统程序，lamports 与数据均为空。只认可关联代币账户地址，
+// 防止 taker 传入任意不存在的地址冒充已关闭的账户来撤销他人的挂单
+fn is_closed_ata(info: &AccountInfo, authority: &Pubkey, mint: &Pubkey) -> bool {
+    let closed = info.owner == &system_program::ID && info.lamports() == 0 && info.data_is_empty();
+    closed
+        && [anchor_spl::token::ID, anchor_spl::token_2022::ID]
+            .iter()
+            .any(|token_program| {
+                get_associated_token_address_with_program_id(authority, mint, token_program)
+                    == info.key()
+            })
+}
+
+// 结算方的代币账户已被关闭、或已触发的止损单无法执行时撤销该订单（调用方已将其从订单簿或
+// 止损单列表取出）：锁定资金留在金库并记入拥有者的待领取余额账户（通过 claim_balance 领取），
+// 触发撤单事件，避免一个失效的订单阻塞整个订单簿的撮合
+fn cancel_stale_order(
+    orderbook: &mut Orderbook,
+    claimable: &mut Account<ClaimableBalance>,
+    side: Side,
+    order: &Order,
+) -> Result<()> {
+    // 同一拥有者的待领取余额账户可能已在本指令中通过另一份副本更新，修改前重新读取
+    claimable.reload()?;
+    let amount = total_locked(&[*order], &side)?;
+    let (balance, total) = match side {
+        Side::Buy => (&mut claimable.quote, &mut orderbook.claimable_quote),
+        Side::Sell => (&mut claimable.base, &mut orderbook.claimable_base),
+    };
+    *balance = balance
+        .checked_add(amount)
+        .ok_or(DexError::CalculationError)?;
+    *total = total
+        .checked_add(amount)
+        .ok_or(DexError::CalculationError)?;
+    claimable.exit(&crate::ID)?;
+
+    emit!(OrderCanceledEvent {
+        order_id: order.order_id,
+        owner: order.owner,
+        refund_mint: orderbook.locked_mint(&side),
+        side,
+        quantity: order.quantity,
+        refund_amount: amount,
+    });
+    Ok(())
+}
+
+// 从 remaining_accounts 读取的一组挂单结算账户
+enum MakerSlot<'info> {
+    Open(Box<MakerAccounts<'info>>),          // 代币账户均有效
+    Closed(Account<'info, ClaimableBalance>), // 结算方的代币账户已被关闭，只剩拥有者的待领取余额账户
+}
+
+// 定义 maker 账户结构体，包含基础和报价代币账户以及订单拥有者的待领取余额账户
+struct MakerAccounts<'info> {
+    owner_token_account: InterfaceAccount<'info, TokenAccount>,
+    quote_token_account: InterfaceAccount<'info, TokenAccount>,
+    claimable: Account<'info, ClaimableBalance>,
+}
+
+impl<'info> MakerAccounts<'info> {
+    // maker 接收某一方向挂单锁定代币（退款）的账户
+    fn locked_account(&self, side: &Side) -> AccountInfo<'info> {
+        match side {
+            Side::Buy => self.quote_token_account.to_account_info(),
+            Side::Sell => self.owner_token_account.to_account_info(),
+        }
+    }
+}
+
+// 定义初始化指令的账户结构体
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = Orderbook::space(INITIAL_MAX_ORDERS), // 分配空间
+        seeds = [b"orderbook".as_ref(), base_mint.key().as_ref(), quote_mint.key().as_ref()],
+        bump
+    )]
+    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
+    pub base_mint: InterfaceAccount<'info, Mint>,  // 基础代币
+    pub quote_mint: InterfaceAccount<'info, Mint>, // 报价代币
+    #[account(
+        init,
+        payer = payer,
+        token::mint = base_mint,
+        token::authority = orderbook,
+        token::token_program = token_program,
+        seeds = [b"base_vault".as_ref(), orderbook.key().as_ref()],
+        bump
+    )]
+    pub base_vault: InterfaceAccount<'info, TokenAccount>, // 基础代币金库
+    #[account(
+        init,
+        payer = payer,
+        token::mint = quote_mint,
+        token::authority = orderbook,
+        token::token_program = token_program,
         seeds = [b"quote_vault".as_ref(), orderbook.key().as_ref()],
         bump
     )]
-    pub quote_vault: Account<'info, TokenAccount>, // 报价代币金库
-    pub token_program: Program<'info, Token>, // 代币程序  就是告诉 Anchor：我要去找“官方 SPL Token 程序”，帮我干转账、铸币这些事。
+    pub quote_vault: InterfaceAccount<'info, TokenAccount>, // 报价代币金库
+    #[account(
+        init,
+        payer = payer,
+        token::mint = base_mint,
+        token::authority = orderbook,
+        token::token_program = token_program,
+        seeds = [b"base_fee_vault".as_ref(), orderbook.key().as_ref()],
+        bump
+    )]
+    pub base_fee_vault: InterfaceAccount<'info, TokenAccount>, // 基础代币手续费金库
+    #[account(
+        init,
+        payer = payer,
+        token::mint = quote_mint,
+        token::authority = orderbook,
+        token::token_program = token_program,
+        seeds = [b"quote_fee_vault".as_ref(), orderbook.key().as_ref()],
+        bump
+    )]
+    pub quote_fee_vault: InterfaceAccount<'info, TokenAccount>, // 报价代币手续费金库
+    #[account(mut)]
+    pub payer: Signer<'info>, // 支付者
+
+    pub system_program: Program<'info, System>, // 系统程序
+    pub token_program: Interface<'info, TokenInterface>, // 代币程序
+    //链上内置的“租金数据”，主要用于创建新账户时 → 计算租金豁免额度 → 防止新建账户被回收。
+    pub rent: Sysvar<'info, Rent>,        // 租金系统变量
+}
+
+// 定义使用 ATA 金库初始化指令的账户结构体，金库须预先创建，只校验而不初始化
+#[derive(Accounts)]
+pub struct InitializeWithVaults<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = Orderbook::space(INITIAL_MAX_ORDERS), // 分配空间
+        seeds = [b"orderbook".as_ref(), base_mint.key().as_ref(), quote_mint.key().as_ref()],
+        bump
+    )]
+    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
+    pub base_mint: InterfaceAccount<'info, Mint>,  // 基础代币
+    pub quote_mint: InterfaceAccount<'info, Mint>, // 报价代币
+    #[account(
+        associated_token::mint = base_mint,
+        associated_token::authority = orderbook,
+        associated_token::token_program = token_program,
+    )]
+    pub base_vault: InterfaceAccount<'info, TokenAccount>, // 基础代币金库（订单簿的 ATA）
+    #[account(
+        associated_token::mint = quote_mint,
+        associated_token::authority = orderbook,
+        associated_token::token_program = token_program,
+    )]
+    pub quote_vault: InterfaceAccount<'info, TokenAccount>, // 报价代币金库（订单簿的 ATA）
+    #[account(
+        init,
+        payer = payer,
+        token::mint = base_mint,
+        token::authority = orderbook,
+        token::token_program = token_program,
+        seeds = [b"base_fee_vault".as_ref(), orderbook.key().as_ref()],
+        bump
+    )]
+    pub base_fee_vault: InterfaceAccount<'info, TokenAccount>, // 基础代币手续费金库
+    #[account(
+        init,
+        payer = payer,
+        token::mint = quote_mint,
+        token::authority = orderbook,
+        token::token_program = token_program,
+        seeds = [b"quote_fee_vault".as_ref(), orderbook.key().as_ref()],
+        bump
+    )]
+    pub quote_fee_vault: InterfaceAccount<'info, TokenAccount>, // 报价代币手续费金库
+    #[account(mut)]
+    pub payer: Signer<'info>, // 支付者
+
+    pub system_program: Program<'info, System>, // 系统程序
+    pub token_program: Interface<'info, TokenInterface>, // 代币程序
+    //链上内置的“租金数据”，主要用于创建新账户时 → 计算租金豁免额度 → 防止新建账户被回收。
+    pub rent: Sysvar<'info, Rent>,        // 租金系统变量
+}
+
+// 定义下单指令的账户结构体
+#[derive(Accounts)]
+pub struct PlaceOrder<'info> {
+    #[account(
+        mut,
+        seeds = [b"orderbook".as_ref(), orderbook.base_mint.as_ref(), orderbook.quote_mint.as_ref()],
+        bump,
+        has_one = base_mint,
+        has_one = quote_mint,
+    )]
+    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
+    pub base_mint: InterfaceAccount<'info, Mint>,  // 基础代币
+    pub quote_mint: InterfaceAccount<'info, Mint>, // 报价代币
+    #[account(mut)]
+    pub owner: Signer<'info>, // 订单拥有者
+    #[account(
+        mut,
+        constraint = owner_base_token_account.mint == orderbook.base_mint,
+        constraint = owner_base_token_account.owner == owner.key()
+    )]
+    pub owner_base_token_account: InterfaceAccount<'info, TokenAccount>, // 用户基础代币账户
+    #[account(
+        mut,
+        constraint = owner_quote_token_account.mint == orderbook.quote_mint,
+        constraint = owner_quote_token_account.owner == owner.key()
+    )]
+    pub owner_quote_token_account: InterfaceAccount<'info, TokenAccount>, // 用户报价代币账户
+    #[account(
+        mut,
+        address = orderbook.base_vault,
+    )]
+    pub base_vault: InterfaceAccount<'info, TokenAccount>, // 基础代币金库
+    #[account(
+        mut,
+        address = orderbook.quote_vault,
+    )]
+    pub quote_vault: InterfaceAccount<'info, TokenAccount>, // 报价代币金库
+    #[account(
+        mut,
+        seeds = [b"base_fee_vault".as_ref(), orderbook.key().as_ref()],
+        bump
+    )]
+    pub base_fee_vault: InterfaceAccount<'info, TokenAccount>, // 基础代币手续费金库
+    #[account(
+        mut,
+        seeds = [b"quote_fee_vault".as_ref(), orderbook.key().as_ref()],
+        bump
+    )]
+    pub quote_fee_vault: InterfaceAccount<'info, TokenAccount>, // 报价代币手续费金库
+    pub referral: Option<Account<'info, ReferralCode>>, // 可选推荐码账户，使用推荐码下单时传入
+    /// CHECK: 预言机价格账户，在 require_within_oracle_band 中校验地址、所属程序与数据
+    pub oracle: Option<UncheckedAccount<'info>>, // 可选预言机账户，订单簿配置了预言机时必须传入
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + ClaimableBalance::SIZE,
+        seeds = [b"claimable".as_ref(), orderbook.key().as_ref(), owner.key().as_ref()],
+        bump
+    )]
+    pub claimable: Account<'info, ClaimableBalance>, // 下单者的待领取余额账户，首次下单时创建（租金由下单者支付）
+    pub native_escrow: Option<InterfaceAccount<'info, TokenAccount>>, // 下单者的托管 wSOL 账户，原生 SOL 市场上会挂单的订单必须传入
+    pub token_program: Interface<'info, TokenInterface>, // 代币程序  就是告诉 Anchor：我要去找“官方 SPL Token 程序”，帮我干转账、铸币这些事。
+    pub system_program: Program<'info, System>, // 系统程序，用于把 SOL 包装为 wSOL
+}
+
+// 定义取消订单指令的账户结构体
+#[derive(Accounts)]
+pub struct CancelOrder<'info> {
+    #[account(
+        mut,
+        seeds = [b"orderbook".as_ref(), orderbook.base_mint.as_ref(), orderbook.quote_mint.as_ref()],
+        bump,
+        has_one = base_mint,
+        has_one = quote_mint,
+    )]
+    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
+    pub base_mint: InterfaceAccount<'info, Mint>,  // 基础代币
+    pub quote_mint: InterfaceAccount<'info, Mint>, // 报价代币
+    #[account(mut)]
+    pub owner: Signer<'info>, // 订单拥有者
+    #[account(
+        mut,
+        constraint = owner_base_token_account.mint == orderbook.base_mint
+    )]
+    pub owner_base_token_account: InterfaceAccount<'info, TokenAccount>, // 用户基础代币账户
+    #[account(
+        mut,
+        constraint = owner_quote_token_account.mint == orderbook.quote_mint
+    )]
+    pub owner_quote_token_account: InterfaceAccount<'info, TokenAccount>, // 用户报价代币账户
+    #[account(
+        mut,
+        address = orderbook.base_vault,
+    )]
+    pub base_vault: InterfaceAccount<'info, TokenAccount>, // 基础代币金库
+    #[account(
+        mut,
+        address = orderbook.quote_vault,
+    )]
+    pub quote_vault: InterfaceAccount<'info, TokenAccount>, // 报价代币金库
+    pub token_program: Interface<'info, TokenInterface>, // 代币程序
+}
+
+// 定义修改订单指令的账户结构体
+#[derive(Accounts)]
+pub struct ModifyOrder<'info> {
+    #[account(
+        mut,
+        seeds = [b"orderbook".as_ref(), orderbook.base_mint.as_ref(), orderbook.quote_mint.as_ref()],
+        bump,
+        has_one = base_mint,
+        has_one = quote_mint,
+    )]
+    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
+    pub base_mint: InterfaceAccount<'info, Mint>,  // 基础代币
+    pub quote_mint: InterfaceAccount<'info, Mint>, // 报价代币
+    #[account(mut)]
+    pub owner: Signer<'info>, // 订单拥有者
+    #[account(
+        mut,
+        constraint = owner_base_token_account.mint == orderbook.base_mint,
+        constraint = owner_base_token_account.owner == owner.key()
+    )]
+    pub owner_base_token_account: InterfaceAccount<'info, TokenAccount>, // 用户基础代币账户
+    #[account(
+        mut,
+        constraint = owner_quote_token_account.mint == orderbook.quote_mint,
+        constraint = owner_quote_token_account.owner == owner.key()
+    )]
+    pub owner_quote_token_account: InterfaceAccount<'info, TokenAccount>, // 用户报价代币账户
+    #[account(
+        mut,
+        address = orderbook.base_vault,
+    )]
+    pub base_vault: InterfaceAccount<'info, TokenAccount>, // 基础代币金库
+    #[account(
+        mut,
+        address = orderbook.quote_vault,
+    )]
+    pub quote_vault: InterfaceAccount<'info, TokenAccount>, // 报价代币金库
+    /// CHECK: 预言机价格账户，在 require_within_oracle_band 中校验地址、所属程序与数据
+    pub oracle: Option<UncheckedAccount<'info>>, // 可选预言机账户，订单簿配置了预言机时必须传入
+    pub token_program: Interface<'info, TokenInterface>, // 代币程序
+}
+
+// 定义提取手续费指令的账户结构体
+#[derive(Accounts)]
+pub struct WithdrawFees<'info> {
+    #[account(
+        mut,
+        seeds = [b"orderbook".as_ref(), orderbook.base_mint.as_ref(), orderbook.quote_mint.as_ref()],
+        bump,
+        has_one = base_mint,
+        has_one = quote_mint,
+    )]
+    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
+    pub base_mint: InterfaceAccount<'info, Mint>,  // 基础代币
+    pub quote_mint: InterfaceAccount<'info, Mint>, // 报价代币
+    pub admin: Signer<'info>, // 管理员
+    #[account(
+        mut,
+        seeds = [b"base_fee_vault".as_ref(), orderbook.key().as_ref()],
+        bump
+    )]
+    pub base_fee_vault: InterfaceAccount<'info, TokenAccount>, // 基础代币手续费金库
+    #[account(
+        mut,
+        seeds = [b"quote_fee_vault".as_ref(), orderbook.key().as_ref()],
+        bump
+    )]
+    pub quote_fee_vault: InterfaceAccount<'info, TokenAccount>, // 报价代币手续费金库
+    #[account(
+        mut,
+        constraint = admin_base_token_account.mint == orderbook.base_mint
+    )]
+    pub admin_base_token_account: InterfaceAccount<'info, TokenAccount>, // 接收基础代币手续费的账户
+    #[account(
+        mut,
+        constraint = admin_quote_token_account.mint == orderbook.quote_mint
+    )]
+    pub admin_quote_token_account: InterfaceAccount<'info, TokenAccount>, // 接收报价代币手续费的账户
+    pub token_program: Interface<'info, TokenInterface>, // 代币程序
+}
+
+// 定义提取金库盈余指令的账户结构体
+#[derive(Accounts)]
+pub struct SweepSurplus<'info> {
+    #[account(
+        mut,
+        seeds = [b"orderbook".as_ref(), orderbook.base_mint.as_ref(), orderbook.quote_mint.as_ref()],
+        bump,
+        has_one = base_mint,
+        has_one = quote_mint,
+    )]
+    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
+    pub base_mint: InterfaceAccount<'info, Mint>,  // 基础代币
+    pub quote_mint: InterfaceAccount<'info, Mint>, // 报价代币
+    pub admin: Signer<'info>, // 管理员
+    #[account(
+        mut,
+        address = orderbook.base_vault,
+    )]
+    pub base_vault: InterfaceAccount<'info, TokenAccount>, // 基础代币金库
+    #[account(
+        mut,
+        address = orderbook.quote_vault,
+    )]
+    pub quote_vault: InterfaceAccount<'info, TokenAccount>, // 报价代币金库
+    #[account(
+        mut,
+        constraint = admin_base_token_account.mint == orderbook.base_mint
+    )]
+    pub admin_base_token_account: InterfaceAccount<'info, TokenAccount>, // 接收基础代币盈余的账户
+    #[account(
+        mut,
+        constraint = admin_quote_token_account.mint == orderbook.quote_mint
+    )]
+    pub admin_quote_token_account: InterfaceAccount<'info, TokenAccount>, // 接收报价代币盈余的账户
+    pub token_program: Interface<'info, TokenInterface>, // 代币程序
+}
+
+// 定义强制撤单指令的账户结构体
+#[derive(Accounts)]
+pub struct ForceCancel<'info> {
+    #[account(
+        mut,
+        seeds = [b"orderbook".as_ref(), orderbook.base_mint.as_ref(), orderbook.quote_mint.as_ref()],
+        bump,
+        has_one = base_mint,
+        has_one = quote_mint,
+    )]
+    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
+    pub base_mint: InterfaceAccount<'info, Mint>,  // 基础代币
+    pub quote_mint: InterfaceAccount<'info, Mint>, // 报价代币
+    pub admin: Signer<'info>, // 管理员
+    #[account(
+        mut,
+        address = orderbook.base_vault,
+    )]
+    pub base_vault: InterfaceAccount<'info, TokenAccount>, // 基础代币金库
+    #[account(
+        mut,
+        address = orderbook.quote_vault,
+    )]
+    pub quote_vault: InterfaceAccount<'info, TokenAccount>, // 报价代币金库
+    #[account(mut)]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>, // 订单拥有者接收退款的代币账户
+    #[account(
+        mut,
+        constraint = escrow_token_account.owner == admin.key() @ DexError::Unauthorized
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>, // 管理员控制的托管账户（拥有者账户被冻结时接收退款）
+    pub token_program: Interface<'info, TokenInterface>, // 代币程序
+}
+
+// 定义设置手续费舍入方式指令的账户结构体
+#[derive(Accounts)]
+pub struct SetFeeRounding<'info> {
+    #[account(
+        mut,
+        seeds = [b"orderbook".as_ref(), orderbook.base_mint.as_ref(), orderbook.quote_mint.as_ref()],
+        bump,
+    )]
+    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
+    pub admin: Signer<'info>, // 管理员
+}
+
+// 定义切换集合竞价模式指令的账户结构体
+#[derive(Accounts)]
+pub struct SetAuctionMode<'info> {
+    #[account(
+        mut,
+        seeds = [b"orderbook".as_ref(), orderbook.base_mint.as_ref(), orderbook.quote_mint.as_ref()],
+        bump,
+    )]
+    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
+    pub admin: Signer<'info>, // 管理员
+}
+
+// 定义设置返佣比例指令的账户结构体
+#[derive(Accounts)]
+pub struct SetRebateBps<'info> {
+    #[account(
+        mut,
+        seeds = [b"orderbook".as_ref(), orderbook.base_mint.as_ref(), orderbook.quote_mint.as_ref()],
+        bump,
+    )]
+    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
+    pub admin: Signer<'info>, // 管理员
+}
+
+// 定义设置订单名义金额下限指令的账户结构体
+#[derive(Accounts)]
+pub struct SetMinQuoteNotional<'info> {
+    #[account(
+        mut,
+        seeds = [b"orderbook".as_ref(), orderbook.base_mint.as_ref(), orderbook.quote_mint.as_ref()],
+        bump,
+    )]
+    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
+    pub admin: Signer<'info>, // 管理员
+}
+
+// 定义设置预言机价格带指令的账户结构体
+#[derive(Accounts)]
+pub struct SetOracle<'info> {
+    #[account(
+        mut,
+        seeds = [b"orderbook".as_ref(), orderbook.base_mint.as_ref(), orderbook.quote_mint.as_ref()],
+        bump,
+    )]
+    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
+    pub admin: Signer<'info>, // 管理员
+}
+
+// 定义领取返佣指令的账户结构体
+#[derive(Accounts)]
+pub struct ClaimRebate<'info> {
+    #[account(
+        mut,
+        seeds = [b"orderbook".as_ref(), orderbook.base_mint.as_ref(), orderbook.quote_mint.as_ref()],
+        bump,
+        has_one = quote_mint,
+    )]
+    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
+    pub quote_mint: InterfaceAccount<'info, Mint>, // 报价代币
+    pub owner: Signer<'info>, // 领取返佣的 maker
+    #[account(
+        mut,
+        seeds = [b"claimable".as_ref(), orderbook.key().as_ref(), owner.key().as_ref()],
+        bump = claimable.bump,
+    )]
+    pub claimable: Account<'info, ClaimableBalance>, // maker 的待领取余额账户
+    #[account(
+        mut,
+        seeds = [b"quote_fee_vault".as_ref(), orderbook.key().as_ref()],
+        bump
+    )]
+    pub quote_fee_vault: InterfaceAccount<'info, TokenAccount>, // 报价代币手续费金库
+    #[account(
+        mut,
+        constraint = owner_quote_token_account.mint == orderbook.quote_mint
+    )]
+    pub owner_quote_token_account: InterfaceAccount<'info, TokenAccount>, // 接收返佣的账户
+    pub token_program: Interface<'info, TokenInterface>, // 代币程序
+}
+
+// 定义领取原生 SOL 挂单所得指令的账户结构体
+#[derive(Accounts)]
+pub struct ClaimNative<'info> {
+    #[account(
+        seeds = [b"orderbook".as_ref(), orderbook.base_mint.as_ref(), orderbook.quote_mint.as_ref()],
+        bump,
+    )]
+    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
+    #[account(mut)]
+    pub owner: Signer<'info>, // 领取所得的挂单拥有者
+    #[account(
+        seeds = [b"claimable".as_ref(), orderbook.key().as_ref(), owner.key().as_ref()],
+        bump = claimable.bump,
+    )]
+    pub claimable: Account<'info, ClaimableBalance>, // 拥有者的待领取余额账户（托管账户的 authority）
+    #[account(
+        mut,
+        constraint = native_escrow.owner == claimable.key() @ DexError::InvalidNativeEscrow,
+        constraint = native_escrow.mint == native_mint.key() @ DexError::InvalidNativeEscrow,
+    )]
+    pub native_escrow: InterfaceAccount<'info, TokenAccount>, // 拥有者的托管 wSOL 账户
+    pub native_mint: InterfaceAccount<'info, Mint>, // 原生 SOL 一方的代币（wSOL）
+    #[account(
+        mut,
+        constraint = owner_wsol_account.owner == owner.key(),
+        constraint = owner_wsol_account.mint == native_mint.key()
+    )]
+    pub owner_wsol_account: InterfaceAccount<'info, TokenAccount>, // 拥有者的临时 wSOL 账户，领取后关闭
+    pub token_program: Interface<'info, TokenInterface>, // 代币程序
+}
+
+// 定义领取待领取撤单资金指令的账户结构体
+#[derive(Accounts)]
+pub struct ClaimBalance<'info> {
+    #[account(
+        mut,
+        seeds = [b"orderbook".as_ref(), orderbook.base_mint.as_ref(), orderbook.quote_mint.as_ref()],
+        bump,
+        has_one = base_mint,
+        has_one = quote_mint,
+    )]
+    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
+    pub base_mint: InterfaceAccount<'info, Mint>,  // 基础代币
+    pub quote_mint: InterfaceAccount<'info, Mint>, // 报价代币
+    pub owner: Signer<'info>, // 领取资金的交易者
+    #[account(
+        mut,
+        seeds = [b"claimable".as_ref(), orderbook.key().as_ref(), owner.key().as_ref()],
+        bump = claimable.bump,
+    )]
+    pub claimable: Account<'info, ClaimableBalance>, // 交易者的待领取余额账户
+    #[account(
+        mut,
+        constraint = owner_base_token_account.mint == orderbook.base_mint,
+        constraint = owner_base_token_account.owner == owner.key()
+    )]
+    pub owner_base_token_account: InterfaceAccount<'info, TokenAccount>, // 接收基础代币的账户
+    #[account(
+        mut,
+        constraint = owner_quote_token_account.mint == orderbook.quote_mint,
+        constraint = owner_quote_token_account.owner == owner.key()
+    )]
+    pub owner_quote_token_account: InterfaceAccount<'info, TokenAccount>, // 接收报价代币的账户
+    #[account(
+        mut,
+        address = orderbook.base_vault,
+    )]
+    pub base_vault: InterfaceAccount<'info, TokenAccount>, // 基础代币金库
+    #[account(
+        mut,
+        address = orderbook.quote_vault,
+    )]
+    pub quote_vault: InterfaceAccount<'info, TokenAccount>, // 报价代币金库
+    pub token_program: Interface<'info, TokenInterface>, // 代币程序
+}
+
+// 定义冻结/解冻订单指令的账户结构体
+#[derive(Accounts)]
+pub struct FreezeOrder<'info> {
+    #[account(
+        mut,
+        seeds = [b"orderbook".as_ref(), orderbook.base_mint.as_ref(), orderbook.quote_mint.as_ref()],
+        bump,
+    )]
+    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
+    pub admin: Signer<'info>, // 管理员
+}
+
+// 定义暂停/恢复交易指令的账户结构体
+#[derive(Accounts)]
+pub struct SetPaused<'info> {
+    #[account(
+        mut,
+        seeds = [b"orderbook".as_ref(), orderbook.base_mint.as_ref(), orderbook.quote_mint.as_ref()],
+        bump,
+    )]
+    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
+    pub admin: Signer<'info>, // 管理员
+}
+
+// 定义结算成交批次指令的账户结构体
+#[derive(Accounts)]
+pub struct SettleFills<'info> {
+    #[account(
+        mut,
+        seeds = [b"orderbook".as_ref(), orderbook.base_mint.as_ref(), orderbook.quote_mint.as_ref()],
+        bump,
+    )]
+    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
+}
+
+// 定义验证成交证明指令的账户结构体
+#[derive(Accounts)]
+pub struct VerifyFill<'info> {
+    #[account(
+        seeds = [b"orderbook".as_ref(), orderbook.base_mint.as_ref(), orderbook.quote_mint.as_ref()],
+        bump,
+    )]
+    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
+}
+
+// 定义清理过期挂单指令的账户结构体
+#[derive(Accounts)]
+pub struct ReapExpired<'info> {
+    #[account(
+        mut,
+        seeds = [b"orderbook".as_ref(), orderbook.base_mint.as_ref(), orderbook.quote_mint.as_ref()],
+        bump,
+        has_one = base_mint,
+        has_one = quote_mint,
+    )]
+    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
+    pub base_mint: InterfaceAccount<'info, Mint>,  // 基础代币
+    pub quote_mint: InterfaceAccount<'info, Mint>, // 报价代币
+    #[account(
+        mut,
+        address = orderbook.base_vault,
+    )]
+    pub base_vault: InterfaceAccount<'info, TokenAccount>, // 基础代币金库
+    #[account(
+        mut,
+        address = orderbook.quote_vault,
+    )]
+    pub quote_vault: InterfaceAccount<'info, TokenAccount>, // 报价代币金库
+    pub token_program: Interface<'info, TokenInterface>, // 代币程序
+}
+
+// 定义触发止损单指令的账户结构体
+#[derive(Accounts)]
+pub struct TriggerStops<'info> {
+    #[account(
+        mut,
+        seeds = [b"orderbook".as_ref(), orderbook.base_mint.as_ref(), orderbook.quote_mint.as_ref()],
+        bump,
+        has_one = base_mint,
+        has_one = quote_mint,
+    )]
+    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
+    pub base_mint: InterfaceAccount<'info, Mint>,  // 基础代币
+    pub quote_mint: InterfaceAccount<'info, Mint>, // 报价代币
+    #[account(
+        mut,
+        address = orderbook.base_vault,
+    )]
+    pub base_vault: InterfaceAccount<'info, TokenAccount>, // 基础代币金库
+    #[account(
+        mut,
+        address = orderbook.quote_vault,
+    )]
+    pub quote_vault: InterfaceAccount<'info, TokenAccount>, // 报价代币金库
+    #[account(
+        mut,
+        seeds = [b"base_fee_vault".as_ref(), orderbook.key().as_ref()],
+        bump
+    )]
+    pub base_fee_vault: InterfaceAccount<'info, TokenAccount>, // 基础代币手续费金库
+    #[account(
+        mut,
+        seeds = [b"quote_fee_vault".as_ref(), orderbook.key().as_ref()],
+        bump
+    )]
+    pub quote_fee_vault: InterfaceAccount<'info, TokenAccount>, // 报价代币手续费金库
+    /// CHECK: 预言机价格账户，在 require_within_oracle_band 中校验地址、所属程序与数据
+    pub oracle: Option<UncheckedAccount<'info>>, // 可选预言机账户，订单簿配置了预言机时必须传入
+    pub token_program: Interface<'info, TokenInterface>, // 代币程序
+}
+
+// 定义撮合交叉挂单指令的账户结构体
+#[derive(Accounts)]
+pub struct MatchOrders<'info> {
+    #[account(
+        mut,
+        seeds = [b"orderbook".as_ref(), orderbook.base_mint.as_ref(), orderbook.quote_mint.as_ref()],
+        bump,
+        has_one = base_mint,
+        has_one = quote_mint,
+    )]
+    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
+    pub base_mint: InterfaceAccount<'info, Mint>,  // 基础代币
+    pub quote_mint: InterfaceAccount<'info, Mint>, // 报价代币
+    #[account(
+        mut,
+        address = orderbook.base_vault,
+    )]
+    pub base_vault: InterfaceAccount<'info, TokenAccount>, // 基础代币金库
+    #[account(
+        mut,
+        address = orderbook.quote_vault,
+    )]
+    pub quote_vault: InterfaceAccount<'info, TokenAccount>, // 报价代币金库
+    #[account(
+        mut,
+        seeds = [b"base_fee_vault".as_ref(), orderbook.key().as_ref()],
+        bump
+    )]
+    pub base_fee_vault: InterfaceAccount<'info, TokenAccount>, // 基础代币手续费金库
+    #[account(
+        mut,
+        seeds = [b"quote_fee_vault".as_ref(), orderbook.key().as_ref()],
+        bump
+    )]
+    pub quote_fee_vault: InterfaceAccount<'info, TokenAccount>, // 报价代币手续费金库
+    pub token_program: Interface<'info, TokenInterface>, // 代币程序
+}
+
+// 定义扩容订单簿指令的账户结构体
+#[derive(Accounts)]
+#[instruction(additional_orders: u32)]
+pub struct GrowBook<'info> {
+    #[account(
+        mut,
+        seeds = [b"orderbook".as_ref(), orderbook.base_mint.as_ref(), orderbook.quote_mint.as_ref()],
+        bump,
+        realloc = Orderbook::space(orderbook.max_orders.saturating_add(additional_orders)),
+        realloc::payer = payer,
+        realloc::zero = false,
+    )]
+    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
+    #[account(mut)]
+    pub payer: Signer<'info>, // 支付新增租金的账户
+    pub system_program: Program<'info, System>, // 系统程序
+}
+
+// 定义迁移订单簿指令的账户结构体；旧布局的订单簿无法按当前布局反序列化，以 UncheckedAccount 传入，
+// 首个版本没有手续费金库，迁移时一并创建
+#[derive(Accounts)]
+pub struct Migrate<'info> {
+    /// CHECK: 在 migrate 中校验所属程序、判别符与 PDA 地址，按数据长度与版本号解析后以当前布局重写
+    #[account(mut)]
+    pub orderbook: UncheckedAccount<'info>, // 订单簿账户（任意布局版本）
+    pub base_mint: InterfaceAccount<'info, Mint>,  // 基础代币
+    pub quote_mint: InterfaceAccount<'info, Mint>, // 报价代币
+    #[account(
+        init_if_needed,
+        payer = admin,
+        token::mint = base_mint,
+        token::authority = orderbook,
+        token::token_program = token_program,
+        seeds = [b"base_fee_vault".as_ref(), orderbook.key().as_ref()],
+        bump
+    )]
+    pub base_fee_vault: InterfaceAccount<'info, TokenAccount>, // 基础代币手续费金库
+    #[account(
+        init_if_needed,
+        payer = admin,
+        token::mint = quote_mint,
+        token::authority = orderbook,
+        token::token_program = token_program,
+        seeds = [b"quote_fee_vault".as_ref(), orderbook.key().as_ref()],
+        bump
+    )]
+    pub quote_fee_vault: InterfaceAccount<'info, TokenAccount>, // 报价代币手续费金库
+    #[account(
+        seeds = [crate::ID.as_ref()],
+        bump,
+        seeds::program = anchor_lang::solana_program::bpf_loader_upgradeable::ID
+    )]
+    pub program_data: Option<Account<'info, ProgramData>>, // 本程序的 ProgramData 账户（仅迁移首个版本的订单簿时需要）
+    #[account(mut)]
+    pub admin: Signer<'info>, // 管理员（首个版本的订单簿由程序升级权限迁移并成为管理员；支付新增租金）
+    pub system_program: Program<'info, System>, // 系统程序
+    pub token_program: Interface<'info, TokenInterface>, // 代币程序
 }
 
-// 定义取消订单指令的账户结构体
+// 定义关闭订单簿指令的账户结构体
 #[derive(Accounts)]
-pub struct CancelOrder<'info> {
+pub struct CloseOrderbook<'info> {
     #[account(
         mut,
         seeds = [b"orderbook".as_ref(), orderbook.base_mint.as_ref(), orderbook.quote_mint.as_ref()],
         bump,
+        close = admin,
     )]
     pub orderbook: Account<'info, Orderbook>, // 订单簿账户
     #[account(mut)]
-    pub owner: Signer<'info>, // 订单拥有者
+    pub admin: Signer<'info>, // 管理员（接收退还的租金）
     #[account(
         mut,
-        constraint = owner_base_token_account.mint == orderbook.base_mint
+        address = orderbook.base_vault,
     )]
-    pub owner_base_token_account: Account<'info, TokenAccount>, // 用户基础代币账户
+    pub base_vault: InterfaceAccount<'info, TokenAccount>, // 基础代币金库
     #[account(
         mut,
-        constraint = owner_quote_token_account.mint == orderbook.quote_mint
+        address = orderbook.quote_vault,
     )]
-    pub owner_quote_token_account: Account<'info, TokenAccount>, // 用户报价代币账户
+    pub quote_vault: InterfaceAccount<'info, TokenAccount>, // 报价代币金库
     #[account(
         mut,
-        seeds = [b"base_vault".as_ref(), orderbook.key().as_ref()],
+        seeds = [b"base_fee_vault".as_ref(), orderbook.key().as_ref()],
         bump
     )]
-    pub base_vault: Account<'info, TokenAccount>, // 基础代币金库
+    pub base_fee_vault: InterfaceAccount<'info, TokenAccount>, // 基础代币手续费金库
     #[account(
         mut,
-        seeds = [b"quote_vault".as_ref(), orderbook.key().as_ref()],
+        seeds = [b"quote_fee_vault".as_ref(), orderbook.key().as_ref()],
+        bump
+    )]
+    pub quote_fee_vault: InterfaceAccount<'info, TokenAccount>, // 报价代币手续费金库
+    pub token_program: Interface<'info, TokenInterface>, // 代币程序
+}
+
+// 定义查询管理员操作日志指令的账户结构体
+#[derive(Accounts)]
+pub struct GetAdminLog<'info> {
+    #[account(
+        seeds = [b"orderbook".as_ref(), orderbook.base_mint.as_ref(), orderbook.quote_mint.as_ref()],
+        bump,
+    )]
+    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
+}
+
+// 定义查询订单簿账户信息指令的账户结构体
+#[derive(Accounts)]
+pub struct GetAccountInfo<'info> {
+    #[account(
+        seeds = [b"orderbook".as_ref(), orderbook.base_mint.as_ref(), orderbook.quote_mint.as_ref()],
+        bump,
+    )]
+    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
+}
+
+// 定义查询订单簿深度指令的账户结构体
+#[derive(Accounts)]
+pub struct GetOrderBookDepth<'info> {
+    #[account(
+        seeds = [b"orderbook".as_ref(), orderbook.base_mint.as_ref(), orderbook.quote_mint.as_ref()],
+        bump,
+    )]
+    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
+}
+
+// 定义查询最优买卖价指令的账户结构体
+#[derive(Accounts)]
+pub struct PeekBestBidAsk<'info> {
+    #[account(
+        seeds = [b"orderbook".as_ref(), orderbook.base_mint.as_ref(), orderbook.quote_mint.as_ref()],
+        bump,
+    )]
+    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
+}
+
+// 定义模拟下单指令的账户结构体
+#[derive(Accounts)]
+pub struct SimulateOrder<'info> {
+    #[account(
+        seeds = [b"orderbook".as_ref(), orderbook.base_mint.as_ref(), orderbook.quote_mint.as_ref()],
+        bump,
+    )]
+    pub orderbook: Account<'info, Orderbook>, // 订单簿账户
+}
+
+// 定义注册推荐码指令的账户结构体
+#[derive(Accounts)]
+#[instruction(code: String)]
+pub struct RegisterReferral<'info> {
+    #[account(
+        init,
+        payer = referrer,
+        space = 8 + 32 + 4 + MAX_REFERRAL_CODE_LEN, // 分配空间
+        seeds = [b"referral".as_ref(), code.as_bytes()],
         bump
     )]
-    pub quote_vault: Account<'info, TokenAccount>, // 报价代币金库
-    pub token_program: Program<'info, Token>, // 代币程序
+    pub referral: Account<'info, ReferralCode>, // 推荐码账户
+    #[account(mut)]
+    pub referrer: Signer<'info>, // 推荐人（同时支付租金）
+    pub system_program: Program<'info, System>, // 系统程序
 }
 
 // 定义订单簿数据结构，存储代币对和订单信息
@@ -514,6 +5347,213 @@ pub struct Orderbook {
     pub bids: Vec<Order>,      // 买单列表
     pub asks: Vec<Order>,      // 卖单列表
     pub order_id_counter: u64, // 订单 ID 计数器
+    pub min_fill_notional: u64, // 单笔成交的最小报价代币金额（0 表示不限制）
+    pub admin: Pubkey,          // 管理员公钥
+    pub sequence_number: u64,   // 成交序列号，每笔成交递增
+    pub pending_fills: Vec<[u8; 32]>, // 待结算成交的 Merkle 叶子
+    pub fill_roots: [[u8; 32]; MAX_FILL_ROOTS], // 最近提交的成交 Merkle 根，按批次编号循环存放
+    pub fill_batch_id: u64,     // 已提交的成交批次数
+    pub execution_price_mode: ExecutionPriceMode, // 成交价格模式
+    pub admin_log: Vec<AdminAction>, // 管理员操作日志（有界，按时间顺序）
+    pub maker_fee_bps: u16,          // maker 手续费（基点）
+    pub taker_fee_bps: u16,          // taker 手续费（基点）
+    pub min_base_quantity: u64,      // 单笔订单的最小基础代币数量
+    pub price_tick: u64,             // 价格最小变动单位，订单价格必须是其整数倍
+    pub max_orders: u32,             // 每一方向可容纳的挂单数量上限
+    pub stops: Vec<StopOrder>,       // 未触发的止损单（按下单顺序）
+    pub last_trade_price: u64,       // 最新成交价（0 表示尚无成交）
+    pub paused: bool,                // 是否暂停交易（暂停时仍可撤单）
+    pub rebate_bps: u16,             // maker 返佣比例（基点）
+    pub rebates_owed: u64,           // maker 累计未领取的返佣总额
+    pub claimable_base: u64,         // 已撤销挂单尚未领取的基础代币总额（仍在金库中）
+    pub claimable_quote: u64,        // 已撤销挂单尚未领取的报价代币总额（仍在金库中）
+    pub native_leg: NativeLeg,       // 以原生 SOL 结算的一方
+    pub base_decimals: u8,           // 基础代币精度
+    pub quote_decimals: u8,          // 报价代币精度
+    pub price_is_scaled: bool,       // 价格是否表示每个完整基础代币的报价代币最小单位数
+    pub min_quote_notional: u64,     // 限价单名义金额（报价代币）下限（0 表示不限制）
+    pub oracle: Option<Pubkey>,      // Pyth 价格账户（None 表示不检查价格带）
+    pub max_deviation_bps: u16,      // 限价允许偏离预言机价格的最大基点数
+    pub oracle_max_age: u32,         // 预言机价格的最大有效时长（秒）
+    pub rolling_volume_base: u64,    // 当前统计窗口内的基础代币成交量
+    pub rolling_volume_quote: u64,   // 当前统计窗口内的报价代币成交额
+    pub volume_window_start: i64,    // 当前成交量统计窗口的起点时间戳
+    pub auction_mode: bool,          // 是否为集合竞价模式（下单只挂单，由 run_auction 撮合）
+    pub max_orders_per_owner: u16,   // 每个拥有者的挂单数量上限（0 表示不限制）
+    pub fee_rounding: RoundingMode,  // 手续费的舍入方式
+    pub base_vault: Pubkey,          // 基础代币金库地址（PDA 或订单簿的 ATA）
+    pub quote_vault: Pubkey,         // 报价代币金库地址（PDA 或订单簿的 ATA）
+    pub version: u8,                 // 账户布局版本（0 表示引入版本号之前创建的账户）
+}
+
+impl Orderbook {
+    // 计算账户所需空间：8 字节判别符 + 各字段大小，
+    // 买单与卖单列表各按 max_orders 个订单（每个 Order::SIZE 字节）预留
+    pub fn space(max_orders: u32) -> usize {
+        let max_orders = max_orders as usize;
+        8 + 32 + 32 // 判别符、基础代币、报价代币
+            + 4 + (Order::SIZE * max_orders) // 买单列表
+            + 4 + (Order::SIZE * max_orders) // 卖单列表
+            + 8 + 8 + 32 // 订单 ID 计数器、最小成交额、管理员
+            + 8 + 4 + (32 * MAX_PENDING_FILLS) + (32 * MAX_FILL_ROOTS) + 8 // 成交序列号与 Merkle 结算
+            + 1 // 成交价格模式
+            + 4 + (AdminAction::SIZE * MAX_ADMIN_LOG_ENTRIES) // 管理员操作日志
+            + 2 + 2 // 手续费
+            + 8 + 8 // 最小下单数量、价格最小变动单位
+            + 4 // 挂单容量
+            + 4 + (StopOrder::SIZE * MAX_STOP_ORDERS) // 止损单列表
+            + 8 // 最新成交价
+            + 1 // 暂停状态
+            + 2 + 8 // 返佣比例与未领取返佣总额
+            + 8 + 8 // 已撤销挂单尚未领取的资金总额
+            + 1 // 原生 SOL 一方
+            + 1 + 1 + 1 // 代币精度与价格缩放标志
+            + 8 // 订单名义金额下限
+            + 1 + 32 + 2 + 4 // 预言机价格带
+            + 8 + 8 + 8 // 成交量统计
+            + 1 // 集合竞价模式
+            + 2 // 每个拥有者的挂单数量上限
+            + 1 // 手续费舍入方式
+            + 32 + 32 // 金库地址
+            + 1 // 账户布局版本
+    }
+
+    // 指定批次的成交 Merkle 根；批次尚未提交或已被循环覆盖时返回 None
+    pub fn fill_root(&self, batch_id: u64) -> Option<[u8; 32]> {
+        let retained = batch_id > 0
+            && batch_id <= self.fill_batch_id
+            && self.fill_batch_id - batch_id < MAX_FILL_ROOTS as u64;
+        retained.then(|| self.fill_roots[batch_id as usize % MAX_FILL_ROOTS])
+    }
+
+    // 某一方向订单锁定的代币：买单锁定报价代币，卖单锁定基础代币
+    pub fn locked_mint(&self, side: &Side) -> Pubkey {
+        match side {
+            Side::Buy => self.quote_mint,
+            Side::Sell => self.base_mint,
+        }
+    }
+
+    // 按基点计算手续费，按订单簿配置的方式舍入
+    pub fn fee_amount(&self, amount: u64, fee_bps: u16) -> Result<u64> {
+        apply_bps(amount as u128, fee_bps, self.fee_rounding)
+    }
+
+    // 价格缩放因子：启用缩放时价格以每个完整基础代币计价，报价金额需除以 10^base_decimals
+    pub fn price_scale(&self) -> u64 {
+        if self.price_is_scaled {
+            10u64.pow(self.base_decimals as u32)
+        } else {
+            1
+        }
+    }
+}
+
+// 首个版本的订单簿账户布局（没有版本号、管理员与参数字段），只用于 migrate 读取旧账户
+#[derive(AnchorDeserialize)]
+struct LegacyOrderbook {
+    base_mint: Pubkey,      // 基础代币公钥
+    quote_mint: Pubkey,     // 报价代币公钥
+    bids: Vec<LegacyOrder>, // 买单列表（按价格降序）
+    asks: Vec<LegacyOrder>, // 卖单列表（按价格升序）
+    order_id_counter: u64,  // 订单 ID 计数器
+}
+
+// 首个版本的订单布局
+#[derive(AnchorDeserialize)]
+struct LegacyOrder {
+    owner: Pubkey, // 订单拥有者公钥
+    price: u64,    // 订单价格
+    quantity: u64, // 订单数量
+    order_id: u64, // 订单 ID
+}
+
+impl LegacyOrderbook {
+    // 首个版本分配的账户大小：判别符 + 代币对 + 订单 ID 计数器 + 两个各 50 个订单（每个 56 字节）的列表
+    const SPACE: usize = 8 + 32 + 32 + 8 + 4 + (56 * 50) + 4 + (56 * 50);
+
+    // 转换为当前布局：保留挂单与订单 ID 计数器，挂单按限价补记锁定资金（首个版本不支持价格缩放）；
+    // 管理员为迁移的调用者，金库为首个版本使用的 PDA 金库，其余参数取显式默认值
+    // （价格最小变动单位 1、默认挂单容量、零手续费）
+    fn into_current(
+        self,
+        orderbook_key: Pubkey,
+        admin: Pubkey,
+        base_decimals: u8,
+        quote_decimals: u8,
+    ) -> Result<Orderbook> {
+        let convert = |orders: Vec<LegacyOrder>, side: Side| {
+            orders
+                .into_iter()
+                .map(|order| {
+                    Order {
+                        owner: order.owner,
+                        price: order.price,
+                        quantity: order.quantity,
+                        order_id: order.order_id,
+                        frozen: false,
+                        oco_group_id: 0,
+                        expiry_ts: 0,
+                        locked_quote: 0,
+                        locked_base: 0,
+                        display_size: 0,
+                        display_quantity: 0,
+                        settlement_authority: None,
+                        original_quantity: order.quantity,
+                    }
+                    .with_locked_funds(&side, 1)
+                })
+                .collect::<Result<Vec<Order>>>()
+        };
+        let bids = convert(self.bids, Side::Buy)?;
+        let asks = convert(self.asks, Side::Sell)?;
+        // 首个版本每一方向最多 50 个挂单，容量不足时按现有挂单数量扩大
+        let max_orders = INITIAL_MAX_ORDERS.max(bids.len().max(asks.len()) as u32);
+        Ok(Orderbook {
+            base_mint: self.base_mint,
+            quote_mint: self.quote_mint,
+            bids,
+            asks,
+            order_id_counter: self.order_id_counter,
+            min_fill_notional: 0,
+            admin,
+            sequence_number: 0,
+            pending_fills: Vec::new(),
+            fill_roots: [[0; 32]; MAX_FILL_ROOTS],
+            fill_batch_id: 0,
+            execution_price_mode: ExecutionPriceMode::MakerPrice,
+            admin_log: Vec::new(),
+            maker_fee_bps: 0,
+            taker_fee_bps: 0,
+            min_base_quantity: 1,
+            price_tick: 1,
+            max_orders,
+            stops: Vec::new(),
+            last_trade_price: 0,
+            paused: false,
+            rebate_bps: 0,
+            rebates_owed: 0,
+            claimable_base: 0,
+            claimable_quote: 0,
+            native_leg: NativeLeg::None,
+            base_decimals,
+            quote_decimals,
+            price_is_scaled: false,
+            min_quote_notional: 0,
+            oracle: None,
+            max_deviation_bps: 0,
+            oracle_max_age: 0,
+            rolling_volume_base: 0,
+            rolling_volume_quote: 0,
+            volume_window_start: 0,
+            auction_mode: false,
+            max_orders_per_owner: 0,
+            fee_rounding: RoundingMode::Ceil,
+            base_vault: vault_pda(b"base_vault", &orderbook_key),
+            quote_vault: vault_pda(b"quote_vault", &orderbook_key),
+            version: 0,
+        })
+    }
 }
 
 // 定义订单数据结构，存储订单详细信息
@@ -523,15 +5563,409 @@ pub struct Order {
     pub price: u64,    // 订单价格
     pub quantity: u64, // 订单数量
     pub order_id: u64, // 订单 ID
+    pub frozen: bool,  // 是否被管理员冻结
+    pub oco_group_id: u64, // OCO 组 ID（0 表示不属于任何组），同组订单任一成交即撤销其余订单
+    pub expiry_ts: i64, // 过期时间戳（0 表示永不过期）
+    pub locked_quote: u64, // 买单实际锁定的报价代币（卖单为 0）
+    pub locked_base: u64,  // 卖单实际锁定的基础代币（买单为 0）
+    pub display_size: u64, // 冰山单每次显示的数量（0 表示普通订单，全部显示）
+    pub display_quantity: u64, // 冰山单当前显示部分的剩余数量，只有这部分可以成交
+    pub settlement_authority: Option<Pubkey>, // 成交所得与退款的接收方（None 表示订单拥有者）
+    pub original_quantity: u64, // 下单时的数量，quantity 为剩余数量，两者之差即已成交（或已撤销）的数量
 }
 
-// 定义订单方向枚举（买入/卖出）
+impl Order {
+    // 序列化后的大小：owner + price + quantity + order_id + frozen + oco_group_id + expiry_ts
+    // + locked_quote + locked_base + display_size + display_quantity + settlement_authority
+    // + original_quantity
+    pub const SIZE: usize = 32 + 8 + 8 + 8 + 1 + 8 + 8 + 8 + 8 + 8 + 8 + 33 + 8;
+
+    // 作为 maker 时代币账户必须属于的地址：指定了结算方时为结算方，否则为订单拥有者
+    pub fn settlement_owner(&self) -> Pubkey {
+        self.settlement_authority.unwrap_or(self.owner)
+    }
+
+    // 当前可成交的数量：冰山单为当前显示的部分，普通订单为全部剩余数量
+    pub fn matchable_quantity(&self) -> u64 {
+        if self.display_size == 0 {
+            self.quantity
+        } else {
+            self.display_quantity
+        }
+    }
+
+    // 更新冰山单的显示部分（普通订单不变）：扣除本次成交的数量，并保持不超过剩余数量；
+    // 显示部分耗尽而仍有隐藏数量时补充新的显示部分，返回 true 表示已补充，挂单需重新排队。
+    // 隐藏部分的资金在挂单时已全部锁定，补充时无需追加存入
+    pub fn update_display(&mut self, filled: u64) -> bool {
+        if self.display_size == 0 {
+            return false;
+        }
+        self.display_quantity = self.display_quantity.saturating_sub(filled).min(self.quantity);
+        if self.display_quantity == 0 && self.quantity > 0 {
+            self.display_quantity = self.display_size.min(self.quantity);
+            return true;
+        }
+        false
+    }
+
+    // 按限价记录挂单需锁定的资金：买单为 价格 × 数量 的报价代币（向上取整），卖单为等量基础代币
+    pub fn with_locked_funds(mut self, side: &Side, price_scale: u64) -> Result<Order> {
+        match side {
+            Side::Buy => {
+                self.locked_quote = locked_quote_amount(self.price, self.quantity, price_scale)?
+            }
+            Side::Sell => self.locked_base = self.quantity,
+        }
+        Ok(self)
+    }
+
+    // 减少订单数量并释放对应的锁定资金，返回释放的数量：买单按价格计算释放的报价代币，
+    // 卖单释放等量基础代币；数量减至 0 时释放剩余的全部锁定资金
+    pub fn reduce(&mut self, side: &Side, quantity: u64, price_scale: u64) -> Result<u64> {
+        // 减少的数量超过订单数量时报错，不修改订单
+        let remaining_quantity = self
+            .quantity
+            .checked_sub(quantity)
+            .ok_or(DexError::CalculationError)?;
+        let locked = match side {
+            Side::Buy => self.locked_quote,
+            Side::Sell => self.locked_base,
+        };
+        let released = if quantity == self.quantity {
+            locked
+        } else {
+            match side {
+                Side::Buy => quote_amount(self.price, quantity, price_scale)?,
+                Side::Sell => quantity,
+            }
+        };
+        let remaining_locked = locked
+            .checked_sub(released)
+            .ok_or(DexError::CalculationError)?;
+        match side {
+            Side::Buy => self.locked_quote = remaining_locked,
+            Side::Sell => self.locked_base = remaining_locked,
+        }
+        self.quantity = remaining_quantity;
+        Ok(released)
+    }
+
+    // 判断订单在给定时间是否已过期
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expiry_ts != 0 && self.expiry_ts < now
+    }
+}
+
+// 定义止损单数据结构：资金已按限价锁定，触发后转为限价单
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct StopOrder {
+    pub owner: Pubkey,      // 订单拥有者公钥
+    pub side: Side,         // 订单方向
+    pub trigger_price: u64, // 触发价格
+    pub limit_price: u64,   // 触发后的限价
+    pub quantity: u64,      // 订单数量
+    pub order_id: u64,      // 订单 ID（触发后挂单沿用此 ID）
+}
+
+impl StopOrder {
+    // 序列化后的大小：owner + side + trigger_price + limit_price + quantity + order_id
+    pub const SIZE: usize = 32 + 1 + 8 + 8 + 8 + 8;
+
+    // 判断止损单是否已被最新成交价触发：买入止损在价格涨至触发价及以上时触发，卖出止损在跌至触发价及以下时触发
+    pub fn is_triggered(&self, last_trade_price: u64) -> bool {
+        last_trade_price != 0
+            && match self.side {
+                Side::Buy => last_trade_price >= self.trigger_price,
+                Side::Sell => last_trade_price <= self.trigger_price,
+            }
+    }
+
+    // 转为以限价挂单的订单，锁定资金的计算与普通挂单一致
+    pub fn to_order(&self, price_scale: u64) -> Result<Order> {
+        Order {
+            owner: self.owner,
+            price: self.limit_price,
+            quantity: self.quantity,
+            order_id: self.order_id,
+            frozen: false,
+            oco_group_id: 0,
+            expiry_ts: 0,
+            locked_quote: 0,
+            locked_base: 0,
+            display_size: 0,
+            display_quantity: 0,
+            settlement_authority: None,
+            original_quantity: self.quantity,
+        }
+        .with_locked_funds(&self.side, price_scale)
+    }
+}
+
+// 定义推荐码数据结构，存储推荐码到推荐人的映射
+#[account]
+pub struct ReferralCode {
+    pub referrer: Pubkey, // 推荐人公钥
+    pub code: String,     // 推荐码
+}
+
+// 定义交易者在订单簿上的待领取余额（PDA：["claimable", 订单簿, 拥有者]），首次下单时创建；
+// 撮合中为 maker 累计的返佣，以及代币账户被关闭而撤销的挂单资金记在这里，数量不受订单簿账户大小限制
+#[account]
+pub struct ClaimableBalance {
+    pub orderbook: Pubkey, // 所属订单簿
+    pub owner: Pubkey,     // 余额归属的交易者
+    pub base: u64,         // 已撤销挂单待领取的基础代币
+    pub quote: u64,        // 已撤销挂单待领取的报价代币
+    pub rebate: u64,       // 累计未领取的 maker 返佣（报价代币）
+    pub bump: u8,          // PDA bump
+}
+
+impl ClaimableBalance {
+    // 序列化后的大小（不含判别符）：orderbook + owner + base + quote + rebate + bump
+    pub const SIZE: usize = 32 + 32 + 8 + 8 + 8 + 1;
+}
+
+// 定义订单簿账户信息，作为只读查询的返回数据
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct OrderbookAccountInfo {
+    pub data_len: u64,            // 账户数据长度（字节）
+    pub rent_exempt_minimum: u64, // 租金豁免所需最低 lamports
+    pub lamports: u64,            // 账户当前 lamports
+}
+
+// 定义订单簿深度，作为只读查询的返回数据（买单价位按价格降序，卖单价位按价格升序）
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct OrderBookDepth {
+    pub bids: Vec<PriceLevel>, // 买单价位
+    pub asks: Vec<PriceLevel>, // 卖单价位
+}
+
+// 定义最优买卖价，作为只读查询的返回数据（某一方向为空时价格与数量为 0）
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct TopOfBook {
+    pub bid_price: u64, // 最优买价
+    pub bid_qty: u64,   // 最优买单的可见数量
+    pub ask_price: u64, // 最优卖价
+    pub ask_qty: u64,   // 最优卖单的可见数量
+}
+
+// 定义模拟的一笔成交
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SimulatedFill {
+    pub price: u64,    // 成交价格
+    pub quantity: u64, // 成交数量
+}
+
+// 定义模拟下单结果，作为 simulate_order 的返回数据（成交额不含手续费）
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct OrderSimulation {
+    pub fills: Vec<SimulatedFill>, // 逐笔成交（最多 MAX_SIMULATED_FILLS 笔）
+    pub filled_quantity: u64,      // 可成交的基础代币总量
+    pub resting_quantity: u64,     // 未成交、将挂到订单簿上的剩余数量
+    pub quote_amount: u64,         // 成交的报价代币总额（买单支付 / 卖单收到）
+}
+
+// 定义下单结果，作为 place_order 的返回数据，便于 CPI 调用方同步获取撮合结果
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct PlaceOrderResult {
+    pub filled_quantity: u64,    // 本次成交的基础代币数量
+    pub average_fill_price: u64, // 按成交数量加权的平均成交价（没有成交时为 0）
+    pub resting_order_id: u64,   // 剩余部分挂单的订单 ID（0 表示没有挂单）
+    pub quote_spent: u64,        // 买单支付给 maker 的报价代币
+    pub quote_received: u64,     // 卖单收到的报价代币（已扣除 taker 手续费）
+}
+
+// 定义批量下单中的单个订单（GTC 限价单）
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct OrderRequest {
+    pub side: Side,    // 订单方向
+    pub price: u64,    // 订单价格
+    pub quantity: u64, // 订单数量
+}
+
+// 定义价位：同一价格上所有挂单的数量合计
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct PriceLevel {
+    pub price: u64,    // 价格
+    pub quantity: u64, // 该价格的挂单数量合计
+}
+
+// 定义成交记录，其哈希作为成交 Merkle 树的叶子
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct FillRecord {
+    pub sequence_number: u64, // 成交序列号
+    pub taker: Pubkey,        // 主动方公钥
+    pub maker: Pubkey,        // 被动方公钥
+    pub price: u64,           // 成交价格
+    pub quantity: u64,        // 成交数量
+}
+
+impl FillRecord {
+    // 计算叶子哈希：Borsh 序列化后加前缀 0x00，与内部节点区分
+    pub fn leaf_hash(&self) -> Result<[u8; 32]> {
+        Ok(hashv(&[&[0u8], &borsh::to_vec(self)?]).to_bytes())
+    }
+}
+
+// 定义管理员操作记录
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct AdminAction {
+    pub actor: Pubkey,         // 执行操作的管理员
+    pub kind: AdminActionKind, // 操作类型
+    pub timestamp: i64,        // 操作时间
+    pub param: u64,            // 操作参数（如订单 ID）
+}
+
+impl AdminAction {
+    // 序列化后的大小：actor + kind + timestamp + param
+    pub const SIZE: usize = 32 + 1 + 8 + 8;
+}
+
+// 定义管理员操作类型枚举
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum AdminActionKind {
+    FreezeOrder,
+    UnfreezeOrder,
+    WithdrawBaseFees,
+    WithdrawQuoteFees,
+    Pause,
+    Unpause,
+    SetRebateBps,
+    SetMinQuoteNotional,
+    SetOracle,
+    SweepBaseSurplus,
+    SweepQuoteSurplus,
+    SetAuctionMode,
+    SetFeeRounding,
+    ForceCancel,
+    Migrate,
+}
+
+// 定义原生 SOL 结算方枚举：被标记的一方（必须为 wSOL）在下单和撤单时自动包装与解包。
+// 用户在该方传入的代币账户是临时 wSOL 账户：存入时从 owner 的 SOL 余额包装，
+// 指令结束时关闭该账户，成交所得与退款以 SOL 返还。挂单不向临时账户付款：撮合时挂单在该方的
+// 成交所得与退款转入拥有者的托管 wSOL 账户（authority 为其待领取余额账户，不会被关闭），
+// 拥有者通过 claim_native 以 SOL 领取
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum NativeLeg {
+    None,
+    Base,
+    Quote,
+}
+
+// 定义舍入方式枚举：向下取整、向上取整或四舍五入（恰好一半时向上）
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum RoundingMode {
+    Floor,
+    Ceil,
+    Nearest,
+}
+
+impl RoundingMode {
+    // 按舍入方式计算 numerator / denominator（分母为 0 或溢出时报错）
+    pub fn divide(&self, numerator: u128, denominator: u128) -> Result<u128> {
+        let adjustment = match self {
+            RoundingMode::Floor => 0,
+            RoundingMode::Ceil => denominator.saturating_sub(1),
+            RoundingMode::Nearest => denominator / 2,
+        };
+        numerator
+            .checked_add(adjustment)
+            .and_then(|adjusted| adjusted.checked_div(denominator))
+            .ok_or_else(|| DexError::CalculationError.into())
+    }
+}
+
+// 定义成交价格模式枚举：按 maker 价格、双方价格中点或 taker 价格成交
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ExecutionPriceMode {
+    MakerPrice,
+    Midpoint,
+    TakerPrice,
+}
+
+impl ExecutionPriceMode {
+    // 计算成交价格；中点向下取整，始终落在双方价格之间
+    pub fn execution_price(&self, maker_price: u64, taker_price: u64) -> u64 {
+        match self {
+            ExecutionPriceMode::MakerPrice => maker_price,
+            ExecutionPriceMode::TakerPrice => taker_price,
+            ExecutionPriceMode::Midpoint => {
+                let (low, high) = if maker_price <= taker_price {
+                    (maker_price, taker_price)
+                } else {
+                    (taker_price, maker_price)
+                };
+                low + (high - low) / 2
+            }
+        }
+    }
+}
+
+// 定义订单方向枚举（买入/卖出）
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
 pub enum Side {
     Buy,
     Sell,
 }
 
+impl Side {
+    // 是否为买入方向
+    pub fn is_buy(&self) -> bool {
+        *self == Side::Buy
+    }
+
+    // 相反的方向
+    pub fn opposite(&self) -> Side {
+        match self {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        }
+    }
+
+    // 本方向订单的价格与对手方价格是否交叉：买价不低于卖价，或卖价不高于买价
+    pub fn crosses(&self, price: u64, opposing_price: u64) -> bool {
+        match self {
+            Side::Buy => price >= opposing_price,
+            Side::Sell => price <= opposing_price,
+        }
+    }
+
+    // 本方向订单撮合的对手盘：买单对应卖单列表，卖单对应买单列表
+    pub fn opposing_book<'a>(&self, orderbook: &'a mut Orderbook) -> &'a mut Vec<Order> {
+        match self {
+            Side::Buy => &mut orderbook.asks,
+            Side::Sell => &mut orderbook.bids,
+        }
+    }
+}
+
+// 定义订单类型枚举：限价单按价格撮合并挂出剩余部分，市价单吃单至数量耗尽且不挂单；
+// 止损限价单锁定资金后等待最新成交价触及触发价，再以限价单形式进入撮合
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum OrderType {
+    Limit,
+    Market,
+    StopLimit { trigger_price: u64, limit_price: u64 },
+}
+
+// 定义自成交防范方式枚举：DecrementAndCancel 将双方同时减少重叠数量（均不成交）；
+// CancelResting 撤销自己的整个挂单，taker 继续与后续挂单撮合
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SelfTradePrevention {
+    DecrementAndCancel,
+    CancelResting,
+}
+
+// 定义订单有效方式枚举：GTC 剩余部分挂单；IOC 尽量成交后撤销剩余部分；FOK 必须全部成交否则拒绝
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum TimeInForce {
+    GoodTillCancel,
+    ImmediateOrCancel,
+    FillOrKill,
+}
+
 // 定义交易事件，记录交易信息
 #[event]
 pub struct TradeEvent {
@@ -541,6 +5975,124 @@ pub struct TradeEvent {
     pub quote_mint: Pubkey, // 报价代币公钥
     pub quantity: u64,      // 交易数量
     pub price: u64,         // 交易价格
+    pub referrer: Option<Pubkey>, // 推荐人公钥（未使用推荐码时为空）
+    pub sequence_number: u64, // 成交序列号，对应 FillRecord 中的序列号
+    pub taker_order_id: u64,  // taker 订单 ID
+    pub maker_order_id: u64,  // 被成交的 maker 挂单 ID
+}
+
+// 定义挂单事件，订单剩余部分挂到订单簿时触发
+#[event]
+pub struct OrderPlacedEvent {
+    pub order_id: u64,          // 订单 ID
+    pub owner: Pubkey,          // 订单拥有者
+    pub side: Side,             // 订单方向
+    pub price: u64,             // 挂单价格
+    pub quantity: u64,          // 挂单数量（剩余数量）
+    pub original_quantity: u64, // 下单时的数量（大于挂单数量表示挂单前已部分成交）
+}
+
+// 定义撤单事件，记录退还的资金（买单为报价代币，卖单为基础代币）
+#[event]
+pub struct OrderCanceledEvent {
+    pub order_id: u64,       // 订单 ID
+    pub owner: Pubkey,       // 订单拥有者
+    pub side: Side,          // 订单方向
+    pub quantity: u64,       // 撤销的数量（撤销前的剩余数量）
+    pub refund_mint: Pubkey, // 退还的代币（买单为报价代币，卖单为基础代币）
+    pub refund_amount: u64,  // 退还的代币数量
+}
+
+// 定义挂单部分撤销事件，记录减少的数量、剩余数量与退还的资金
+#[event]
+pub struct OrderReducedEvent {
+    pub order_id: u64,           // 订单 ID
+    pub owner: Pubkey,           // 订单拥有者
+    pub side: Side,              // 订单方向
+    pub reduced_quantity: u64,   // 减少的数量
+    pub remaining_quantity: u64, // 减少后挂单的剩余数量
+    pub refund_mint: Pubkey,     // 退还的代币（买单为报价代币，卖单为基础代币）
+    pub refund_amount: u64,      // 退还的代币数量
+}
+
+// 定义改单事件，记录修改前后的价格与数量
+#[event]
+pub struct OrderModifiedEvent {
+    pub order_id: u64,     // 订单 ID
+    pub owner: Pubkey,     // 订单拥有者
+    pub side: Side,        // 订单方向
+    pub old_price: u64,    // 修改前的价格
+    pub new_price: u64,    // 修改后的价格
+    pub old_quantity: u64, // 修改前的剩余数量
+    pub new_quantity: u64, // 修改后的剩余数量
+}
+
+// 定义管理员强制撤单事件，记录退款去向（拥有者账户被冻结时转入托管账户）
+#[event]
+pub struct OrderForceCanceledEvent {
+    pub order_id: u64,          // 订单 ID
+    pub owner: Pubkey,          // 订单拥有者
+    pub side: Side,             // 订单方向
+    pub refund_amount: u64,     // 退还的代币数量
+    pub refund_account: Pubkey, // 接收退款的代币账户
+    pub escrowed: bool,         // 是否转入托管账户
+    pub admin: Pubkey,          // 执行撤单的管理员
+}
+
+// 定义 maker 订单成交事件，便于链下直接扣减挂单数量
+#[event]
+pub struct OrderFilledEvent {
+    pub order_id: u64,           // maker 订单 ID
+    pub owner: Pubkey,           // maker 公钥
+    pub side: Side,              // maker 订单方向
+    pub fill_quantity: u64,      // 本次成交数量
+    pub remaining_quantity: u64, // 成交后剩余数量
+    pub full_fill: bool,         // 是否全部成交（已从订单簿移除）
+}
+
+// 定义止损单下单事件，止损单在触发前不进入订单簿
+#[event]
+pub struct StopOrderPlacedEvent {
+    pub order_id: u64,      // 订单 ID
+    pub owner: Pubkey,      // 订单拥有者
+    pub side: Side,         // 订单方向
+    pub trigger_price: u64, // 触发价格
+    pub limit_price: u64,   // 触发后的限价
+    pub quantity: u64,      // 订单数量
+}
+
+// 定义止损单触发事件，记录触发时的最新成交价
+#[event]
+pub struct StopOrderTriggeredEvent {
+    pub order_id: u64,         // 订单 ID
+    pub owner: Pubkey,         // 订单拥有者
+    pub last_trade_price: u64, // 触发时的最新成交价
+}
+
+// 定义订单簿交叉事件：记录检查时两侧的最佳挂单
+#[event]
+pub struct BookCrossedEvent {
+    pub best_bid_order_id: u64, // 最佳买单 ID
+    pub best_bid_price: u64,    // 最佳买价
+    pub best_ask_order_id: u64, // 最佳卖单 ID
+    pub best_ask_price: u64,    // 最佳卖价
+}
+
+// 定义集合竞价事件：记录本次竞价的统一清算价与成交情况
+#[event]
+pub struct AuctionRunEvent {
+    pub clearing_price: u64, // 统一清算价
+    pub volume: u64,         // 本次成交的基础代币数量
+    pub fills: u32,          // 本次成交笔数
+}
+
+// 定义成交批次提交事件，记录新的 Merkle 根
+#[event]
+pub struct FillBatchSettledEvent {
+    pub batch_id: u64,             // 批次编号
+    pub root: [u8; 32],            // 该批次成交的 Merkle 根
+    pub fill_count: u32,           // 该批次包含的成交数
+    pub last_sequence_number: u64, // 该批次最后一笔成交的序列号
 }
 
 // 定义错误代码，处理可能出现的错误
@@ -554,4 +6106,269 @@ pub enum DexError {
     MakerAccountMismatch, // maker 账户不匹配
     #[msg("An error occurred during a mathematical calculation.")]
     CalculationError, // 计算错误
+    #[msg("The referral code is empty or too long.")]
+    InvalidReferralCode, // 推荐码无效
+    #[msg("A referral code was given but no referral account was provided.")]
+    ReferralCodeNotFound, // 缺少推荐码账户
+    #[msg("The referral account does not match the given referral code.")]
+    ReferralCodeMismatch, // 推荐码账户不匹配
+    #[msg("Only the orderbook admin can perform this action.")]
+    Unauthorized, // 非管理员
+    #[msg("The fill is not included in the committed fill root.")]
+    InvalidFillProof, // 成交证明无效
+    #[msg("The fill-or-kill order cannot be filled in full.")]
+    FillOrKillUnsatisfied, // FOK 订单无法全部成交
+    #[msg("The post-only order would cross the spread.")]
+    PostOnlyWouldCross, // post-only 订单会与对手盘成交
+    #[msg("Maker token account mint does not match the orderbook.")]
+    MakerMintMismatch, // maker 代币账户的 mint 不匹配
+    #[msg("Fee basis points must not exceed 10000.")]
+    InvalidFeeBps, // 手续费基点超出范围
+    #[msg("Order quantity is below the minimum order size.")]
+    OrderTooSmall, // 订单数量低于最小下单数量
+    #[msg("Order price is not a multiple of the price tick.")]
+    InvalidTickSize, // 价格不是最小变动单位的整数倍
+    #[msg("The order book side is full; grow the book to add capacity.")]
+    BookFull, // 订单簿该方向已满
+    #[msg("The modified price would cross the spread.")]
+    ModifyWouldCross, // 修改后的价格会与对手盘成交
+    #[msg("The vault received less than the required amount, likely due to a transfer fee.")]
+    TransferAmountShortfall, // 金库实际到账数量不足（转账手续费）
+    #[msg("The stop trigger price must be a positive multiple of the price tick.")]
+    InvalidTriggerPrice, // 止损触发价无效
+    #[msg("Too many untriggered stop orders on this orderbook.")]
+    TooManyStopOrders, // 未触发止损单已达上限
+    #[msg("Trading on this market is paused.")]
+    MarketPaused, // 市场已暂停交易
+    #[msg("The orderbook still has resting orders.")]
+    BookNotEmpty, // 订单簿仍有挂单
+    #[msg("The vault still holds tokens.")]
+    VaultNotEmpty, // 金库余额不为零
+    #[msg("Only good-till-cancel limit orders can defer matching.")]
+    InvalidDeferredOrder, // 不能延迟撮合的订单
+    #[msg("Order price and quantity must be greater than zero.")]
+    InvalidOrderParams, // 订单价格或数量为零
+    #[msg("The fee vault does not hold enough to pay the rebate.")]
+    InsufficientRebatePool, // 手续费金库余额不足以支付返佣
+    #[msg("The native leg's mint must be wrapped SOL.")]
+    InvalidNativeMint, // 原生 SOL 一方的代币不是 wSOL
+    #[msg("Cannot reduce an order by more than its remaining quantity.")]
+    ReduceExceedsQuantity, // 减少的数量超过挂单剩余数量
+    #[msg("The vault does not hold enough tokens to settle the trade.")]
+    InsufficientVaultLiquidity, // 金库余额不足以完成成交结算
+    #[msg("The base mint has too many decimals for price scaling.")]
+    UnsupportedDecimals, // 基础代币精度过大，无法启用价格缩放
+    #[msg("A maker account passed in remaining accounts is not a valid account.")]
+    InvalidMakerAccount, // maker 的代币账户或待领取余额账户无法反序列化
+    #[msg("Order notional (price * quantity) is below the market minimum.")]
+    NotionalTooSmall, // 订单名义金额低于下限
+    #[msg("Not enough maker accounts were supplied for the fills this order will make.")]
+    InsufficientMakerAccounts, // 传入的 maker 账户少于撮合所需
+    #[msg("The base mint and the quote mint must be different.")]
+    IdenticalMints, // 基础代币与报价代币相同
+    #[msg("The order price deviates too far from the oracle price.")]
+    PriceOutOfBand, // 订单价格超出预言机价格带
+    #[msg("The oracle price is too old.")]
+    StaleOracle, // 预言机价格过旧
+    #[msg("The oracle account is missing or does not match the configured oracle.")]
+    InvalidOracle, // 预言机账户缺失、不匹配或数据无效
+    #[msg("The vaults hold no surplus above the funds locked by resting orders.")]
+    NoSurplus, // 金库余额没有超出挂单锁定的资金
+    #[msg("The orderbook is in auction mode; crossing orders are matched by run_auction.")]
+    AuctionModeActive, // 集合竞价模式下不能连续撮合
+    #[msg("The orderbook is not in auction mode.")]
+    AuctionModeInactive, // 连续撮合模式下不能运行集合竞价
+    #[msg("The owner already has the maximum number of open orders on this orderbook.")]
+    TooManyOpenOrders, // 拥有者的挂单数量已达上限
+    #[msg("Post-only order with must_improve does not beat the current best price.")]
+    MustImprove, // 要求改善最优价的 post-only 订单未优于当前最优价
+    #[msg("The fill batch has not been committed or its root is no longer retained.")]
+    UnknownFillBatch, // 成交批次尚未提交或其根已被覆盖
+    #[msg("The native escrow must be a wSOL account owned by the owner's claimable balance.")]
+    InvalidNativeEscrow, // 托管 wSOL 账户缺失、mint 不符或 authority 不是拥有者的待领取余额账户
+    #[msg("The account to migrate is not an orderbook of this program for the given mints.")]
+    InvalidMigrationAccount, // 待迁移的账户不属于本程序、判别符不符或不是两个代币对应的 PDA
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 构造一个只有卖单的订单簿，其余参数取不限制撮合的默认值
+    fn orderbook_with_asks(asks: Vec<Order>) -> Orderbook {
+        Orderbook {
+            base_mint: Pubkey::new_unique(),
+            quote_mint: Pubkey::new_unique(),
+            bids: Vec::new(),
+            asks,
+            order_id_counter: 0,
+            min_fill_notional: 0,
+            admin: Pubkey::new_unique(),
+            sequence_number: 0,
+            pending_fills: Vec::new(),
+            fill_roots: [[0; 32]; MAX_FILL_ROOTS],
+            fill_batch_id: 0,
+            execution_price_mode: ExecutionPriceMode::MakerPrice,
+            admin_log: Vec::new(),
+            maker_fee_bps: 0,
+            taker_fee_bps: 0,
+            min_base_quantity: 1,
+            price_tick: 1,
+            max_orders: INITIAL_MAX_ORDERS,
+            stops: Vec::new(),
+            last_trade_price: 0,
+            paused: false,
+            rebate_bps: 0,
+            rebates_owed: 0,
+            claimable_base: 0,
+            claimable_quote: 0,
+            native_leg: NativeLeg::None,
+            base_decimals: 0,
+            quote_decimals: 0,
+            price_is_scaled: false,
+            min_quote_notional: 0,
+            oracle: None,
+            max_deviation_bps: 0,
+            oracle_max_age: 0,
+            rolling_volume_base: 0,
+            rolling_volume_quote: 0,
+            volume_window_start: 0,
+            auction_mode: false,
+            max_orders_per_owner: 0,
+            fee_rounding: RoundingMode::Ceil,
+            base_vault: Pubkey::new_unique(),
+            quote_vault: Pubkey::new_unique(),
+            version: 0,
+        }
+    }
+
+    // 构造一个卖单，锁定的基础代币为 locked_base
+    fn ask(order_id: u64, price: u64, quantity: u64, locked_base: u64) -> Order {
+        Order {
+            owner: Pubkey::new_unique(),
+            price,
+            quantity,
+            order_id,
+            frozen: false,
+            oco_group_id: 0,
+            expiry_ts: 0,
+            locked_quote: 0,
+            locked_base,
+            display_size: 0,
+            display_quantity: 0,
+            settlement_authority: None,
+            original_quantity: quantity,
+        }
+    }
+
+    // 数量为 0 的挂单在撮合时被移除并退还残留的锁定资金，估算时必须计入其 maker 账户，
+    // 且不影响后续挂单的成交
+    #[test]
+    fn estimate_fills_counts_zero_quantity_makers() {
+        let orderbook = orderbook_with_asks(vec![ask(1, 10, 0, 5), ask(2, 10, 4, 4)]);
+        let taker = Order {
+            owner: Pubkey::new_unique(),
+            locked_quote: 40,
+            ..ask(3, 10, 4, 0)
+        };
+        let estimate = estimate_fills(
+            &orderbook,
+            &Side::Buy,
+            &taker,
+            OrderType::Limit,
+            0,
+            false,
+            SelfTradePrevention::CancelResting,
+            0,
+            0,
+        )
+        .unwrap();
+        assert_eq!(estimate.quantity, 4);
+        assert_eq!(estimate.makers, 2);
+        assert_eq!(estimate.quote, 40);
+    }
+
+    // 减少的数量超过订单剩余数量时报错，订单的数量与锁定资金保持不变
+    #[test]
+    fn reduce_rejects_quantity_above_remaining() {
+        for side in [Side::Buy, Side::Sell] {
+            let mut order = Order {
+                locked_quote: 40,
+                ..ask(1, 10, 4, 4)
+            };
+            let before = order.try_to_vec().unwrap();
+            let result = order.reduce(&side, 5, 1);
+            assert_eq!(result.unwrap_err(), DexError::CalculationError.into());
+            assert_eq!(order.try_to_vec().unwrap(), before);
+        }
+    }
+
+    // 按首个版本的布局写入订单（owner、price、quantity、order_id）
+    fn write_legacy_orders(data: &mut Vec<u8>, orders: &[(Pubkey, u64, u64, u64)]) {
+        data.extend_from_slice(&(orders.len() as u32).to_le_bytes());
+        for (owner, price, quantity, order_id) in orders {
+            data.extend_from_slice(owner.as_ref());
+            data.extend_from_slice(&price.to_le_bytes());
+            data.extend_from_slice(&quantity.to_le_bytes());
+            data.extend_from_slice(&order_id.to_le_bytes());
+        }
+    }
+
+    // 首个版本的订单簿账户数据迁移后保留代币对、挂单与订单 ID 计数器，挂单补记锁定资金，
+    // 其余字段取显式默认值，并能以当前布局写回账户
+    #[test]
+    fn legacy_orderbook_migrates_to_current_layout() {
+        let (base_mint, quote_mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (buyer, seller) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut data = Orderbook::DISCRIMINATOR.to_vec();
+        data.extend_from_slice(base_mint.as_ref());
+        data.extend_from_slice(quote_mint.as_ref());
+        write_legacy_orders(&mut data, &[(buyer, 10, 3, 1)]);
+        write_legacy_orders(&mut data, &[(seller, 12, 2, 2), (seller, 15, 1, 3)]);
+        data.extend_from_slice(&3u64.to_le_bytes());
+        data.resize(LegacyOrderbook::SPACE, 0);
+
+        let orderbook_key = Pubkey::new_unique();
+        let admin = Pubkey::new_unique();
+        let mut orderbook = LegacyOrderbook::deserialize(&mut &data[8..])
+            .unwrap()
+            .into_current(orderbook_key, admin, 6, 9)
+            .unwrap();
+        orderbook.version = ORDERBOOK_VERSION;
+
+        let mut migrated = vec![0; Orderbook::space(orderbook.max_orders)];
+        orderbook.try_serialize(&mut &mut migrated[..]).unwrap();
+        let orderbook = Orderbook::try_deserialize(&mut &migrated[..]).unwrap();
+        assert_eq!(orderbook.base_mint, base_mint);
+        assert_eq!(orderbook.quote_mint, quote_mint);
+        assert_eq!(orderbook.order_id_counter, 3);
+        assert_eq!(orderbook.admin, admin);
+        assert_eq!(orderbook.version, ORDERBOOK_VERSION);
+        assert_eq!(
+            (orderbook.price_tick, orderbook.max_orders),
+            (1, INITIAL_MAX_ORDERS)
+        );
+        assert_eq!((orderbook.maker_fee_bps, orderbook.taker_fee_bps), (0, 0));
+        assert_eq!((orderbook.base_decimals, orderbook.quote_decimals), (6, 9));
+        assert_eq!(
+            orderbook.base_vault,
+            vault_pda(b"base_vault", &orderbook_key)
+        );
+        assert_eq!(
+            orderbook.quote_vault,
+            vault_pda(b"quote_vault", &orderbook_key)
+        );
+
+        assert_eq!(orderbook.bids.len(), 1);
+        let bid = orderbook.bids[0];
+        assert_eq!((bid.owner, bid.price, bid.quantity), (buyer, 10, 3));
+        assert_eq!((bid.locked_quote, bid.locked_base), (30, 0));
+        let asks: Vec<(u64, u64, u64)> = orderbook
+            .asks
+            .iter()
+            .map(|ask| (ask.order_id, ask.quantity, ask.locked_base))
+            .collect();
+        assert_eq!(asks, vec![(2, 2, 2), (3, 1, 1)]);
+        assert_eq!(vault_obligations(&orderbook).unwrap(), (3, 30));
+    }
 }
@@ -11,15 +11,30 @@ import {
   SystemProgram,
   Connection,
   LAMPORTS_PER_SOL,
+  Transaction,
+  sendAndConfirmTransaction,
 } from "@solana/web3.js";
 // 导入 SPL Token 程序的常量和方法，用于代币账户的创建、铸造和查询
 import {
   TOKEN_PROGRAM_ID,
+  TOKEN_2022_PROGRAM_ID,
+  ExtensionType,
   createMint,
   createAccount,
   mintTo,
   getAccount,
+  getMintLen,
+  createInitializeMintInstruction,
+  createInitializeTransferFeeConfigInstruction,
+  createWrappedNativeAccount,
+  NATIVE_MINT,
+  transfer,
+  closeAccount,
+  createAssociatedTokenAccountIdempotent,
+  freezeAccount,
 } from "@solana/spl-token";
+// 导入 SHA-256，用于在测试中复现成交 Merkle 树
+import { createHash } from "crypto";
 
 // 定义测试套件，命名为 "orderbook"
 describe("orderbook", () => {
@@ -91,6 +106,216 @@ describe("orderbook", () => {
     return new BN(price);
   };
 
+  // 交易者：密钥对及其基础/报价代币账户
+  type Trader = { keypair: Keypair; base: PublicKey; quote: PublicKey };
+  // 市场：一组独立的代币、订单簿及金库，供各场景互不干扰地使用
+  type Market = {
+    baseMint: PublicKey;
+    quoteMint: PublicKey;
+    orderbook: PublicKey;
+    baseVault: PublicKey;
+    quoteVault: PublicKey;
+    baseFeeVault: PublicKey;
+    quoteFeeVault: PublicKey;
+  };
+
+  // 辅助函数：从 payer 向账户转入 SOL，用于支付首次下单时创建待领取余额账户的租金
+  const fundSol = async (owner: PublicKey, lamports = LAMPORTS_PER_SOL / 10) => {
+    const tx = new Transaction().add(SystemProgram.transfer({ fromPubkey: payer.publicKey, toPubkey: owner, lamports }));
+    await sendAndConfirmTransaction(connection, tx, [payer.payer]);
+  };
+
+  // 辅助函数：交易者在订单簿上的待领取余额账户（PDA），首次下单时创建
+  const claimablePda = (orderbook: PublicKey, owner: PublicKey): PublicKey =>
+    PublicKey.findProgramAddressSync([Buffer.from("claimable"), orderbook.toBuffer(), owner.toBuffer()], program.programId)[0];
+
+  // 挂单的结算方：代币账户属于该交易者，orderOwner 为设置了结算方的挂单的拥有者（默认即该交易者）
+  type Settlement = Trader & { orderOwner?: PublicKey };
+
+  // 辅助函数：一个挂单在 remaining_accounts 中的一组账户（结算方的基础/报价代币账户及拥有者的待领取余额账户）
+  const makerAccounts = (orderbook: PublicKey, trader: Settlement) => [
+    { pubkey: trader.base, isSigner: false, isWritable: true },
+    { pubkey: trader.quote, isSigner: false, isWritable: true },
+    { pubkey: claimablePda(orderbook, trader.orderOwner ?? trader.keypair.publicKey), isSigner: false, isWritable: true },
+  ];
+
+  // 辅助函数：创建交易者并为其铸造基础代币和报价代币
+  const createTrader = async (market: Market): Promise<Trader> => {
+    const keypair = Keypair.generate();
+    await fundSol(keypair.publicKey);
+    const base = await createAccount(connection, payer.payer, market.baseMint, keypair.publicKey);
+    const quote = await createAccount(connection, payer.payer, market.quoteMint, keypair.publicKey);
+    await mintTo(connection, payer.payer, market.baseMint, base, payer.payer, toTokenAmount(1000, BASE_DECIMALS).toNumber());
+    await mintTo(connection, payer.payer, market.quoteMint, quote, payer.payer, toTokenAmount(100000, QUOTE_DECIMALS).toNumber());
+    return { keypair, base, quote };
+  };
+
+  // 初始化订单簿的可选参数
+  type MarketOptions = {
+    minFillNotional?: BN; // 单笔最小成交额
+    executionPriceMode?: { makerPrice: {} } | { midpoint: {} } | { takerPrice: {} }; // 成交价格模式
+    makerFeeBps?: number; // maker 手续费（基点）
+    takerFeeBps?: number; // taker 手续费（基点）
+    minBaseQuantity?: BN; // 最小下单数量
+    priceTick?: BN; // 价格最小变动单位
+    nativeQuote?: boolean; // 报价代币为原生 SOL（wSOL）
+    priceIsScaled?: boolean; // 价格按每个完整基础代币计价
+    maxOrdersPerOwner?: number; // 每个拥有者的挂单数量上限
+    ataVaults?: boolean; // 使用订单簿的关联代币账户（ATA）作为金库
+    freezable?: boolean; // 代币设置冻结权限（支付者），可冻结交易者的代币账户
+  };
+
+  // 辅助函数：创建全新的代币对并初始化订单簿
+  const setupMarket = async (options: MarketOptions = {}): Promise<Market> => {
+    const freezeAuthority = options.freezable ? payer.publicKey : null;
+    const marketBaseMint = await createMint(connection, payer.payer, payer.publicKey, freezeAuthority, BASE_DECIMALS);
+    const marketQuoteMint = options.nativeQuote
+      ? NATIVE_MINT
+      : await createMint(connection, payer.payer, payer.publicKey, freezeAuthority, QUOTE_DECIMALS);
+    const [orderbook] = PublicKey.findProgramAddressSync([Buffer.from("orderbook"), marketBaseMint.toBuffer(), marketQuoteMint.toBuffer()], program.programId);
+    // ATA 金库需在初始化之前创建（订单簿 PDA 不在曲线上）
+    const [baseVault, quoteVault] = options.ataVaults
+      ? await Promise.all(
+          [marketBaseMint, marketQuoteMint].map((mint) =>
+            createAssociatedTokenAccountIdempotent(connection, payer.payer, mint, orderbook, {}, TOKEN_PROGRAM_ID, undefined, true)
+          )
+        )
+      : [
+          PublicKey.findProgramAddressSync([Buffer.from("base_vault"), orderbook.toBuffer()], program.programId)[0],
+          PublicKey.findProgramAddressSync([Buffer.from("quote_vault"), orderbook.toBuffer()], program.programId)[0],
+        ];
+    const [baseFeeVault] = PublicKey.findProgramAddressSync([Buffer.from("base_fee_vault"), orderbook.toBuffer()], program.programId);
+    const [quoteFeeVault] = PublicKey.findProgramAddressSync([Buffer.from("quote_fee_vault"), orderbook.toBuffer()], program.programId);
+
+    await (options.ataVaults ? program.methods.initializeWithVaults : program.methods.initialize)(
+      options.minFillNotional ?? new BN(0),
+      options.executionPriceMode ?? { makerPrice: {} },
+      options.makerFeeBps ?? 0,
+      options.takerFeeBps ?? 0,
+      options.minBaseQuantity ?? new BN(0),
+      options.priceTick ?? new BN(1),
+      options.nativeQuote ? { quote: {} } : { none: {} },
+      options.priceIsScaled ?? false,
+      options.maxOrdersPerOwner ?? 0
+    )
+      .accountsPartial({
+        orderbook,
+        baseMint: marketBaseMint,
+        quoteMint: marketQuoteMint,
+        baseVault,
+        quoteVault,
+        baseFeeVault,
+        quoteFeeVault,
+        payer: payer.publicKey,
+        systemProgram: SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      })
+      .rpc();
+
+    return { baseMint: marketBaseMint, quoteMint: marketQuoteMint, orderbook, baseVault, quoteVault, baseFeeVault, quoteFeeVault };
+  };
+
+  // 下单的可选参数
+  type OrderOptions = {
+    makers?: Settlement[]; // 依次撮合的 maker，按撮合顺序传入其账户
+    referralCode?: string | null; // 推荐码
+    referral?: PublicKey | null; // 推荐码账户
+    ocoGroupId?: BN; // OCO 组 ID
+    market?: boolean; // 是否为市价单
+    maxQuote?: BN; // 市价买单的报价代币上限
+    timeInForce?: "gtc" | "ioc" | "fok"; // 订单有效方式，默认 GTC
+    postOnly?: boolean; // 是否只做 maker
+    expiryTs?: BN; // 过期时间戳，默认 0（永不过期）
+    decrementAndCancel?: boolean; // 自成交防范方式，默认撤销自己的挂单
+    stop?: { triggerPrice: BN; limitPrice: BN }; // 止损限价单的触发价与限价
+    worstPrice?: BN; // 滑点保护价格，默认 0（不限制）
+    deferMatching?: boolean; // 是否只挂单、留待 match_orders 撮合
+    displayQuantity?: BN; // 冰山单每次显示的数量，默认 0（全部显示）
+    quoteBudget?: boolean; // 买单的 quantity 表示报价代币预算
+    oracle?: PublicKey | null; // 预言机价格账户
+    settlementAuthority?: PublicKey | null; // 挂单成交所得的接收方
+    restRemainder?: boolean; // GTC 限价单未成交部分是否挂单，默认挂单
+    mustImprove?: boolean; // post-only 订单必须优于同方向当前最优价
+  };
+
+  // 辅助函数：以指定交易者身份下单，返回交易签名
+  const placeOrder = async (
+    market: Market,
+    trader: Trader,
+    side: "buy" | "sell",
+    price: BN,
+    quantity: BN,
+    options: OrderOptions = {}
+  ): Promise<string> => {
+    const remainingAccounts = (options.makers ?? []).flatMap((maker) => makerAccounts(market.orderbook, maker));
+    return program.methods
+      .placeOrder(
+        side === "buy" ? { buy: {} } : { sell: {} },
+        price,
+        quantity,
+        options.referralCode ?? null,
+        options.ocoGroupId ?? new BN(0),
+        options.stop
+          ? { stopLimit: options.stop }
+          : options.market
+          ? { market: {} }
+          : { limit: {} },
+        options.maxQuote ?? new BN(0),
+        options.timeInForce === "ioc"
+          ? { immediateOrCancel: {} }
+          : options.timeInForce === "fok"
+          ? { fillOrKill: {} }
+          : { goodTillCancel: {} },
+        options.postOnly ?? false,
+        options.expiryTs ?? new BN(0),
+        options.decrementAndCancel ? { decrementAndCancel: {} } : { cancelResting: {} },
+        options.worstPrice ?? new BN(0),
+        options.deferMatching ?? false,
+        options.displayQuantity ?? new BN(0),
+        options.quoteBudget ?? false,
+        options.settlementAuthority ?? null,
+        options.restRemainder ?? true,
+        options.mustImprove ?? false
+      )
+      .accountsPartial({
+        orderbook: market.orderbook,
+        owner: trader.keypair.publicKey,
+        ownerBaseTokenAccount: trader.base,
+        ownerQuoteTokenAccount: trader.quote,
+        baseVault: market.baseVault,
+        quoteVault: market.quoteVault,
+        baseFeeVault: market.baseFeeVault,
+        quoteFeeVault: market.quoteFeeVault,
+        referral: options.referral ?? null,
+        oracle: options.oracle ?? null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .remainingAccounts(remainingAccounts)
+      .signers([trader.keypair])
+      .rpc({ commitment: "confirmed" });
+  };
+
+  // 辅助函数：解析交易日志中的程序事件
+  const getEvents = async (signature: string) => {
+    const tx = await connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    return Array.from(parser.parseLogs(tx?.meta?.logMessages ?? []));
+  };
+
+  // 辅助函数：解析 place_order 通过返回数据给出的撮合结果
+  const getPlaceOrderResult = async (signature: string) => {
+    const tx = await connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const [data] = tx!.meta!.returnData!.data;
+    return program.coder.types.decode("placeOrderResult", Buffer.from(data, "base64"));
+  };
+
   // 定义测试用例：执行完整的订单簿生命周期，设置 60 秒超时
   it(
     "Executes the full orderbook lifecycle",
@@ -136,7 +361,7 @@ describe("orderbook", () => {
 
       // 调用程序的 initialize 方法，初始化订单簿
       await program.methods
-        .initialize()
+        .initialize(new BN(0), { makerPrice: {} }, 0, 0, new BN(0), new BN(1), { none: {} }, false, 0) // 不限制单笔最小成交额，按 maker 价格成交
         // 指定所需的账户
         .accounts({
           orderbook: orderbookPDA, // 订单簿 PDA
@@ -173,7 +398,7 @@ describe("orderbook", () => {
 
       // 调用 placeOrder 方法，下买单
       await program.methods
-        .placeOrder({ buy: {} }, buyPrice1, buyQuantity1) // 指定买单、价格和数量
+        .placeOrder({ buy: {} }, buyPrice1, buyQuantity1, null, new BN(0), { limit: {} }, new BN(0), { goodTillCancel: {} }, false, new BN(0), { cancelResting: {} }, new BN(0), false, new BN(0), false, null, true, false) // 指定买单、价格和数量
         .accounts({
           orderbook: orderbookPDA, // 订单簿 PDA
           owner: user1.publicKey, // 买单拥有者（user1）
@@ -215,7 +440,7 @@ describe("orderbook", () => {
       const user2QuoteBalanceBefore = await getTokenBalance(user2QuoteTokenAccount);
       // 调用 placeOrder 方法，下卖单，动态传递 user1 的账户用于撮合
       await program.methods
-        .placeOrder({ sell: {} }, sellPrice1, sellQuantity1) // 指定卖单、价格和数量
+        .placeOrder({ sell: {} }, sellPrice1, sellQuantity1, null, new BN(0), { limit: {} }, new BN(0), { goodTillCancel: {} }, false, new BN(0), { cancelResting: {} }, new BN(0), false, new BN(0), false, null, true, false) // 指定卖单、价格和数量
         .accounts({
           orderbook: orderbookPDA, // 订单簿 PDA
           owner: user2.publicKey, // 卖单拥有者（user2）
@@ -229,6 +454,7 @@ describe("orderbook", () => {
         .remainingAccounts([
           { pubkey: user1BaseTokenAccount, isSigner: false, isWritable: true }, // user1 的基础代币账户
           { pubkey: user1QuoteTokenAccount, isSigner: false, isWritable: true }, // user1 的报价代币账户
+          { pubkey: claimablePda(orderbookPDA, user1.publicKey), isSigner: false, isWritable: true }, // user1 的待领取余额账户
         ])
         .signers([user2]) // user2 签名交易
         .rpc(); // 执行交易
@@ -259,7 +485,7 @@ describe("orderbook", () => {
       const buyQuantity2 = toTokenAmount(20, BASE_DECIMALS);
       // 下第二个买单
       await program.methods
-        .placeOrder({ buy: {} }, buyPrice2, buyQuantity2)
+        .placeOrder({ buy: {} }, buyPrice2, buyQuantity2, null, new BN(0), { limit: {} }, new BN(0), { goodTillCancel: {} }, false, new BN(0), { cancelResting: {} }, new BN(0), false, new BN(0), false, null, true, false)
         .accounts({
           orderbook: orderbookPDA, // 订单簿 PDA
           owner: user1.publicKey, // 买单拥有者（user1）
@@ -278,7 +504,7 @@ describe("orderbook", () => {
       const sellQuantity2 = toTokenAmount(5, BASE_DECIMALS);
       // 下第二个卖单，部分撮合买单
       await program.methods
-        .placeOrder({ sell: {} }, sellPrice2, sellQuantity2)
+        .placeOrder({ sell: {} }, sellPrice2, sellQuantity2, null, new BN(0), { limit: {} }, new BN(0), { goodTillCancel: {} }, false, new BN(0), { cancelResting: {} }, new BN(0), false, new BN(0), false, null, true, false)
         .accounts({
           orderbook: orderbookPDA, // 订单簿 PDA
           owner: user2.publicKey, // 卖单拥有者（user2）
@@ -292,6 +518,7 @@ describe("orderbook", () => {
         .remainingAccounts([
           { pubkey: user1BaseTokenAccount, isSigner: false, isWritable: true },
           { pubkey: user1QuoteTokenAccount, isSigner: false, isWritable: true },
+          { pubkey: claimablePda(orderbookPDA, user1.publicKey), isSigner: false, isWritable: true },
         ])
         .signers([user2]) // user2 签名
         .rpc(); // 执行交易
@@ -321,7 +548,7 @@ describe("orderbook", () => {
 
       // 调用 cancelOrder 方法，取消剩余买单
       await program.methods
-        .cancelOrder(orderIdToCancel)
+        .cancelOrder(orderIdToCancel, { buy: {} })
         .accounts({
           orderbook: orderbookPDA, // 订单簿 PDA
           owner: user1.publicKey, // 订单拥有者（user1）
@@ -358,7 +585,7 @@ describe("orderbook", () => {
       // 测试取消不存在的订单（ID 9999），预期抛出 OrderNotFound 错误
       await expect(
         program.methods
-          .cancelOrder(new BN(9999))
+          .cancelOrder(new BN(9999), { buy: {} })
           .accounts({
             orderbook: orderbookPDA,
             owner: user1.publicKey,
@@ -376,7 +603,7 @@ describe("orderbook", () => {
       const sellPrice3 = toPriceAmount(15);
       const sellQuantity3 = toTokenAmount(2, BASE_DECIMALS);
       await program.methods
-        .placeOrder({ sell: {} }, sellPrice3, sellQuantity3)
+        .placeOrder({ sell: {} }, sellPrice3, sellQuantity3, null, new BN(0), { limit: {} }, new BN(0), { goodTillCancel: {} }, false, new BN(0), { cancelResting: {} }, new BN(0), false, new BN(0), false, null, true, false)
         .accounts({
           orderbook: orderbookPDA,
           owner: user2.publicKey,
@@ -396,7 +623,7 @@ describe("orderbook", () => {
       // 测试 user1 尝试取消 user2 的订单，预期抛出 OrderNotOwned 错误
       await expect(
         program.methods
-          .cancelOrder(user2OrderId)
+          .cancelOrder(user2OrderId, { sell: {} })
           .accounts({
             orderbook: orderbookPDA,
             owner: user1.publicKey,
@@ -412,4 +639,3357 @@ describe("orderbook", () => {
     },
     60000 // 设置测试超时时间为 60 秒
   );
-});
\ No newline at end of file
+
+  // 测试推荐码：注册推荐码后，使用推荐码下单应解析出正确的推荐人
+  it(
+    "Resolves a registered referral code to its referrer",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+
+      // 注册推荐码（payer 作为推荐人）
+      const code = `ref${Date.now() % 100000}`;
+      const [referralPDA] = PublicKey.findProgramAddressSync([Buffer.from("referral"), Buffer.from(code)], program.programId);
+      await program.methods
+        .registerReferral(code)
+        .accounts({ referrer: payer.publicKey })
+        .rpc();
+
+      const referralAccount = await program.account.referralCode.fetch(referralPDA);
+      expect(referralAccount.referrer.equals(payer.publicKey)).toBe(true);
+      expect(referralAccount.code).toBe(code);
+
+      // 同一推荐码不能重复注册
+      await expect(
+        program.methods.registerReferral(code).accounts({ referrer: payer.publicKey }).rpc()
+      ).rejects.toThrow();
+
+      // maker 挂卖单，taker 使用推荐码买入
+      await placeOrder(market, maker, "sell", toPriceAmount(10), toTokenAmount(1, BASE_DECIMALS));
+      const signature = await placeOrder(market, taker, "buy", toPriceAmount(10), toTokenAmount(1, BASE_DECIMALS), {
+        makers: [maker],
+        referralCode: code,
+        referral: referralPDA,
+      });
+
+      const trades = (await getEvents(signature)).filter((e) => e.name === "tradeEvent");
+      expect(trades.length).toBe(1);
+      expect(trades[0].data.referrer.equals(payer.publicKey)).toBe(true);
+    },
+    60000
+  );
+
+  // 测试单笔最小成交额：成交额不足的 maker 被跳过并继续挂单
+  it(
+    "Skips maker orders whose fill would be below min_fill_notional",
+    async () => {
+      const price = toPriceAmount(10);
+      // 单笔成交额下限：价格 10 × 5 个基础代币
+      const market = await setupMarket({ minFillNotional: price.mul(toTokenAmount(5, BASE_DECIMALS)) });
+      const smallMaker = await createTrader(market);
+      const largeMaker = await createTrader(market);
+      const taker = await createTrader(market);
+
+      // 同一价格挂出一小一大两个卖单
+      await placeOrder(market, smallMaker, "sell", price, toTokenAmount(2, BASE_DECIMALS));
+      await placeOrder(market, largeMaker, "sell", price, toTokenAmount(10, BASE_DECIMALS));
+
+      // taker 买入 12 个：大单成交额达标而成交，小单成交额不足而被跳过
+      const takerBaseBefore = await getTokenBalance(taker.base);
+      const signature = await placeOrder(market, taker, "buy", price, toTokenAmount(12, BASE_DECIMALS), {
+        makers: [largeMaker],
+      });
+
+      const trades = (await getEvents(signature)).filter((e) => e.name === "tradeEvent");
+      expect(trades.length).toBe(1);
+      expect(trades[0].data.maker.equals(largeMaker.keypair.publicKey)).toBe(true);
+      expect(trades[0].data.quantity.eq(toTokenAmount(10, BASE_DECIMALS))).toBe(true);
+      expect((await getTokenBalance(taker.base)) - takerBaseBefore).toBe(BigInt(toTokenAmount(10, BASE_DECIMALS).toString()));
+
+      // 小卖单仍完整挂在订单簿上，taker 剩余部分成为买单
+      const orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.asks.length).toBe(1);
+      expect(orderbookAccount.asks[0].owner.equals(smallMaker.keypair.publicKey)).toBe(true);
+      expect(orderbookAccount.asks[0].quantity.eq(toTokenAmount(2, BASE_DECIMALS))).toBe(true);
+      expect(orderbookAccount.bids.length).toBe(1);
+      expect(orderbookAccount.bids[0].quantity.eq(toTokenAmount(2, BASE_DECIMALS))).toBe(true);
+    },
+    60000
+  );
+
+  // 测试冻结订单：被冻结的挂单在撮合中被跳过，解冻后恢复撮合
+  it(
+    "Skips frozen orders in matching until they are unfrozen",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      const price = toPriceAmount(10);
+      const quantity = toTokenAmount(5, BASE_DECIMALS);
+
+      await placeOrder(market, maker, "sell", price, quantity);
+      let orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      const makerOrderId = orderbookAccount.asks[0].orderId;
+
+      // 非管理员无法冻结订单
+      await expect(
+        program.methods
+          .freezeOrder(makerOrderId)
+          .accounts({ orderbook: market.orderbook, admin: taker.keypair.publicKey })
+          .signers([taker.keypair])
+          .rpc()
+      ).rejects.toThrow(/Unauthorized/);
+
+      // 管理员（payer）冻结卖单
+      await program.methods.freezeOrder(makerOrderId).accounts({ orderbook: market.orderbook, admin: payer.publicKey }).rpc();
+      orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.asks[0].frozen).toBe(true);
+
+      // 冻结期间买单不会与其成交
+      let signature = await placeOrder(market, taker, "buy", price, quantity);
+      expect((await getEvents(signature)).filter((e) => e.name === "tradeEvent").length).toBe(0);
+      orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.asks.length).toBe(1);
+
+      // 解冻后卖单恢复撮合
+      await program.methods.unfreezeOrder(makerOrderId).accounts({ orderbook: market.orderbook, admin: payer.publicKey }).rpc();
+      signature = await placeOrder(market, taker, "buy", price, quantity, { makers: [maker] });
+      expect((await getEvents(signature)).filter((e) => e.name === "tradeEvent").length).toBe(1);
+      orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.asks.length).toBe(0);
+    },
+    60000
+  );
+
+  // 测试 OCO：同组订单中一个成交后，其余订单自动撤销并退款
+  it(
+    "Cancels the rest of an OCO group when one order fills",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      const ocoGroupId = new BN(7);
+      const quantity = toTokenAmount(5, BASE_DECIMALS);
+      const bidPrice = toPriceAmount(9);
+      const askPrice = toPriceAmount(11);
+
+      // maker 在同一 OCO 组中同时挂出买单和卖单
+      await placeOrder(market, maker, "buy", bidPrice, quantity, { ocoGroupId });
+      await placeOrder(market, maker, "sell", askPrice, quantity, { ocoGroupId });
+      const makerQuoteBefore = await getTokenBalance(maker.quote);
+
+      // taker 买入吃掉卖单
+      await placeOrder(market, taker, "buy", askPrice, quantity, { makers: [maker] });
+
+      // 同组买单被撤销，maker 收到卖出所得及买单锁定资金的退款
+      const orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.bids.length).toBe(0);
+      expect(orderbookAccount.asks.length).toBe(0);
+      const expectedQuote = askPrice.mul(quantity).add(bidPrice.mul(quantity));
+      expect((await getTokenBalance(maker.quote)) - makerQuoteBefore).toBe(BigInt(expectedQuote.toString()));
+      expect(await getTokenBalance(market.quoteVault)).toBe(BigInt(0));
+    },
+    60000
+  );
+
+  // 测试账户信息查询：返回的大小、租金与链上账户实际分配一致
+  it(
+    "Reports the orderbook account size and rent",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      await placeOrder(market, maker, "buy", toPriceAmount(9), toTokenAmount(1, BASE_DECIMALS));
+      await placeOrder(market, maker, "sell", toPriceAmount(11), toTokenAmount(1, BASE_DECIMALS));
+
+      const info = await program.methods.getAccountInfo().accounts({ orderbook: market.orderbook }).view();
+      const accountInfo = await connection.getAccountInfo(market.orderbook);
+
+      expect(info.dataLen.toNumber()).toBe(accountInfo!.data.length);
+      expect(info.lamports.toNumber()).toBe(accountInfo!.lamports);
+      expect(info.rentExemptMinimum.toNumber()).toBe(await connection.getMinimumBalanceForRentExemption(accountInfo!.data.length));
+    },
+    60000
+  );
+
+  // 测试成交 Merkle 根：批量提交后可验证包含的成交，拒绝未包含的成交
+  it(
+    "Commits fills into a Merkle root and verifies inclusion proofs",
+    async () => {
+      const market = await setupMarket();
+      const maker1 = await createTrader(market);
+      const maker2 = await createTrader(market);
+      const taker = await createTrader(market);
+      const price = toPriceAmount(10);
+      const quantity = toTokenAmount(1, BASE_DECIMALS);
+
+      // 两笔独立成交，从事件中取得成交记录
+      const fills = [];
+      for (const maker of [maker1, maker2]) {
+        await placeOrder(market, maker, "sell", price, quantity);
+        const signature = await placeOrder(market, taker, "buy", price, quantity, { makers: [maker] });
+        const trade = (await getEvents(signature)).find((e) => e.name === "tradeEvent")!;
+        fills.push({
+          sequenceNumber: trade.data.sequenceNumber,
+          taker: trade.data.taker,
+          maker: trade.data.maker,
+          price: trade.data.price,
+          quantity: trade.data.quantity,
+        });
+      }
+
+      // 叶子哈希：0x00 || borsh(FillRecord)；内部节点：0x01 || 排序后的两个子节点
+      const sha256 = (...parts: Buffer[]) => createHash("sha256").update(Buffer.concat(parts)).digest();
+      const leafHash = (fill: (typeof fills)[number]) =>
+        sha256(
+          Buffer.from([0]),
+          fill.sequenceNumber.toArrayLike(Buffer, "le", 8),
+          fill.taker.toBuffer(),
+          fill.maker.toBuffer(),
+          fill.price.toArrayLike(Buffer, "le", 8),
+          fill.quantity.toArrayLike(Buffer, "le", 8)
+        );
+      const hashPair = (a: Buffer, b: Buffer) => (Buffer.compare(a, b) <= 0 ? sha256(Buffer.from([1]), a, b) : sha256(Buffer.from([1]), b, a));
+      const [leaf1, leaf2] = fills.map(leafHash);
+
+      // 运行结算曲柄，提交的根应与本地计算一致
+      await program.methods.settleFills().accounts({ orderbook: market.orderbook }).rpc();
+      const orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.fillBatchId.toNumber()).toBe(1);
+      expect(Buffer.from(orderbookAccount.fillRoots[1]).equals(hashPair(leaf1, leaf2))).toBe(true);
+      expect(orderbookAccount.pendingFills.length).toBe(0);
+
+      // 包含的成交验证通过
+      await program.methods
+        .verifyFill(new BN(1), [Array.from(leaf2)], fills[0])
+        .accounts({ orderbook: market.orderbook })
+        .rpc();
+
+      // 篡改数量后的成交验证失败
+      await expect(
+        program.methods
+          .verifyFill(new BN(1), [Array.from(leaf2)], { ...fills[0], quantity: fills[0].quantity.addn(1) })
+          .accounts({ orderbook: market.orderbook })
+          .rpc()
+      ).rejects.toThrow(/InvalidFillProof/);
+    },
+    60000
+  );
+
+  // 测试成交价格模式：maker 价格、中间价、taker 价格下的成交价与结算
+  it(
+    "Executes at the configured execution price mode",
+    async () => {
+      const bidPrice = toPriceAmount(12);
+      const askPrice = toPriceAmount(10);
+      const quantity = toTokenAmount(5, BASE_DECIMALS);
+      const cases = [
+        { mode: { makerPrice: {} }, expectedPrice: toPriceAmount(12) },
+        { mode: { midpoint: {} }, expectedPrice: toPriceAmount(11) },
+        { mode: { takerPrice: {} }, expectedPrice: toPriceAmount(10) },
+      ] as const;
+
+      for (const { mode, expectedPrice } of cases) {
+        const market = await setupMarket({ executionPriceMode: mode });
+        const maker = await createTrader(market);
+        const taker = await createTrader(market);
+
+        // maker 以 12 挂买单，taker 以 10 卖出
+        await placeOrder(market, maker, "buy", bidPrice, quantity);
+        const makerQuoteBefore = await getTokenBalance(maker.quote);
+        const takerQuoteBefore = await getTokenBalance(taker.quote);
+        const signature = await placeOrder(market, taker, "sell", askPrice, quantity, { makers: [maker] });
+
+        const trades = (await getEvents(signature)).filter((e) => e.name === "tradeEvent");
+        expect(trades[0].data.price.eq(expectedPrice)).toBe(true);
+        // taker 按成交价收到报价代币
+        expect((await getTokenBalance(taker.quote)) - takerQuoteBefore).toBe(BigInt(expectedPrice.mul(quantity).toString()));
+        // maker 收回多锁定的报价代币
+        expect((await getTokenBalance(maker.quote)) - makerQuoteBefore).toBe(BigInt(bidPrice.sub(expectedPrice).mul(quantity).toString()));
+        expect(await getTokenBalance(market.quoteVault)).toBe(BigInt(0));
+      }
+    },
+    120000
+  );
+
+  // 测试管理员操作日志：按顺序记录冻结与解冻操作
+  it(
+    "Records admin actions in the audit log",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      await placeOrder(market, maker, "buy", toPriceAmount(9), toTokenAmount(1, BASE_DECIMALS));
+      const orderId = (await program.account.orderbook.fetch(market.orderbook)).bids[0].orderId;
+
+      await program.methods.freezeOrder(orderId).accounts({ orderbook: market.orderbook, admin: payer.publicKey }).rpc();
+      await program.methods.unfreezeOrder(orderId).accounts({ orderbook: market.orderbook, admin: payer.publicKey }).rpc();
+
+      const log = await program.methods.getAdminLog().accounts({ orderbook: market.orderbook }).view();
+      expect(log.length).toBe(2);
+      expect(log[0].kind).toEqual({ freezeOrder: {} });
+      expect(log[1].kind).toEqual({ unfreezeOrder: {} });
+      for (const entry of log) {
+        expect(entry.actor.equals(payer.publicKey)).toBe(true);
+        expect(entry.param.eq(orderId)).toBe(true);
+      }
+      expect(log[1].timestamp.gte(log[0].timestamp)).toBe(true);
+    },
+    60000
+  );
+
+  // 测试买单超额锁定退款：以低于限价成交时，差额立即退还，剩余挂单仅按限价锁定
+  it(
+    "Refunds buy-side overpayment when matched below the limit price",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      const askPrice = toPriceAmount(9);
+      const bidPrice = toPriceAmount(10);
+      const filled = toTokenAmount(5, BASE_DECIMALS);
+      const resting = toTokenAmount(3, BASE_DECIMALS);
+
+      await placeOrder(market, maker, "sell", askPrice, filled);
+      const takerQuoteBefore = await getTokenBalance(taker.quote);
+      // 以 10 买入 8 个：5 个以 9 成交，剩余 3 个以 10 挂单
+      await placeOrder(market, taker, "buy", bidPrice, filled.add(resting), { makers: [maker] });
+
+      // taker 只支付成交金额与剩余挂单的锁定金额
+      const expectedSpent = askPrice.mul(filled).add(bidPrice.mul(resting));
+      expect(takerQuoteBefore - (await getTokenBalance(taker.quote))).toBe(BigInt(expectedSpent.toString()));
+      // 金库中只保留剩余挂单的锁定金额
+      expect(await getTokenBalance(market.quoteVault)).toBe(BigInt(bidPrice.mul(resting).toString()));
+    },
+    60000
+  );
+
+  // 测试市价单：无视价格扫单、受报价上限约束，且未成交部分不挂单而退款
+  it(
+    "Sweeps the book with market orders without resting the remainder",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      // maker 在 9 和 11 各挂 2 个卖单
+      await placeOrder(market, maker, "sell", toPriceAmount(9), unit.muln(2));
+      await placeOrder(market, maker, "sell", toPriceAmount(11), unit.muln(2));
+
+      // 市价买入 10 个（价格参数被忽略），预算充足：吃掉全部 4 个，剩余部分不挂单
+      const takerBaseBefore = await getTokenBalance(taker.base);
+      const takerQuoteBefore = await getTokenBalance(taker.quote);
+      await placeOrder(market, taker, "buy", new BN(0), unit.muln(10), {
+        makers: [maker, maker],
+        market: true,
+        maxQuote: toPriceAmount(1000).mul(unit),
+      });
+      const spent = toPriceAmount(9).mul(unit.muln(2)).add(toPriceAmount(11).mul(unit.muln(2)));
+      expect((await getTokenBalance(taker.base)) - takerBaseBefore).toBe(BigInt(unit.muln(4).toString()));
+      expect(takerQuoteBefore - (await getTokenBalance(taker.quote))).toBe(BigInt(spent.toString()));
+      let orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.asks.length).toBe(0);
+      expect(orderbookAccount.bids.length).toBe(0);
+      expect(await getTokenBalance(market.quoteVault)).toBe(BigInt(0));
+
+      // 报价上限只够买 3 个时，只成交 3 个
+      await placeOrder(market, maker, "sell", toPriceAmount(10), unit.muln(10));
+      await placeOrder(market, taker, "buy", new BN(0), unit.muln(10), {
+        makers: [maker],
+        market: true,
+        maxQuote: toPriceAmount(10).mul(unit.muln(3)),
+      });
+      orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.asks[0].quantity.eq(unit.muln(7))).toBe(true);
+      expect(orderbookAccount.bids.length).toBe(0);
+
+      // 市价卖出 10 个，只有 2 个买单可成交，剩余基础代币退还
+      const bidder = await createTrader(market);
+      await placeOrder(market, bidder, "buy", toPriceAmount(8), unit.muln(2));
+      const sellerBaseBefore = await getTokenBalance(taker.base);
+      await placeOrder(market, taker, "sell", new BN(0), unit.muln(10), { makers: [bidder], market: true });
+      expect(sellerBaseBefore - (await getTokenBalance(taker.base))).toBe(BigInt(unit.muln(2).toString()));
+      orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.bids.length).toBe(0);
+      expect(orderbookAccount.asks.length).toBe(1);
+    },
+    60000
+  );
+
+  // 测试 IOC / FOK：IOC 剩余部分退款不挂单；FOK 无法全部成交时不触碰金库直接拒绝
+  it(
+    "Handles immediate-or-cancel and fill-or-kill orders",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+      const price = toPriceAmount(10);
+
+      // maker 挂 2 个卖单，taker 以 IOC 买入 5 个：成交 2 个，剩余 3 个的锁定资金退还
+      await placeOrder(market, maker, "sell", price, unit.muln(2));
+      const takerQuoteBefore = await getTokenBalance(taker.quote);
+      await placeOrder(market, taker, "buy", price, unit.muln(5), { makers: [maker], timeInForce: "ioc" });
+      expect(takerQuoteBefore - (await getTokenBalance(taker.quote))).toBe(BigInt(price.mul(unit.muln(2)).toString()));
+      let orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.bids.length).toBe(0);
+      expect(orderbookAccount.asks.length).toBe(0);
+      expect(await getTokenBalance(market.quoteVault)).toBe(BigInt(0));
+
+      // maker 再挂 3 个卖单，FOK 买入 5 个无法全部成交，交易被拒绝且余额不变
+      await placeOrder(market, maker, "sell", price, unit.muln(3));
+      const baseVaultBefore = await getTokenBalance(market.baseVault);
+      const quoteBeforeFok = await getTokenBalance(taker.quote);
+      await expect(
+        placeOrder(market, taker, "buy", price, unit.muln(5), { makers: [maker], timeInForce: "fok" })
+      ).rejects.toThrow(/FillOrKillUnsatisfied/);
+      expect(await getTokenBalance(taker.quote)).toBe(quoteBeforeFok);
+      expect(await getTokenBalance(market.baseVault)).toBe(baseVaultBefore);
+
+      // FOK 买入 3 个可全部成交
+      await placeOrder(market, taker, "buy", price, unit.muln(3), { makers: [maker], timeInForce: "fok" });
+      orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.asks.length).toBe(0);
+      expect(orderbookAccount.bids.length).toBe(0);
+    },
+    60000
+  );
+
+  // 测试 post-only：会交叉时拒绝且不锁定资金，不交叉时直接挂单
+  it(
+    "Rejects crossing post-only orders and rests the rest",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const trader = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      await placeOrder(market, maker, "sell", toPriceAmount(10), unit);
+      await placeOrder(market, maker, "buy", toPriceAmount(8), unit);
+
+      // post-only 买单价格 >= 最优卖价，被拒绝且余额不变
+      const quoteBefore = await getTokenBalance(trader.quote);
+      await expect(
+        placeOrder(market, trader, "buy", toPriceAmount(10), unit, { makers: [maker], postOnly: true })
+      ).rejects.toThrow(/PostOnlyWouldCross/);
+      expect(await getTokenBalance(trader.quote)).toBe(quoteBefore);
+
+      // post-only 卖单价格 <= 最优买价，被拒绝且余额不变
+      const baseBefore = await getTokenBalance(trader.base);
+      await expect(
+        placeOrder(market, trader, "sell", toPriceAmount(8), unit, { makers: [maker], postOnly: true })
+      ).rejects.toThrow(/PostOnlyWouldCross/);
+      expect(await getTokenBalance(trader.base)).toBe(baseBefore);
+
+      // 不交叉的 post-only 订单直接挂单
+      await placeOrder(market, trader, "buy", toPriceAmount(9), unit, { postOnly: true });
+      await placeOrder(market, trader, "sell", toPriceAmount(11), unit, { postOnly: true });
+      const orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.bids.length).toBe(2);
+      expect(orderbookAccount.asks.length).toBe(2);
+    },
+    60000
+  );
+
+  // 测试 maker 代币账户 mint 校验：传入错误代币的账户时拒绝撮合
+  it(
+    "Rejects maker token accounts with the wrong mint",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      await placeOrder(market, maker, "sell", toPriceAmount(10), unit);
+
+      // 以 maker 的基础代币账户冒充其报价代币账户
+      const wrongMaker = { ...maker, quote: maker.base };
+      await expect(
+        placeOrder(market, taker, "buy", toPriceAmount(10), unit, { makers: [wrongMaker] })
+      ).rejects.toThrow(/MakerMintMismatch/);
+
+      // 传入正确的账户后正常成交
+      await placeOrder(market, taker, "buy", toPriceAmount(10), unit, { makers: [maker] });
+      const orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.asks.length).toBe(0);
+    },
+    60000
+  );
+
+  // 测试手续费：成交时从双方所得中扣除手续费（向上取整），管理员可提取手续费
+  it(
+    "Charges maker and taker fees and lets the admin withdraw them",
+    async () => {
+      const market = await setupMarket({ makerFeeBps: 10, takerFeeBps: 30 });
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      const quantity = toTokenAmount(1, BASE_DECIMALS).addn(1); // 非整除数量，验证向上取整
+      const price = toPriceAmount(10);
+      const notional = price.mul(quantity);
+      const ceilFee = (amount: BN, bps: number) => amount.muln(bps).addn(9999).divn(10000);
+
+      // maker 挂卖单，taker 买入：taker 手续费以基础代币收取，maker 手续费以报价代币收取
+      await placeOrder(market, maker, "sell", price, quantity);
+      const takerBaseBefore = await getTokenBalance(taker.base);
+      const makerQuoteBefore = await getTokenBalance(maker.quote);
+      await placeOrder(market, taker, "buy", price, quantity, { makers: [maker] });
+
+      const takerFee = ceilFee(quantity, 30);
+      const makerFee = ceilFee(notional, 10);
+      expect((await getTokenBalance(taker.base)) - takerBaseBefore).toBe(BigInt(quantity.sub(takerFee).toString()));
+      expect((await getTokenBalance(maker.quote)) - makerQuoteBefore).toBe(BigInt(notional.sub(makerFee).toString()));
+      expect(await getTokenBalance(market.baseFeeVault)).toBe(BigInt(takerFee.toString()));
+      expect(await getTokenBalance(market.quoteFeeVault)).toBe(BigInt(makerFee.toString()));
+
+      // 非管理员无法提取手续费
+      const withdrawAccounts = (admin: PublicKey, recipient: Trader) => ({
+        orderbook: market.orderbook,
+        admin,
+        baseFeeVault: market.baseFeeVault,
+        quoteFeeVault: market.quoteFeeVault,
+        adminBaseTokenAccount: recipient.base,
+        adminQuoteTokenAccount: recipient.quote,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      });
+      await expect(
+        program.methods
+          .withdrawFees()
+          .accountsPartial(withdrawAccounts(taker.keypair.publicKey, taker))
+          .signers([taker.keypair])
+          .rpc()
+      ).rejects.toThrow(/Unauthorized/);
+
+      // 管理员（payer）提取全部手续费到指定账户
+      const recipient = await createTrader(market);
+      const recipientBaseBefore = await getTokenBalance(recipient.base);
+      const recipientQuoteBefore = await getTokenBalance(recipient.quote);
+      await program.methods.withdrawFees().accountsPartial(withdrawAccounts(payer.publicKey, recipient)).rpc();
+      expect((await getTokenBalance(recipient.base)) - recipientBaseBefore).toBe(BigInt(takerFee.toString()));
+      expect((await getTokenBalance(recipient.quote)) - recipientQuoteBefore).toBe(BigInt(makerFee.toString()));
+      expect(await getTokenBalance(market.baseFeeVault)).toBe(BigInt(0));
+      expect(await getTokenBalance(market.quoteFeeVault)).toBe(BigInt(0));
+    },
+    60000
+  );
+
+  // 测试订单过期：撮合时跳过并移除过期挂单且退款，reap_expired 可清理过期挂单
+  it(
+    "Skips expired makers during matching and reaps expired orders",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+      const expired = new BN(1); // 早已过去的时间戳
+
+      // maker 挂一个已过期的卖单，taker 买入时不成交，卖单被移除且基础代币退还给 maker
+      const makerBaseBefore = await getTokenBalance(maker.base);
+      await placeOrder(market, maker, "sell", toPriceAmount(10), unit, { expiryTs: expired });
+      const signature = await placeOrder(market, taker, "buy", toPriceAmount(10), unit, { makers: [maker] });
+      expect((await getEvents(signature)).filter((e) => e.name === "tradeEvent").length).toBe(0);
+      expect(await getTokenBalance(maker.base)).toBe(makerBaseBefore);
+      let orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.asks.length).toBe(0);
+      expect(orderbookAccount.bids.length).toBe(1); // taker 的买单挂单
+
+      // maker 挂一个过期买单和一个过期卖单，再挂一个未过期的卖单
+      const makerQuoteBefore = await getTokenBalance(maker.quote);
+      await placeOrder(market, maker, "buy", toPriceAmount(5), unit, { expiryTs: expired });
+      await placeOrder(market, maker, "sell", toPriceAmount(20), unit, { expiryTs: expired });
+      await placeOrder(market, maker, "sell", toPriceAmount(30), unit);
+
+      // 任何人都可以清理过期挂单，按先买单后卖单的顺序传入拥有者账户
+      await program.methods
+        .reapExpired()
+        .accounts({ orderbook: market.orderbook })
+        .remainingAccounts([maker, maker].flatMap((owner) => makerAccounts(market.orderbook, owner)))
+        .rpc();
+
+      orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.bids.length).toBe(1);
+      expect(orderbookAccount.bids[0].owner.equals(taker.keypair.publicKey)).toBe(true);
+      expect(orderbookAccount.asks.length).toBe(1);
+      expect(orderbookAccount.asks[0].price.eq(toPriceAmount(30))).toBe(true);
+      // 过期买单锁定的报价代币已退还，只有未过期卖单的基础代币仍锁定
+      expect(await getTokenBalance(maker.quote)).toBe(makerQuoteBefore);
+      expect(makerBaseBefore - (await getTokenBalance(maker.base))).toBe(BigInt(unit.toString()));
+    },
+    60000
+  );
+
+  // 测试订单簿排序与价格优先：新挂单按价格插入正确位置，撮合从最佳价格开始
+  it(
+    "Keeps the book sorted and matches the best price first",
+    async () => {
+      const market = await setupMarket();
+      const cheapMaker = await createTrader(market);
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      // 乱序挂单后，卖单按价格升序、买单按价格降序排列
+      await placeOrder(market, maker, "sell", toPriceAmount(12), unit);
+      await placeOrder(market, cheapMaker, "sell", toPriceAmount(10), unit);
+      await placeOrder(market, maker, "sell", toPriceAmount(11), unit);
+      await placeOrder(market, maker, "buy", toPriceAmount(8), unit);
+      await placeOrder(market, maker, "buy", toPriceAmount(9), unit);
+      await placeOrder(market, maker, "buy", toPriceAmount(7), unit);
+      let orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.asks.map((o) => o.price.toString())).toEqual(
+        [10, 11, 12].map((p) => toPriceAmount(p).toString())
+      );
+      expect(orderbookAccount.bids.map((o) => o.price.toString())).toEqual(
+        [9, 8, 7].map((p) => toPriceAmount(p).toString())
+      );
+
+      // 限价 12 买入 1 个，与最佳卖单（价格 10）成交
+      const signature = await placeOrder(market, taker, "buy", toPriceAmount(12), unit, { makers: [cheapMaker] });
+      const trades = (await getEvents(signature)).filter((e) => e.name === "tradeEvent");
+      expect(trades.length).toBe(1);
+      expect(trades[0].data.price.eq(toPriceAmount(10))).toBe(true);
+      orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.asks.map((o) => o.price.toString())).toEqual(
+        [11, 12].map((p) => toPriceAmount(p).toString())
+      );
+    },
+    60000
+  );
+
+  // 测试最小下单数量与价格最小变动单位：不满足时拒绝且不锁定资金
+  it(
+    "Rejects dust orders and prices off the tick",
+    async () => {
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+      const tick = toPriceAmount(5); // 价格必须是 5 的整数倍
+      const market = await setupMarket({ minBaseQuantity: unit, priceTick: tick });
+      const trader = await createTrader(market);
+
+      // 数量低于最小下单数量
+      const quoteBefore = await getTokenBalance(trader.quote);
+      await expect(
+        placeOrder(market, trader, "buy", toPriceAmount(10), unit.subn(1))
+      ).rejects.toThrow(/OrderTooSmall/);
+      // 价格不是最小变动单位的整数倍
+      await expect(
+        placeOrder(market, trader, "buy", toPriceAmount(12), unit)
+      ).rejects.toThrow(/InvalidTickSize/);
+      expect(await getTokenBalance(trader.quote)).toBe(quoteBefore);
+
+      // 满足约束的订单正常挂单
+      await placeOrder(market, trader, "buy", toPriceAmount(15), unit);
+      const orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.bids.length).toBe(1);
+    },
+    60000
+  );
+
+  // 测试自成交防范：taker 不会与自己的挂单成交，自己的挂单被撤销并退款后继续撮合
+  it(
+    "Prevents self-trades and keeps matching against other makers",
+    async () => {
+      const market = await setupMarket();
+      const trader = await createTrader(market);
+      const maker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      // trader 自己在 10 挂卖单，其他 maker 在 11 挂卖单
+      await placeOrder(market, trader, "sell", toPriceAmount(10), unit.muln(2));
+      await placeOrder(market, maker, "sell", toPriceAmount(11), unit.muln(2));
+
+      // CancelResting：trader 买入时撤销自己的卖单，继续与 maker 的卖单成交（自己的挂单无需传入账户）
+      const baseBefore = await getTokenBalance(trader.base);
+      let signature = await placeOrder(market, trader, "buy", toPriceAmount(11), unit, { makers: [maker] });
+      let trades = (await getEvents(signature)).filter((e) => e.name === "tradeEvent");
+      expect(trades.length).toBe(1);
+      expect(trades[0].data.maker.equals(maker.keypair.publicKey)).toBe(true);
+      // 退还自己卖单的 2 个，加上买入的 1 个
+      expect((await getTokenBalance(trader.base)) - baseBefore).toBe(BigInt(unit.muln(3).toString()));
+      // 被撤销的自己的卖单触发撤单事件
+      let canceled = (await getEvents(signature)).filter((e) => e.name === "orderCanceledEvent");
+      expect(canceled.length).toBe(1);
+      expect(canceled[0].data.owner.equals(trader.keypair.publicKey)).toBe(true);
+      expect(canceled[0].data.quantity.eq(unit.muln(2))).toBe(true);
+      expect(canceled[0].data.refundAmount.eq(unit.muln(2))).toBe(true);
+      let orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.asks.length).toBe(1);
+      expect(orderbookAccount.asks[0].owner.equals(maker.keypair.publicKey)).toBe(true);
+
+      // DecrementAndCancel：trader 挂 3 个买单后卖出 2 个，双方各减少 2 个，不产生成交
+      await placeOrder(market, trader, "buy", toPriceAmount(9), unit.muln(3));
+      const quoteBefore = await getTokenBalance(trader.quote);
+      signature = await placeOrder(market, trader, "sell", toPriceAmount(9), unit.muln(2), { decrementAndCancel: true });
+      trades = (await getEvents(signature)).filter((e) => e.name === "tradeEvent");
+      expect(trades.length).toBe(0);
+      // 买单仍有剩余，未被移除
+      canceled = (await getEvents(signature)).filter((e) => e.name === "orderCanceledEvent");
+      expect(canceled.length).toBe(0);
+      expect((await getTokenBalance(trader.quote)) - quoteBefore).toBe(BigInt(toPriceAmount(9).mul(unit.muln(2)).toString()));
+      orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.bids.length).toBe(1);
+      expect(orderbookAccount.bids[0].quantity.eq(unit)).toBe(true);
+      expect(orderbookAccount.asks.length).toBe(1); // taker 卖单已全部抵消，未挂单
+    },
+    60000
+  );
+
+  // 测试订单簿容量：挂单数量达到上限时拒绝挂单，grow_book 扩容后可继续挂单
+  it(
+    "Rejects resting orders on a full book until it is grown",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      // 挂满 max_orders 个卖单（价格各不相同，分批并发提交）
+      const maxOrders = (await program.account.orderbook.fetch(market.orderbook)).maxOrders;
+      for (let batch = 0; batch < maxOrders; batch += 10) {
+        await Promise.all(
+          Array.from({ length: Math.min(10, maxOrders - batch) }, (_, i) =>
+            placeOrder(market, maker, "sell", toPriceAmount(100 + batch + i), unit)
+          )
+        );
+      }
+      await expect(placeOrder(market, maker, "sell", toPriceAmount(500), unit)).rejects.toThrow(/BookFull/);
+
+      // 扩容 2 个位置，账户大小相应增加
+      const sizeBefore = (await connection.getAccountInfo(market.orderbook))!.data.length;
+      await program.methods.growBook(2).accounts({ orderbook: market.orderbook, payer: payer.publicKey }).rpc();
+      const orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.maxOrders).toBe(maxOrders + 2);
+      const sizeAfter = (await connection.getAccountInfo(market.orderbook))!.data.length;
+      expect(sizeAfter - sizeBefore).toBe(2 * 2 * 146); // 买卖两侧各 2 个订单，每个 146 字节
+
+      // 扩容后可以继续挂单
+      await placeOrder(market, maker, "sell", toPriceAmount(500), unit);
+      expect((await program.account.orderbook.fetch(market.orderbook)).asks.length).toBe(maxOrders + 1);
+    },
+    120000
+  );
+
+  // 测试挂单、成交与撤单事件：仅凭日志即可重建订单簿深度
+  it(
+    "Emits order placed, filled and canceled events",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+      const price = toPriceAmount(10);
+
+      // 挂单事件
+      let signature = await placeOrder(market, maker, "sell", price, unit.muln(3));
+      const placed = (await getEvents(signature)).filter((e) => e.name === "orderPlacedEvent");
+      expect(placed.length).toBe(1);
+      const orderId = placed[0].data.orderId;
+      expect(placed[0].data.owner.equals(maker.keypair.publicKey)).toBe(true);
+      expect(placed[0].data.side).toEqual({ sell: {} });
+      expect(placed[0].data.quantity.eq(unit.muln(3))).toBe(true);
+
+      // 部分成交事件
+      signature = await placeOrder(market, taker, "buy", price, unit, { makers: [maker] });
+      const filled = (await getEvents(signature)).filter((e) => e.name === "orderFilledEvent");
+      expect(filled.length).toBe(1);
+      expect(filled[0].data.orderId.eq(orderId)).toBe(true);
+      expect(filled[0].data.fillQuantity.eq(unit)).toBe(true);
+      expect(filled[0].data.remainingQuantity.eq(unit.muln(2))).toBe(true);
+      expect(filled[0].data.fullFill).toBe(false);
+
+      // 撤单事件，退还剩余的基础代币
+      signature = await program.methods
+        .cancelOrder(orderId, { sell: {} })
+        .accountsPartial({
+          orderbook: market.orderbook,
+          owner: maker.keypair.publicKey,
+          ownerBaseTokenAccount: maker.base,
+          ownerQuoteTokenAccount: maker.quote,
+          baseVault: market.baseVault,
+          quoteVault: market.quoteVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([maker.keypair])
+        .rpc({ commitment: "confirmed" });
+      const canceled = (await getEvents(signature)).filter((e) => e.name === "orderCanceledEvent");
+      expect(canceled.length).toBe(1);
+      expect(canceled[0].data.orderId.eq(orderId)).toBe(true);
+      expect(canceled[0].data.side).toEqual({ sell: {} });
+      expect(canceled[0].data.refundAmount.eq(unit.muln(2))).toBe(true);
+    },
+    60000
+  );
+
+  // 测试修改订单：按差额补充或退还锁定资金，会交叉的修改被拒绝
+  it(
+    "Modifies resting orders and adjusts locked collateral",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const other = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      await placeOrder(market, maker, "buy", toPriceAmount(9), unit.muln(2));
+      await placeOrder(market, other, "buy", toPriceAmount(9), unit);
+      await placeOrder(market, other, "sell", toPriceAmount(10), unit);
+      const orderId = (await program.account.orderbook.fetch(market.orderbook)).bids[0].orderId;
+
+      const modifyOrder = (price: BN, quantity: BN) =>
+        program.methods
+          .modifyOrder(orderId, price, quantity)
+          .accountsPartial({
+            orderbook: market.orderbook,
+            owner: maker.keypair.publicKey,
+            ownerBaseTokenAccount: maker.base,
+            ownerQuoteTokenAccount: maker.quote,
+            baseVault: market.baseVault,
+            quoteVault: market.quoteVault,
+            oracle: null,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([maker.keypair])
+          .rpc({ commitment: "confirmed" });
+
+      // 增加数量：补充锁定差额，保持排队位置
+      let quoteBefore = await getTokenBalance(maker.quote);
+      await modifyOrder(toPriceAmount(9), unit.muln(3));
+      expect(quoteBefore - (await getTokenBalance(maker.quote))).toBe(BigInt(toPriceAmount(9).mul(unit).toString()));
+      let orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.bids[0].orderId.eq(orderId)).toBe(true);
+      expect(orderbookAccount.bids[0].quantity.eq(unit.muln(3))).toBe(true);
+
+      // 降低价格并减少数量：退还差额，订单按新价格重新排序
+      quoteBefore = await getTokenBalance(maker.quote);
+      const signature = await modifyOrder(toPriceAmount(8), unit);
+      const modified = (await getEvents(signature)).find((e) => e.name === "orderModifiedEvent")!;
+      expect(modified.data.orderId.eq(orderId)).toBe(true);
+      expect(modified.data.owner.equals(maker.keypair.publicKey)).toBe(true);
+      expect(modified.data.side).toEqual({ buy: {} });
+      expect(modified.data.oldPrice.eq(toPriceAmount(9))).toBe(true);
+      expect(modified.data.newPrice.eq(toPriceAmount(8))).toBe(true);
+      expect(modified.data.oldQuantity.eq(unit.muln(3))).toBe(true);
+      expect(modified.data.newQuantity.eq(unit)).toBe(true);
+      const refund = toPriceAmount(9).mul(unit.muln(3)).sub(toPriceAmount(8).mul(unit));
+      expect((await getTokenBalance(maker.quote)) - quoteBefore).toBe(BigInt(refund.toString()));
+      orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.bids[1].orderId.eq(orderId)).toBe(true);
+      expect(orderbookAccount.bids[1].price.eq(toPriceAmount(8))).toBe(true);
+
+      // 新价格会与最佳卖单交叉时拒绝
+      await expect(modifyOrder(toPriceAmount(10), unit)).rejects.toThrow(/ModifyWouldCross/);
+    },
+    60000
+  );
+
+  // 测试批量撤单：一次性撤销调用者的全部挂单并退款，其他人的挂单不受影响
+  it(
+    "Cancels all of an owner's orders at once",
+    async () => {
+      const market = await setupMarket();
+      const bot = await createTrader(market);
+      const other = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      const baseBefore = await getTokenBalance(bot.base);
+      const quoteBefore = await getTokenBalance(bot.quote);
+      await placeOrder(market, bot, "buy", toPriceAmount(8), unit);
+      await placeOrder(market, other, "buy", toPriceAmount(8), unit);
+      await placeOrder(market, bot, "buy", toPriceAmount(7), unit.muln(2));
+      await placeOrder(market, bot, "sell", toPriceAmount(12), unit);
+      await placeOrder(market, other, "sell", toPriceAmount(11), unit);
+      await placeOrder(market, bot, "sell", toPriceAmount(13), unit.muln(3));
+
+      await program.methods
+        .cancelAllOrders()
+        .accountsPartial({
+          orderbook: market.orderbook,
+          owner: bot.keypair.publicKey,
+          ownerBaseTokenAccount: bot.base,
+          ownerQuoteTokenAccount: bot.quote,
+          baseVault: market.baseVault,
+          quoteVault: market.quoteVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([bot.keypair])
+        .rpc();
+
+      // 全部锁定资金已退还
+      expect(await getTokenBalance(bot.base)).toBe(baseBefore);
+      expect(await getTokenBalance(bot.quote)).toBe(quoteBefore);
+      const orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.bids.length).toBe(1);
+      expect(orderbookAccount.asks.length).toBe(1);
+      expect(orderbookAccount.bids[0].owner.equals(other.keypair.publicKey)).toBe(true);
+      expect(orderbookAccount.asks[0].owner.equals(other.keypair.publicKey)).toBe(true);
+    },
+    60000
+  );
+
+  // 测试 Token-2022：带转账手续费的代币按金库实际到账数量入账
+  it(
+    "Supports Token-2022 mints and credits the post-fee amount",
+    async () => {
+      // 创建带 1% 转账手续费的 Token-2022 基础代币
+      const baseMintKeypair = Keypair.generate();
+      const mintLen = getMintLen([ExtensionType.TransferFeeConfig]);
+      await sendAndConfirmTransaction(
+        connection,
+        new Transaction().add(
+          SystemProgram.createAccount({
+            fromPubkey: payer.publicKey,
+            newAccountPubkey: baseMintKeypair.publicKey,
+            space: mintLen,
+            lamports: await connection.getMinimumBalanceForRentExemption(mintLen),
+            programId: TOKEN_2022_PROGRAM_ID,
+          }),
+          createInitializeTransferFeeConfigInstruction(
+            baseMintKeypair.publicKey,
+            payer.publicKey,
+            payer.publicKey,
+            100,
+            BigInt(toTokenAmount(1000, BASE_DECIMALS).toString()),
+            TOKEN_2022_PROGRAM_ID
+          ),
+          createInitializeMintInstruction(baseMintKeypair.publicKey, BASE_DECIMALS, payer.publicKey, null, TOKEN_2022_PROGRAM_ID)
+        ),
+        [payer.payer, baseMintKeypair]
+      );
+      const base2022 = baseMintKeypair.publicKey;
+      const quote2022 = await createMint(connection, payer.payer, payer.publicKey, null, QUOTE_DECIMALS, undefined, undefined, TOKEN_2022_PROGRAM_ID);
+      const [orderbook] = PublicKey.findProgramAddressSync([Buffer.from("orderbook"), base2022.toBuffer(), quote2022.toBuffer()], program.programId);
+      const [baseVault] = PublicKey.findProgramAddressSync([Buffer.from("base_vault"), orderbook.toBuffer()], program.programId);
+
+      await program.methods
+        .initialize(new BN(0), { makerPrice: {} }, 0, 0, new BN(0), new BN(1), { none: {} }, false, 0)
+        .accountsPartial({ orderbook, baseMint: base2022, quoteMint: quote2022, payer: payer.publicKey, tokenProgram: TOKEN_2022_PROGRAM_ID })
+        .rpc();
+
+      const maker = Keypair.generate();
+      await fundSol(maker.publicKey);
+      const makerBase = await createAccount(connection, payer.payer, base2022, maker.publicKey, undefined, undefined, TOKEN_2022_PROGRAM_ID);
+      const makerQuote = await createAccount(connection, payer.payer, quote2022, maker.publicKey, undefined, undefined, TOKEN_2022_PROGRAM_ID);
+      await mintTo(connection, payer.payer, base2022, makerBase, payer.payer, toTokenAmount(1000, BASE_DECIMALS).toNumber(), [], undefined, TOKEN_2022_PROGRAM_ID);
+
+      // 卖出 100 个，金库实际收到 99 个，挂单数量以实际到账为准
+      const quantity = toTokenAmount(100, BASE_DECIMALS);
+      await program.methods
+        .placeOrder({ sell: {} }, toPriceAmount(10), quantity, null, new BN(0), { limit: {} }, new BN(0), { goodTillCancel: {} }, false, new BN(0), { cancelResting: {} }, new BN(0), false, new BN(0), false, null, true, false)
+        .accountsPartial({
+          orderbook,
+          owner: maker.publicKey,
+          ownerBaseTokenAccount: makerBase,
+          ownerQuoteTokenAccount: makerQuote,
+          referral: null,
+          oracle: null,
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+        })
+        .signers([maker])
+        .rpc();
+
+      const received = quantity.muln(99).divn(100);
+      const orderbookAccount = await program.account.orderbook.fetch(orderbook);
+      expect(orderbookAccount.asks[0].quantity.eq(received)).toBe(true);
+      const vaultAccount = await getAccount(connection, baseVault, undefined, TOKEN_2022_PROGRAM_ID);
+      expect(vaultAccount.amount).toBe(BigInt(received.toString()));
+    },
+    60000
+  );
+
+  // 测试订单簿深度查询：同价订单合并为一个价位，只返回最优的 N 个价位
+  it(
+    "Returns aggregated order book depth",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const other = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      await placeOrder(market, maker, "buy", toPriceAmount(9), unit);
+      await placeOrder(market, other, "buy", toPriceAmount(9), unit.muln(2));
+      await placeOrder(market, maker, "buy", toPriceAmount(8), unit);
+      await placeOrder(market, maker, "buy", toPriceAmount(7), unit);
+      await placeOrder(market, maker, "sell", toPriceAmount(11), unit);
+      await placeOrder(market, other, "sell", toPriceAmount(11), unit);
+
+      const depth = await program.methods.getOrderBookDepth(2).accounts({ orderbook: market.orderbook }).view();
+      expect(depth.bids.map((l) => [l.price.toString(), l.quantity.toString()])).toEqual([
+        [toPriceAmount(9).toString(), unit.muln(3).toString()],
+        [toPriceAmount(8).toString(), unit.toString()],
+      ]);
+      expect(depth.asks.map((l) => [l.price.toString(), l.quantity.toString()])).toEqual([
+        [toPriceAmount(11).toString(), unit.muln(2).toString()],
+      ]);
+    },
+    60000
+  );
+
+  // 测试止损限价单：最新成交价触及触发价后由 trigger_stops 转为限价单撮合，未触发时可撤单退款
+  it(
+    "Triggers stop-limit orders on the last trade price",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      const stopper = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      await placeOrder(market, maker, "sell", toPriceAmount(10), unit);
+      await placeOrder(market, maker, "sell", toPriceAmount(12), unit);
+
+      // 买入止损：价格涨到 10 时以不高于 12 的价格买入
+      const stopQuoteBefore = await getTokenBalance(stopper.quote);
+      let signature = await placeOrder(market, stopper, "buy", new BN(0), unit, {
+        stop: { triggerPrice: toPriceAmount(10), limitPrice: toPriceAmount(12) },
+      });
+      const placed = (await getEvents(signature)).filter((e) => e.name === "stopOrderPlacedEvent");
+      expect(placed.length).toBe(1);
+      const stopOrderId = placed[0].data.orderId;
+      // 资金按限价锁定，止损单不进入订单簿
+      expect(stopQuoteBefore - (await getTokenBalance(stopper.quote))).toBe(BigInt(toPriceAmount(12).mul(unit).toString()));
+      let book = await program.account.orderbook.fetch(market.orderbook);
+      expect(book.bids.length).toBe(0);
+      expect(book.stops.length).toBe(1);
+
+      // 卖出止损：尚无成交价时不会触发，撤单退还基础代币
+      signature = await placeOrder(market, stopper, "sell", new BN(0), unit, {
+        stop: { triggerPrice: toPriceAmount(5), limitPrice: toPriceAmount(5) },
+      });
+      const sellStopId = (await getEvents(signature)).find((e) => e.name === "stopOrderPlacedEvent")!.data.orderId;
+      const stopBaseBefore = await getTokenBalance(stopper.base);
+      await program.methods
+        .cancelOrder(sellStopId, { sell: {} })
+        .accountsPartial({
+          orderbook: market.orderbook,
+          owner: stopper.keypair.publicKey,
+          ownerBaseTokenAccount: stopper.base,
+          ownerQuoteTokenAccount: stopper.quote,
+          baseVault: market.baseVault,
+          quoteVault: market.quoteVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([stopper.keypair])
+        .rpc({ commitment: "confirmed" });
+      expect((await getTokenBalance(stopper.base)) - stopBaseBefore).toBe(BigInt(unit.toString()));
+
+      // 在 10 成交一笔，最新成交价达到触发价
+      await placeOrder(market, taker, "buy", toPriceAmount(10), unit, { makers: [maker] });
+      book = await program.account.orderbook.fetch(market.orderbook);
+      expect(book.lastTradePrice.eq(toPriceAmount(10))).toBe(true);
+
+      // 触发止损：先传入止损单拥有者的代币账户，再传入撮合的 maker 账户
+      const stopBaseBeforeTrigger = await getTokenBalance(stopper.base);
+      signature = await program.methods
+        .triggerStops()
+        .accountsPartial({
+          orderbook: market.orderbook,
+          baseVault: market.baseVault,
+          quoteVault: market.quoteVault,
+          baseFeeVault: market.baseFeeVault,
+          quoteFeeVault: market.quoteFeeVault,
+          oracle: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts([stopper, maker].flatMap((trader) => makerAccounts(market.orderbook, trader)))
+        .rpc({ commitment: "confirmed" });
+      const triggered = (await getEvents(signature)).filter((e) => e.name === "stopOrderTriggeredEvent");
+      expect(triggered.length).toBe(1);
+      expect(triggered[0].data.orderId.eq(stopOrderId)).toBe(true);
+
+      // 止损单以 12 的限价吃掉剩余卖单
+      expect((await getTokenBalance(stopper.base)) - stopBaseBeforeTrigger).toBe(BigInt(unit.toString()));
+      book = await program.account.orderbook.fetch(market.orderbook);
+      expect(book.stops.length).toBe(0);
+      expect(book.asks.length).toBe(0);
+      expect(book.lastTradePrice.eq(toPriceAmount(12))).toBe(true);
+    },
+    60000
+  );
+
+  // 测试暂停交易：仅管理员可暂停，暂停期间拒绝下单但仍可撤单
+  it(
+    "Pauses trading while still allowing cancels",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      const signature = await placeOrder(market, maker, "sell", toPriceAmount(10), unit);
+      const orderId = (await getEvents(signature)).find((e) => e.name === "orderPlacedEvent")!.data.orderId;
+
+      // 非管理员无法暂停
+      await expect(
+        program.methods
+          .setPaused(true)
+          .accounts({ orderbook: market.orderbook, admin: maker.keypair.publicKey })
+          .signers([maker.keypair])
+          .rpc()
+      ).rejects.toThrow(/Unauthorized/);
+
+      await program.methods.setPaused(true).accounts({ orderbook: market.orderbook, admin: payer.publicKey }).rpc();
+      await expect(placeOrder(market, maker, "sell", toPriceAmount(10), unit)).rejects.toThrow(/MarketPaused/);
+
+      // 暂停期间仍可撤单取回资金
+      const baseBefore = await getTokenBalance(maker.base);
+      await program.methods
+        .cancelOrder(orderId, { sell: {} })
+        .accountsPartial({
+          orderbook: market.orderbook,
+          owner: maker.keypair.publicKey,
+          ownerBaseTokenAccount: maker.base,
+          ownerQuoteTokenAccount: maker.quote,
+          baseVault: market.baseVault,
+          quoteVault: market.quoteVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([maker.keypair])
+        .rpc();
+      expect((await getTokenBalance(maker.base)) - baseBefore).toBe(BigInt(unit.toString()));
+
+      // 恢复交易后可以正常下单，两次操作均记入管理员日志
+      await program.methods.setPaused(false).accounts({ orderbook: market.orderbook, admin: payer.publicKey }).rpc();
+      await placeOrder(market, maker, "sell", toPriceAmount(10), unit);
+      const log = await program.methods.getAdminLog().accounts({ orderbook: market.orderbook }).view();
+      expect(log.map((entry) => entry.kind)).toEqual([{ pause: {} }, { unpause: {} }]);
+    },
+    60000
+  );
+
+  // 测试关闭订单簿：仍有挂单时拒绝，清空后关闭金库与订单簿账户并退还租金
+  it(
+    "Closes an empty orderbook and reclaims rent",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+      const closeAccounts = {
+        orderbook: market.orderbook,
+        baseVault: market.baseVault,
+        quoteVault: market.quoteVault,
+        baseFeeVault: market.baseFeeVault,
+        quoteFeeVault: market.quoteFeeVault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      };
+
+      const signature = await placeOrder(market, maker, "buy", toPriceAmount(10), unit);
+      const orderId = (await getEvents(signature)).find((e) => e.name === "orderPlacedEvent")!.data.orderId;
+
+      // 非管理员无法关闭
+      await expect(
+        program.methods
+          .closeOrderbook()
+          .accountsPartial({ ...closeAccounts, admin: maker.keypair.publicKey })
+          .signers([maker.keypair])
+          .rpc()
+      ).rejects.toThrow(/Unauthorized/);
+      // 仍有挂单时拒绝关闭
+      await expect(
+        program.methods.closeOrderbook().accountsPartial({ ...closeAccounts, admin: payer.publicKey }).rpc()
+      ).rejects.toThrow(/BookNotEmpty/);
+
+      await program.methods
+        .cancelOrder(orderId, { buy: {} })
+        .accountsPartial({
+          orderbook: market.orderbook,
+          owner: maker.keypair.publicKey,
+          ownerBaseTokenAccount: maker.base,
+          ownerQuoteTokenAccount: maker.quote,
+          baseVault: market.baseVault,
+          quoteVault: market.quoteVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([maker.keypair])
+        .rpc();
+
+      const adminLamportsBefore = await connection.getBalance(payer.publicKey);
+      await program.methods.closeOrderbook().accountsPartial({ ...closeAccounts, admin: payer.publicKey }).rpc();
+      expect(await connection.getAccountInfo(market.orderbook)).toBeNull();
+      expect(await connection.getAccountInfo(market.baseVault)).toBeNull();
+      expect(await connection.getAccountInfo(market.quoteFeeVault)).toBeNull();
+      expect(await connection.getBalance(payer.publicKey)).toBeGreaterThan(adminLamportsBefore);
+    },
+    60000
+  );
+
+  // 测试同价订单的时间优先：同一价格上最早的挂单最先成交
+  it(
+    "Fills the oldest order first at the same price",
+    async () => {
+      const market = await setupMarket();
+      const makers = [await createTrader(market), await createTrader(market), await createTrader(market)];
+      const taker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+      const price = toPriceAmount(10);
+
+      const orderIds: BN[] = [];
+      for (const maker of makers) {
+        const signature = await placeOrder(market, maker, "buy", price, unit);
+        orderIds.push((await getEvents(signature)).find((e) => e.name === "orderPlacedEvent")!.data.orderId);
+      }
+      let orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.bids.map((o) => o.orderId.toString())).toEqual(orderIds.map((id) => id.toString()));
+
+      // 卖出 1 个，只与最早的买单成交
+      const signature = await placeOrder(market, taker, "sell", price, unit, { makers: [makers[0]] });
+      const filled = (await getEvents(signature)).filter((e) => e.name === "orderFilledEvent");
+      expect(filled.length).toBe(1);
+      expect(filled[0].data.orderId.eq(orderIds[0])).toBe(true);
+      orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.bids.map((o) => o.orderId.toString())).toEqual(orderIds.slice(1).map((id) => id.toString()));
+    },
+    60000
+  );
+
+  // 测试批量下单：一笔交易挂出双边报价；批次内后面的订单继续使用后续的 maker 账户撮合
+  it(
+    "Places a batch of orders in one transaction",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      // 辅助函数：以指定交易者身份批量下单
+      const placeOrders = (trader: Trader, orders: { side: "buy" | "sell"; price: number }[], makers: Trader[] = []) =>
+        program.methods
+          .placeOrders(
+            orders.map((order) => ({
+              side: order.side === "buy" ? { buy: {} } : { sell: {} },
+              price: toPriceAmount(order.price),
+              quantity: unit,
+            }))
+          )
+          .accountsPartial({
+            orderbook: market.orderbook,
+            owner: trader.keypair.publicKey,
+            ownerBaseTokenAccount: trader.base,
+            ownerQuoteTokenAccount: trader.quote,
+            baseVault: market.baseVault,
+            quoteVault: market.quoteVault,
+            baseFeeVault: market.baseFeeVault,
+            quoteFeeVault: market.quoteFeeVault,
+            referral: null,
+            oracle: null,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .remainingAccounts(makers.flatMap((m) => makerAccounts(market.orderbook, m)))
+          .signers([trader.keypair])
+          .rpc({ commitment: "confirmed" });
+
+      // 双边报价：一个买单、两个卖单
+      await placeOrders(maker, [
+        { side: "buy", price: 8 },
+        { side: "sell", price: 10 },
+        { side: "sell", price: 11 },
+      ]);
+      let orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.bids.length).toBe(1);
+      expect(orderbookAccount.asks.map((o) => o.price.toString())).toEqual(
+        [10, 11].map((p) => toPriceAmount(p).toString())
+      );
+
+      // 两个买单分别吃掉两个卖单，maker 账户按批次内的撮合顺序传入
+      const baseBefore = await getTokenBalance(taker.base);
+      const signature = await placeOrders(
+        taker,
+        [
+          { side: "buy", price: 10 },
+          { side: "buy", price: 11 },
+        ],
+        [maker, maker]
+      );
+      expect((await getEvents(signature)).filter((e) => e.name === "tradeEvent").length).toBe(2);
+      expect((await getTokenBalance(taker.base)) - baseBefore).toBe(BigInt(unit.muln(2).toString()));
+      orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.asks.length).toBe(0);
+      expect(orderbookAccount.bids.length).toBe(1);
+    },
+    60000
+  );
+
+  // 测试大额报价计算：价格 × 数量以 u128 计算，乘积在 u64 范围内的大额订单可以正常成交
+  it(
+    "Handles large price and quantity products",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      const price = new BN(2).pow(new BN(40));
+      const quantity = new BN(2).pow(new BN(20));
+      const notional = price.mul(quantity); // 2^60，仍在 u64 范围内
+      await mintTo(connection, payer.payer, market.quoteMint, taker.quote, payer.payer, BigInt(notional.toString()));
+
+      await placeOrder(market, maker, "sell", price, quantity);
+      const makerQuoteBefore = await getTokenBalance(maker.quote);
+      const signature = await placeOrder(market, taker, "buy", price, quantity, { makers: [maker] });
+      const trades = (await getEvents(signature)).filter((e) => e.name === "tradeEvent");
+      expect(trades.length).toBe(1);
+      expect(trades[0].data.quantity.eq(quantity)).toBe(true);
+      expect((await getTokenBalance(maker.quote)) - makerQuoteBefore).toBe(BigInt(notional.toString()));
+
+      // 乘积超出 u64 时仍然拒绝（2^40 × 2^30 = 2^70）
+      await expect(
+        placeOrder(market, taker, "buy", price, new BN(2).pow(new BN(30)))
+      ).rejects.toThrow(/CalculationError/);
+    },
+    60000
+  );
+
+  // 测试滑点保护：超出最差价格的挂单不会成交，撮合正常结束而不报错
+  it(
+    "Stops matching at the worst acceptable price",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      await placeOrder(market, maker, "sell", toPriceAmount(10), unit);
+      await placeOrder(market, maker, "sell", toPriceAmount(12), unit);
+
+      // 限价 12 的 IOC 买单，最差价格 11：只与 10 的卖单成交，剩余部分撤销退款
+      const quoteBefore = await getTokenBalance(taker.quote);
+      const signature = await placeOrder(market, taker, "buy", toPriceAmount(12), unit.muln(2), {
+        makers: [maker],
+        timeInForce: "ioc",
+        worstPrice: toPriceAmount(11),
+      });
+      const trades = (await getEvents(signature)).filter((e) => e.name === "tradeEvent");
+      expect(trades.length).toBe(1);
+      expect(trades[0].data.price.eq(toPriceAmount(10))).toBe(true);
+      expect(quoteBefore - (await getTokenBalance(taker.quote))).toBe(BigInt(toPriceAmount(10).mul(unit).toString()));
+      const orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.asks.length).toBe(1);
+      expect(orderbookAccount.bids.length).toBe(0);
+
+      // FOK 订单同样遵守最差价格
+      await expect(
+        placeOrder(market, taker, "buy", toPriceAmount(12), unit, {
+          makers: [maker],
+          timeInForce: "fok",
+          worstPrice: toPriceAmount(11),
+        })
+      ).rejects.toThrow(/FillOrKillUnsatisfied/);
+    },
+    60000
+  );
+
+  // 测试挂单锁定资金记录：部分成交后按实际释放的数量递减，撤单退还记录的锁定资金，金库清零
+  it(
+    "Tracks locked funds per resting order",
+    async () => {
+      const market = await setupMarket({ executionPriceMode: { midpoint: {} } });
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      let signature = await placeOrder(market, maker, "buy", toPriceAmount(10), unit.muln(3));
+      const orderId = (await getEvents(signature)).find((e) => e.name === "orderPlacedEvent")!.data.orderId;
+      let orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.bids[0].lockedQuote.eq(toPriceAmount(10).mul(unit.muln(3)))).toBe(true);
+      expect(orderbookAccount.bids[0].lockedBase.isZero()).toBe(true);
+
+      // 以 8 卖出 1 个，按中点 9 成交，maker 多锁定的差额被退还，剩余锁定资金按 10 × 2 计
+      await placeOrder(market, taker, "sell", toPriceAmount(8), unit, { makers: [maker] });
+      orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.bids[0].lockedQuote.eq(toPriceAmount(10).mul(unit.muln(2)))).toBe(true);
+
+      // 撤单退还记录的锁定资金，报价金库清零
+      const quoteBefore = await getTokenBalance(maker.quote);
+      await program.methods
+        .cancelOrder(orderId, { buy: {} })
+        .accountsPartial({
+          orderbook: market.orderbook,
+          owner: maker.keypair.publicKey,
+          ownerBaseTokenAccount: maker.base,
+          ownerQuoteTokenAccount: maker.quote,
+          baseVault: market.baseVault,
+          quoteVault: market.quoteVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([maker.keypair])
+        .rpc();
+      expect((await getTokenBalance(maker.quote)) - quoteBefore).toBe(BigInt(toPriceAmount(10).mul(unit.muln(2)).toString()));
+      expect(await getTokenBalance(market.quoteVault)).toBe(BigInt(0));
+    },
+    60000
+  );
+
+  // 测试延迟撮合：交叉的挂单留在订单簿上，由无需许可的 match_orders 撮合
+  it(
+    "Matches crossed orders through the match_orders crank",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      // 延迟撮合只适用于会挂单的订单
+      await expect(
+        placeOrder(market, taker, "buy", toPriceAmount(10), unit, { timeInForce: "ioc", deferMatching: true })
+      ).rejects.toThrow(/InvalidDeferredOrder/);
+
+      await placeOrder(market, maker, "sell", toPriceAmount(10), unit);
+      let signature = await placeOrder(market, taker, "buy", toPriceAmount(11), unit.muln(2), { deferMatching: true });
+      expect((await getEvents(signature)).filter((e) => e.name === "tradeEvent").length).toBe(0);
+      let book = await program.account.orderbook.fetch(market.orderbook);
+      expect(book.bids[0].price.gte(book.asks[0].price)).toBe(true);
+
+      // 较新的买单作为 taker：先传入其拥有者的代币账户，再传入 maker 账户
+      const baseBefore = await getTokenBalance(taker.base);
+      signature = await program.methods
+        .matchOrders(4)
+        .accountsPartial({
+          orderbook: market.orderbook,
+          baseVault: market.baseVault,
+          quoteVault: market.quoteVault,
+          baseFeeVault: market.baseFeeVault,
+          quoteFeeVault: market.quoteFeeVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts([taker, maker].flatMap((trader) => makerAccounts(market.orderbook, trader)))
+        .rpc({ commitment: "confirmed" });
+      const trades = (await getEvents(signature)).filter((e) => e.name === "tradeEvent");
+      expect(trades.length).toBe(1);
+      expect(trades[0].data.price.eq(toPriceAmount(10))).toBe(true);
+      expect((await getTokenBalance(taker.base)) - baseBefore).toBe(BigInt(unit.toString()));
+
+      // 订单簿不再交叉，买单剩余部分留在订单簿上
+      book = await program.account.orderbook.fetch(market.orderbook);
+      expect(book.asks.length).toBe(0);
+      expect(book.bids.length).toBe(1);
+      expect(book.bids[0].quantity.eq(unit)).toBe(true);
+      expect(book.bids[0].lockedQuote.eq(toPriceAmount(11).mul(unit))).toBe(true);
+    },
+    60000
+  );
+
+  // 测试零价格与零数量的订单在锁定资金之前被拒绝，金库余额不变
+  it(
+    "Rejects zero-price and zero-quantity orders",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      await placeOrder(market, maker, "sell", toPriceAmount(10), unit);
+      const baseVaultBefore = await getTokenBalance(market.baseVault);
+      const quoteVaultBefore = await getTokenBalance(market.quoteVault);
+
+      // 零价格买单不锁定报价代币，否则可以白拿卖单的基础代币
+      await expect(
+        placeOrder(market, taker, "buy", new BN(0), unit, { makers: [maker] })
+      ).rejects.toThrow(/InvalidOrderParams/);
+      // 零数量订单
+      await expect(
+        placeOrder(market, taker, "buy", toPriceAmount(10), new BN(0), { makers: [maker] })
+      ).rejects.toThrow(/InvalidOrderParams/);
+      await expect(
+        placeOrder(market, taker, "sell", toPriceAmount(10), new BN(0))
+      ).rejects.toThrow(/InvalidOrderParams/);
+
+      expect(await getTokenBalance(market.baseVault)).toBe(baseVaultBefore);
+      expect(await getTokenBalance(market.quoteVault)).toBe(quoteVaultBefore);
+      const orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.asks.length).toBe(1);
+      expect(orderbookAccount.bids.length).toBe(0);
+    },
+    60000
+  );
+
+  // 测试 maker 返佣：按成交额累计，从报价代币手续费金库领取，金库不足时拒绝领取
+  it(
+    "Accrues maker rebates and pays them from collected fees",
+    async () => {
+      const market = await setupMarket({ makerFeeBps: 10 });
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+      const notional = toPriceAmount(10).mul(unit);
+      const ceilFee = (amount: BN, bps: number) => amount.muln(bps).addn(9999).divn(10000);
+      const claimAccounts = {
+        orderbook: market.orderbook,
+        owner: maker.keypair.publicKey,
+        claimable: claimablePda(market.orderbook, maker.keypair.publicKey),
+        quoteFeeVault: market.quoteFeeVault,
+        ownerQuoteTokenAccount: maker.quote,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      };
+
+      // 只有管理员可以设置返佣比例
+      await expect(
+        program.methods
+          .setRebateBps(5)
+          .accounts({ orderbook: market.orderbook, admin: maker.keypair.publicKey })
+          .signers([maker.keypair])
+          .rpc()
+      ).rejects.toThrow(/Unauthorized/);
+      await program.methods.setRebateBps(5).accounts({ orderbook: market.orderbook, admin: payer.publicKey }).rpc();
+
+      await placeOrder(market, maker, "sell", toPriceAmount(10), unit);
+      await placeOrder(market, taker, "buy", toPriceAmount(10), unit, { makers: [maker] });
+      let claimable = await program.account.claimableBalance.fetch(claimAccounts.claimable);
+      expect(claimable.owner.equals(maker.keypair.publicKey)).toBe(true);
+      expect(claimable.rebate.eq(ceilFee(notional, 5))).toBe(true);
+      let book = await program.account.orderbook.fetch(market.orderbook);
+      expect(book.rebatesOwed.eq(ceilFee(notional, 5))).toBe(true);
+
+      // 领取返佣后累计清零
+      const quoteBefore = await getTokenBalance(maker.quote);
+      await program.methods.claimRebate().accountsPartial(claimAccounts).signers([maker.keypair]).rpc();
+      expect((await getTokenBalance(maker.quote)) - quoteBefore).toBe(BigInt(ceilFee(notional, 5).toString()));
+      claimable = await program.account.claimableBalance.fetch(claimAccounts.claimable);
+      expect(claimable.rebate.toNumber()).toBe(0);
+      book = await program.account.orderbook.fetch(market.orderbook);
+      expect(book.rebatesOwed.toNumber()).toBe(0);
+
+      // 返佣比例高于已收取的手续费时，手续费金库不足以支付
+      await program.methods.setRebateBps(50).accounts({ orderbook: market.orderbook, admin: payer.publicKey }).rpc();
+      await placeOrder(market, maker, "sell", toPriceAmount(10), unit);
+      await placeOrder(market, taker, "buy", toPriceAmount(10), unit, { makers: [maker] });
+      await expect(
+        program.methods.claimRebate().accountsPartial(claimAccounts).signers([maker.keypair]).rpc()
+      ).rejects.toThrow(/InsufficientRebatePool/);
+    },
+    60000
+  );
+
+  // 测试原生 SOL 报价：买单从 SOL 余额包装存入，指令结束时关闭临时 wSOL 账户，退款以 SOL 返还；
+  // maker 的所得转入托管 wSOL 账户，通过 claim_native 以 SOL 领取
+  it(
+    "Wraps and unwraps a native SOL quote leg",
+    async () => {
+      // 标记为原生 SOL 的一方必须是 wSOL
+      const plainBase = await createMint(connection, payer.payer, payer.publicKey, null, BASE_DECIMALS);
+      const plainQuote = await createMint(connection, payer.payer, payer.publicKey, null, QUOTE_DECIMALS);
+      const [plainOrderbook] = PublicKey.findProgramAddressSync([Buffer.from("orderbook"), plainBase.toBuffer(), plainQuote.toBuffer()], program.programId);
+      await expect(
+        program.methods
+          .initialize(new BN(0), { makerPrice: {} }, 0, 0, new BN(0), new BN(1), { quote: {} }, false, 0)
+          .accountsPartial({ orderbook: plainOrderbook, baseMint: plainBase, quoteMint: plainQuote, payer: payer.publicKey, tokenProgram: TOKEN_PROGRAM_ID })
+          .rpc()
+      ).rejects.toThrow(/InvalidNativeMint/);
+
+      const market = await setupMarket({ nativeQuote: true });
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+      const price = new BN(1000); // 每个基础代币单位 1000 lamports
+      const notional = price.mul(unit);
+
+      // maker 卖出基础代币：挂单的 SOL 所得转入其托管 wSOL 账户（authority 为其待领取余额账户）
+      const maker = Keypair.generate();
+      await fundSol(maker.publicKey);
+      const makerBase = await createAccount(connection, payer.payer, market.baseMint, maker.publicKey);
+      await mintTo(connection, payer.payer, market.baseMint, makerBase, payer.payer, unit.toNumber());
+      const makerClaimable = claimablePda(market.orderbook, maker.publicKey);
+      const makerEscrow = await createAccount(connection, payer.payer, NATIVE_MINT, makerClaimable, Keypair.generate());
+      let makerTemp = await createAccount(connection, payer.payer, NATIVE_MINT, maker.publicKey, Keypair.generate());
+      const placeMakerOrder = (options: { nativeEscrow: PublicKey | null; settlementAuthority: PublicKey | null }) =>
+        program.methods
+          .placeOrder({ sell: {} }, price, unit, null, new BN(0), { limit: {} }, new BN(0), { goodTillCancel: {} }, false, new BN(0), { cancelResting: {} }, new BN(0), false, new BN(0), false, options.settlementAuthority, true, false)
+          .accountsPartial({
+            orderbook: market.orderbook,
+            owner: maker.publicKey,
+            ownerBaseTokenAccount: makerBase,
+            ownerQuoteTokenAccount: makerTemp,
+            referral: null,
+            oracle: null,
+            nativeEscrow: options.nativeEscrow,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([maker])
+          .rpc();
+
+      // 会挂单的订单必须传入托管账户，且不能指定结算方
+      await expect(placeMakerOrder({ nativeEscrow: null, settlementAuthority: null })).rejects.toThrow(/InvalidNativeEscrow/);
+      await expect(
+        placeMakerOrder({ nativeEscrow: makerEscrow, settlementAuthority: Keypair.generate().publicKey })
+      ).rejects.toThrow(/InvalidOrderParams/);
+      await placeMakerOrder({ nativeEscrow: makerEscrow, settlementAuthority: null });
+
+      // taker 持有 SOL，以临时 wSOL 账户下单，限价高于卖单价格：多锁定的部分退还后一并解包
+      const taker = Keypair.generate();
+      const takerBase = await createAccount(connection, payer.payer, market.baseMint, taker.publicKey);
+      const takerTemp = await createAccount(connection, payer.payer, NATIVE_MINT, taker.publicKey, Keypair.generate());
+      const transferTx = new Transaction().add(
+        SystemProgram.transfer({ fromPubkey: payer.publicKey, toPubkey: taker.publicKey, lamports: LAMPORTS_PER_SOL })
+      );
+      await sendAndConfirmTransaction(connection, transferTx, [payer.payer]);
+      const rent = await connection.getMinimumBalanceForRentExemption(165);
+      const claimableRent = await connection.getMinimumBalanceForRentExemption(program.account.claimableBalance.size);
+      const lamportsBefore = await connection.getBalance(taker.publicKey);
+
+      // IOC 订单不会挂单，无需托管账户
+      await program.methods
+        .placeOrder({ buy: {} }, price.muln(2), unit, null, new BN(0), { limit: {} }, new BN(0), { immediateOrCancel: {} }, false, new BN(0), { cancelResting: {} }, new BN(0), false, new BN(0), false, null, true, false)
+        .accountsPartial({
+          orderbook: market.orderbook,
+          owner: taker.publicKey,
+          ownerBaseTokenAccount: takerBase,
+          ownerQuoteTokenAccount: takerTemp,
+          referral: null,
+          oracle: null,
+          nativeEscrow: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts([
+          { pubkey: makerBase, isSigner: false, isWritable: true },
+          { pubkey: makerEscrow, isSigner: false, isWritable: true },
+          { pubkey: makerClaimable, isSigner: false, isWritable: true },
+        ])
+        .signers([taker])
+        .rpc();
+
+      // taker 只花费成交额与首次下单创建待领取余额账户的租金（临时账户的租金随关闭返还），临时 wSOL 账户已关闭
+      expect(lamportsBefore - (await connection.getBalance(taker.publicKey))).toBe(notional.toNumber() - rent + claimableRent);
+      expect(await connection.getAccountInfo(takerTemp)).toBeNull();
+      expect(await getTokenBalance(takerBase)).toBe(BigInt(unit.toString()));
+      expect(await getTokenBalance(makerEscrow)).toBe(BigInt(notional.toString()));
+      expect(await getTokenBalance(market.quoteVault)).toBe(BigInt(0));
+
+      // maker 以 SOL 领取托管账户中的所得：经临时 wSOL 账户解包，托管账户保留供后续挂单继续使用
+      makerTemp = await createAccount(connection, payer.payer, NATIVE_MINT, maker.publicKey, Keypair.generate());
+      const makerLamportsBefore = await connection.getBalance(maker.publicKey);
+      await program.methods
+        .claimNative()
+        .accountsPartial({
+          orderbook: market.orderbook,
+          owner: maker.publicKey,
+          claimable: makerClaimable,
+          nativeEscrow: makerEscrow,
+          nativeMint: NATIVE_MINT,
+          ownerWsolAccount: makerTemp,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([maker])
+        .rpc();
+      expect((await connection.getBalance(maker.publicKey)) - makerLamportsBefore).toBe(notional.toNumber() + rent);
+      expect(await connection.getAccountInfo(makerTemp)).toBeNull();
+      expect(await getTokenBalance(makerEscrow)).toBe(BigInt(0));
+      expect(await connection.getAccountInfo(makerEscrow)).not.toBeNull();
+    },
+    60000
+  );
+
+  // 测试撤单方向提示：只在指定方向查找，方向不符时返回 OrderNotFound 且不退款
+  it(
+    "Rejects a cancel with the wrong side hint",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      const signature = await placeOrder(market, maker, "buy", toPriceAmount(10), unit);
+      const orderId = (await getEvents(signature)).find((e) => e.name === "orderPlacedEvent")!.data.orderId;
+      const cancel = (side: { buy: {} } | { sell: {} }) =>
+        program.methods
+          .cancelOrder(orderId, side)
+          .accountsPartial({
+            orderbook: market.orderbook,
+            owner: maker.keypair.publicKey,
+            ownerBaseTokenAccount: maker.base,
+            ownerQuoteTokenAccount: maker.quote,
+            baseVault: market.baseVault,
+            quoteVault: market.quoteVault,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([maker.keypair])
+          .rpc();
+
+      const quoteBefore = await getTokenBalance(maker.quote);
+      await expect(cancel({ sell: {} })).rejects.toThrow(/OrderNotFound/);
+      expect(await getTokenBalance(maker.quote)).toBe(quoteBefore);
+      let orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.bids.length).toBe(1);
+
+      // 正确的方向正常撤单
+      await cancel({ buy: {} });
+      expect((await getTokenBalance(maker.quote)) - quoteBefore).toBe(BigInt(toPriceAmount(10).mul(unit).toString()));
+      orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.bids.length).toBe(0);
+    },
+    60000
+  );
+
+  // 测试部分撤单：退还对应的锁定资金并保留排队位置，减少全部数量时等同完整撤单
+  it(
+    "Partially cancels a resting order without losing priority",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const other = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+      const price = toPriceAmount(10);
+
+      const signature = await placeOrder(market, maker, "buy", price, unit.muln(3));
+      const orderId = (await getEvents(signature)).find((e) => e.name === "orderPlacedEvent")!.data.orderId;
+      await placeOrder(market, other, "buy", price, unit);
+      const cancelPartial = (reduceBy: BN) =>
+        program.methods
+          .cancelPartial(orderId, reduceBy)
+          .accountsPartial({
+            orderbook: market.orderbook,
+            owner: maker.keypair.publicKey,
+            ownerBaseTokenAccount: maker.base,
+            ownerQuoteTokenAccount: maker.quote,
+            baseVault: market.baseVault,
+            quoteVault: market.quoteVault,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([maker.keypair])
+          .rpc({ commitment: "confirmed" });
+
+      // 减少 1 个：退还 10 × 1 的报价代币并触发减量事件，订单 ID 与排队位置不变
+      const quoteBefore = await getTokenBalance(maker.quote);
+      const reducedSignature = await cancelPartial(unit);
+      expect((await getTokenBalance(maker.quote)) - quoteBefore).toBe(BigInt(price.mul(unit).toString()));
+      const reducedEvents = await getEvents(reducedSignature);
+      expect(reducedEvents.filter((e) => e.name === "orderCanceledEvent").length).toBe(0);
+      const reduced = reducedEvents.filter((e) => e.name === "orderReducedEvent");
+      expect(reduced.length).toBe(1);
+      expect(reduced[0].data.orderId.eq(orderId)).toBe(true);
+      expect(reduced[0].data.reducedQuantity.eq(unit)).toBe(true);
+      expect(reduced[0].data.remainingQuantity.eq(unit.muln(2))).toBe(true);
+      expect(reduced[0].data.refundMint.equals(market.quoteMint)).toBe(true);
+      expect(reduced[0].data.refundAmount.eq(price.mul(unit))).toBe(true);
+      let book = await program.account.orderbook.fetch(market.orderbook);
+      expect(book.bids[0].orderId.eq(orderId)).toBe(true);
+      expect(book.bids[0].quantity.eq(unit.muln(2))).toBe(true);
+      expect(book.bids[0].lockedQuote.eq(price.mul(unit.muln(2)))).toBe(true);
+
+      // 减少的数量超过剩余数量
+      await expect(cancelPartial(unit.muln(3))).rejects.toThrow(/ReduceExceedsQuantity/);
+
+      // 减少全部剩余数量：移除挂单并触发撤单事件
+      const canceledSignature = await cancelPartial(unit.muln(2));
+      const canceledEvents = await getEvents(canceledSignature);
+      expect(canceledEvents.filter((e) => e.name === "orderReducedEvent").length).toBe(0);
+      const canceled = canceledEvents.filter((e) => e.name === "orderCanceledEvent");
+      expect(canceled.length).toBe(1);
+      expect(canceled[0].data.refundAmount.eq(price.mul(unit.muln(2)))).toBe(true);
+      expect((await getTokenBalance(maker.quote)) - quoteBefore).toBe(BigInt(price.mul(unit.muln(3)).toString()));
+      book = await program.account.orderbook.fetch(market.orderbook);
+      expect(book.bids.length).toBe(1);
+      expect(book.bids[0].owner.equals(other.keypair.publicKey)).toBe(true);
+    },
+    60000
+  );
+
+  // 测试成交事件中的订单 ID：不挂单的 taker 订单同样分配 ID，序列号逐笔递增
+  it(
+    "Tags trades with taker and maker order ids",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      const makerIds: BN[] = [];
+      for (const price of [10, 11]) {
+        const signature = await placeOrder(market, maker, "sell", toPriceAmount(price), unit);
+        makerIds.push((await getEvents(signature)).find((e) => e.name === "orderPlacedEvent")!.data.orderId);
+      }
+
+      // IOC 买单吃掉两个卖单，不会挂单但同样获得 ID
+      const signature = await placeOrder(market, taker, "buy", toPriceAmount(11), unit.muln(2), {
+        makers: [maker, maker],
+        timeInForce: "ioc",
+      });
+      const trades = (await getEvents(signature)).filter((e) => e.name === "tradeEvent");
+      expect(trades.length).toBe(2);
+      const takerOrderId = trades[0].data.takerOrderId;
+      expect(takerOrderId.gt(makerIds[1])).toBe(true);
+      expect(trades[1].data.takerOrderId.eq(takerOrderId)).toBe(true);
+      expect(trades[0].data.makerOrderId.eq(makerIds[0])).toBe(true);
+      expect(trades[1].data.makerOrderId.eq(makerIds[1])).toBe(true);
+      expect(trades[1].data.sequenceNumber.eq(trades[0].data.sequenceNumber.addn(1))).toBe(true);
+
+      // 下一个订单的 ID 紧随 taker 订单之后
+      const next = await placeOrder(market, maker, "sell", toPriceAmount(12), unit);
+      const nextId = (await getEvents(next)).find((e) => e.name === "orderPlacedEvent")!.data.orderId;
+      expect(nextId.eq(takerOrderId.addn(1))).toBe(true);
+    },
+    60000
+  );
+
+  // 测试 maker 账户顺序：基础/报价代币账户颠倒时拒绝撮合，资金不会转入错误的账户
+  it(
+    "Rejects maker accounts passed in the wrong order",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      await placeOrder(market, maker, "sell", toPriceAmount(10), unit);
+      await placeOrder(market, maker, "buy", toPriceAmount(8), unit);
+      const makerBaseBefore = await getTokenBalance(maker.base);
+      const makerQuoteBefore = await getTokenBalance(maker.quote);
+
+      // 两个账户都属于 maker，但顺序颠倒
+      const swappedMaker = { ...maker, base: maker.quote, quote: maker.base };
+      await expect(
+        placeOrder(market, taker, "buy", toPriceAmount(10), unit, { makers: [swappedMaker] })
+      ).rejects.toThrow(/MakerMintMismatch/);
+      await expect(
+        placeOrder(market, taker, "sell", toPriceAmount(8), unit, { makers: [swappedMaker] })
+      ).rejects.toThrow(/MakerMintMismatch/);
+
+      expect(await getTokenBalance(maker.base)).toBe(makerBaseBefore);
+      expect(await getTokenBalance(maker.quote)).toBe(makerQuoteBefore);
+      const orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.asks.length).toBe(1);
+      expect(orderbookAccount.bids.length).toBe(1);
+    },
+    60000
+  );
+
+  // 测试冰山单：每次只有显示部分可以成交，显示部分耗尽后从隐藏部分补充并失去时间优先
+  it(
+    "Matches iceberg orders one displayed slice at a time",
+    async () => {
+      const market = await setupMarket();
+      const iceberg = await createTrader(market);
+      const other = await createTrader(market);
+      const taker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+      const price = toPriceAmount(10);
+
+      // 冰山卖单共 5 个，每次显示 2 个；全部数量在挂单时锁定
+      let signature = await placeOrder(market, iceberg, "sell", price, unit.muln(5), { displayQuantity: unit.muln(2) });
+      const icebergId = (await getEvents(signature)).find((e) => e.name === "orderPlacedEvent")!.data.orderId;
+      await placeOrder(market, other, "sell", price, unit);
+      expect(await getTokenBalance(market.baseVault)).toBe(BigInt(unit.muln(6).toString()));
+      let book = await program.account.orderbook.fetch(market.orderbook);
+      expect(book.asks[0].displayQuantity.eq(unit.muln(2))).toBe(true);
+
+      // 买入 3 个：冰山单只成交显示的 2 个，补充后排到同价卖单之后，剩余 1 个与另一个卖单成交
+      signature = await placeOrder(market, taker, "buy", price, unit.muln(3), { makers: [iceberg, other] });
+      const trades = (await getEvents(signature)).filter((e) => e.name === "tradeEvent");
+      expect(trades.length).toBe(2);
+      expect(trades[0].data.makerOrderId.eq(icebergId)).toBe(true);
+      expect(trades[0].data.quantity.eq(unit.muln(2))).toBe(true);
+      expect(trades[1].data.maker.equals(other.keypair.publicKey)).toBe(true);
+      expect(trades[1].data.quantity.eq(unit)).toBe(true);
+
+      // 冰山单以新的订单 ID 显示下一部分，隐藏数量无需追加存入
+      book = await program.account.orderbook.fetch(market.orderbook);
+      expect(book.asks.length).toBe(1);
+      expect(book.asks[0].orderId.gt(icebergId)).toBe(true);
+      expect(book.asks[0].quantity.eq(unit.muln(3))).toBe(true);
+      expect(book.asks[0].displayQuantity.eq(unit.muln(2))).toBe(true);
+      expect(book.asks[0].lockedBase.eq(unit.muln(3))).toBe(true);
+    },
+    60000
+  );
+
+  // 测试价格缩放：价格表示每个完整基础代币的报价代币最小单位数，成交金额除以 10^base_decimals
+  it(
+    "Interprets prices per whole base token when price scaling is enabled",
+    async () => {
+      const market = await setupMarket({ priceIsScaled: true });
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      const orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.priceIsScaled).toBe(true);
+      expect(orderbookAccount.baseDecimals).toBe(BASE_DECIMALS);
+      expect(orderbookAccount.quoteDecimals).toBe(QUOTE_DECIMALS);
+
+      // 每个基础代币 2.5 个报价代币，买入 2 个基础代币共支付 5 个报价代币
+      const price = toTokenAmount(2.5, QUOTE_DECIMALS);
+      const quantity = toTokenAmount(2, BASE_DECIMALS);
+      const takerQuoteBefore = await getTokenBalance(taker.quote);
+      const makerQuoteBefore = await getTokenBalance(maker.quote);
+      await placeOrder(market, maker, "sell", price, quantity);
+      await placeOrder(market, taker, "buy", price, quantity, { makers: [maker] });
+
+      const notional = BigInt(toTokenAmount(5, QUOTE_DECIMALS).toString());
+      expect(takerQuoteBefore - (await getTokenBalance(taker.quote))).toBe(notional);
+      expect((await getTokenBalance(maker.quote)) - makerQuoteBefore).toBe(notional);
+      expect(await getTokenBalance(market.quoteVault)).toBe(BigInt(0));
+    },
+    60000
+  );
+
+  // 测试失效 maker：maker 关闭关联代币账户后，撮合撤销其挂单并把锁定资金记入待领取余额，
+  // 而不是让整笔 taker 订单失败；其他无效的 maker 账户一律报错，taker 无法借此跳过挂单
+  it(
+    "Cancels a maker whose token account was closed instead of reverting the taker",
+    async () => {
+      const market = await setupMarket();
+      const stale = await createTrader(market);
+      const other = await createTrader(market);
+      const taker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+      const price = toPriceAmount(10);
+
+      let signature = await placeOrder(market, stale, "sell", price, unit);
+      const staleId = (await getEvents(signature)).find((e) => e.name === "orderPlacedEvent")!.data.orderId;
+      await placeOrder(market, other, "sell", price, unit);
+
+      // 伪造的代币账户（不存在但不是 maker 的关联代币账户）不能用来跳过挂单
+      await expect(
+        placeOrder(market, taker, "buy", price, unit, {
+          makers: [{ ...stale, quote: Keypair.generate().publicKey }, other],
+        })
+      ).rejects.toThrow(/InvalidMakerAccount/);
+
+      // 失效 maker 转出全部报价代币并关闭报价代币关联账户
+      const staleQuote = await getTokenBalance(stale.quote);
+      await transfer(connection, payer.payer, stale.quote, other.quote, stale.keypair, staleQuote);
+      await closeAccount(connection, payer.payer, stale.quote, payer.publicKey, stale.keypair);
+
+      // taker 仍按订单簿顺序传入两个 maker 的账户，失效挂单被撤销，与下一个卖单成交
+      signature = await placeOrder(market, taker, "buy", price, unit, { makers: [stale, other] });
+      const events = await getEvents(signature);
+      const canceled = events.find((e) => e.name === "orderCanceledEvent")!;
+      expect(canceled.data.orderId.eq(staleId)).toBe(true);
+      expect(canceled.data.owner.equals(stale.keypair.publicKey)).toBe(true);
+      expect(canceled.data.refundMint.equals(market.baseMint)).toBe(true);
+      expect(canceled.data.refundAmount.eq(unit)).toBe(true);
+      const trades = events.filter((e) => e.name === "tradeEvent");
+      expect(trades.length).toBe(1);
+      expect(trades[0].data.maker.equals(other.keypair.publicKey)).toBe(true);
+
+      // 失效挂单已从订单簿移除，锁定的基础代币记入拥有者的待领取余额并留在金库中
+      const claimable = claimablePda(market.orderbook, stale.keypair.publicKey);
+      let orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.asks.length).toBe(0);
+      expect(orderbookAccount.claimableBase.eq(unit)).toBe(true);
+      expect((await program.account.claimableBalance.fetch(claimable)).base.eq(unit)).toBe(true);
+      expect(await getTokenBalance(market.baseVault)).toBe(BigInt(unit.toString()));
+
+      // 拥有者重新创建报价代币账户后领取
+      await createAssociatedTokenAccountIdempotent(connection, payer.payer, market.quoteMint, stale.keypair.publicKey);
+      const baseBefore = await getTokenBalance(stale.base);
+      await program.methods
+        .claimBalance()
+        .accountsPartial({
+          orderbook: market.orderbook,
+          baseMint: market.baseMint,
+          quoteMint: market.quoteMint,
+          owner: stale.keypair.publicKey,
+          claimable,
+          ownerBaseTokenAccount: stale.base,
+          ownerQuoteTokenAccount: stale.quote,
+          baseVault: market.baseVault,
+          quoteVault: market.quoteVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([stale.keypair])
+        .rpc();
+      expect((await getTokenBalance(stale.base)) - baseBefore).toBe(BigInt(unit.toString()));
+      expect((await program.account.claimableBalance.fetch(claimable)).base.toNumber()).toBe(0);
+      orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.claimableBase.toNumber()).toBe(0);
+      expect(await getTokenBalance(market.baseVault)).toBe(BigInt(0));
+    },
+    60000
+  );
+
+  // 测试订单名义金额下限：价格 × 数量低于下限的限价单在锁定资金前被拒绝
+  it(
+    "Rejects limit orders below the minimum quote notional",
+    async () => {
+      const market = await setupMarket();
+      const trader = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+      const price = toPriceAmount(10);
+      const minNotional = price.mul(unit).muln(2);
+
+      // 只有管理员可以设置下限
+      await expect(
+        program.methods
+          .setMinQuoteNotional(minNotional)
+          .accounts({ orderbook: market.orderbook, admin: trader.keypair.publicKey })
+          .signers([trader.keypair])
+          .rpc()
+      ).rejects.toThrow(/Unauthorized/);
+      await program.methods.setMinQuoteNotional(minNotional).accounts({ orderbook: market.orderbook, admin: payer.publicKey }).rpc();
+
+      const quoteBefore = await getTokenBalance(trader.quote);
+      await expect(placeOrder(market, trader, "buy", price, unit)).rejects.toThrow(/NotionalTooSmall/);
+      await expect(placeOrder(market, trader, "sell", price, unit)).rejects.toThrow(/NotionalTooSmall/);
+      expect(await getTokenBalance(trader.quote)).toBe(quoteBefore);
+
+      // 恰好等于下限的订单可以挂单
+      await placeOrder(market, trader, "buy", price, unit.muln(2));
+      const orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.minQuoteNotional.eq(minNotional)).toBe(true);
+      expect(orderbookAccount.bids.length).toBe(1);
+    },
+    60000
+  );
+
+  // 测试按报价代币预算买入：逐笔按 maker 价格换算买入数量直到预算用完，剩余预算按限价换算后挂单，零头退还
+  it(
+    "Spends a quote budget across makers and rests the remainder at the limit price",
+    async () => {
+      const market = await setupMarket();
+      const cheap = await createTrader(market);
+      const dear = await createTrader(market);
+      const taker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+      const half = unit.divn(2);
+      await placeOrder(market, cheap, "sell", toPriceAmount(10), unit);
+      await placeOrder(market, dear, "sell", toPriceAmount(12), unit);
+
+      // 只有买单支持报价预算
+      await expect(
+        placeOrder(market, taker, "sell", toPriceAmount(12), new BN(1000), { quoteBudget: true })
+      ).rejects.toThrow(/InvalidOrderParams/);
+
+      // 预算买入 1 个（价格 10）和半个（价格 12）后剩余 5，不足以按最优卖价买入一个最小单位，退还而不挂单
+      const budget = unit.muln(10).add(half.muln(12)).addn(5);
+      let quoteBefore = await getTokenBalance(taker.quote);
+      let baseBefore = await getTokenBalance(taker.base);
+      await placeOrder(market, taker, "buy", toPriceAmount(12), budget, { makers: [cheap, dear], quoteBudget: true });
+      expect(quoteBefore - (await getTokenBalance(taker.quote))).toBe(BigInt(budget.subn(5).toString()));
+      expect((await getTokenBalance(taker.base)) - baseBefore).toBe(BigInt(unit.add(half).toString()));
+      let book = await program.account.orderbook.fetch(market.orderbook);
+      expect(book.bids.length).toBe(0);
+      expect(book.asks[0].quantity.eq(half)).toBe(true);
+
+      // 买完剩余的半个后，剩余预算按限价 12 换算为 2 个挂单，零头 7 退还
+      const secondBudget = half.muln(12).add(unit.muln(24)).addn(7);
+      quoteBefore = await getTokenBalance(taker.quote);
+      await placeOrder(market, taker, "buy", toPriceAmount(12), secondBudget, { makers: [dear], quoteBudget: true });
+      expect(quoteBefore - (await getTokenBalance(taker.quote))).toBe(BigInt(secondBudget.subn(7).toString()));
+      book = await program.account.orderbook.fetch(market.orderbook);
+      expect(book.asks.length).toBe(0);
+      expect(book.bids.length).toBe(1);
+      expect(book.bids[0].quantity.eq(unit.muln(2))).toBe(true);
+      expect(book.bids[0].lockedQuote.eq(unit.muln(24))).toBe(true);
+    },
+    60000
+  );
+
+  // 测试 maker 账户预检：撮合会涉及的 maker 多于传入的账户时，在撮合开始前直接报错
+  it(
+    "Rejects a taker that supplies fewer maker accounts than its fills need",
+    async () => {
+      const market = await setupMarket();
+      const first = await createTrader(market);
+      const second = await createTrader(market);
+      const taker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+      await placeOrder(market, first, "sell", toPriceAmount(10), unit);
+      await placeOrder(market, second, "sell", toPriceAmount(11), unit);
+
+      // 买入 2 个会与两个卖单成交，只传入第一个 maker 的账户
+      const quoteBefore = await getTokenBalance(taker.quote);
+      await expect(
+        placeOrder(market, taker, "buy", toPriceAmount(11), unit.muln(2), { makers: [first] })
+      ).rejects.toThrow(/InsufficientMakerAccounts/);
+      expect(await getTokenBalance(taker.quote)).toBe(quoteBefore);
+
+      // 只与第一个卖单交叉时一个 maker 的账户就足够
+      await placeOrder(market, taker, "buy", toPriceAmount(10), unit.muln(2), { makers: [first] });
+      const orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.asks.length).toBe(1);
+      expect(orderbookAccount.bids[0].quantity.eq(unit)).toBe(true);
+    },
+    60000
+  );
+
+  // 测试订单簿检查：健康时不触发事件，交叉时触发 BookCrossedEvent，并可使用传入的账户撮合交叉挂单
+  it(
+    "Reports a crossed book and optionally uncrosses it",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+      const validateAccounts = {
+        orderbook: market.orderbook,
+        baseVault: market.baseVault,
+        quoteVault: market.quoteVault,
+        baseFeeVault: market.baseFeeVault,
+        quoteFeeVault: market.quoteFeeVault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      };
+
+      await placeOrder(market, maker, "sell", toPriceAmount(10), unit);
+      let signature = await program.methods.validateBook(0).accountsPartial(validateAccounts).rpc({ commitment: "confirmed" });
+      expect((await getEvents(signature)).length).toBe(0);
+
+      // 延迟撮合的买单使订单簿交叉，只检查时仅触发事件
+      await placeOrder(market, taker, "buy", toPriceAmount(11), unit, { deferMatching: true });
+      signature = await program.methods.validateBook(0).accountsPartial(validateAccounts).rpc({ commitment: "confirmed" });
+      const crossed = (await getEvents(signature)).find((e) => e.name === "bookCrossedEvent")!;
+      expect(crossed.data.bestBidPrice.eq(toPriceAmount(11))).toBe(true);
+      expect(crossed.data.bestAskPrice.eq(toPriceAmount(10))).toBe(true);
+      let book = await program.account.orderbook.fetch(market.orderbook);
+      expect(book.bids.length).toBe(1);
+
+      // 传入账户后撮合交叉挂单，订单簿恢复健康
+      signature = await program.methods
+        .validateBook(4)
+        .accountsPartial(validateAccounts)
+        .remainingAccounts([taker, maker].flatMap((trader) => makerAccounts(market.orderbook, trader)))
+        .rpc({ commitment: "confirmed" });
+      expect((await getEvents(signature)).filter((e) => e.name === "tradeEvent").length).toBe(1);
+      book = await program.account.orderbook.fetch(market.orderbook);
+      expect(book.bids.length).toBe(0);
+      expect(book.asks.length).toBe(0);
+    },
+    60000
+  );
+
+  // 测试基础代币与报价代币相同的订单簿无法初始化
+  it(
+    "Rejects an orderbook whose base and quote mints are the same",
+    async () => {
+      const mint = await createMint(connection, payer.payer, payer.publicKey, null, BASE_DECIMALS);
+      const [orderbook] = PublicKey.findProgramAddressSync([Buffer.from("orderbook"), mint.toBuffer(), mint.toBuffer()], program.programId);
+      await expect(
+        program.methods
+          .initialize(new BN(0), { makerPrice: {} }, 0, 0, new BN(0), new BN(1), { none: {} }, false, 0)
+          .accountsPartial({ orderbook, baseMint: mint, quoteMint: mint, payer: payer.publicKey, tokenProgram: TOKEN_PROGRAM_ID })
+          .rpc()
+      ).rejects.toThrow(/IdenticalMints/);
+      expect(await connection.getAccountInfo(orderbook)).toBeNull();
+    },
+    60000
+  );
+
+  // 测试 place_order 的返回数据：成交数量、按数量加权的成交均价、挂单 ID 以及报价代币收支
+  it(
+    "Returns a fill summary from place_order",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      const seller = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+      await placeOrder(market, maker, "sell", toPriceAmount(10), unit);
+      await placeOrder(market, maker, "sell", toPriceAmount(12), unit);
+
+      // 买入 3 个：以 10 和 12 各成交 1 个，剩余 1 个挂单
+      let signature = await placeOrder(market, taker, "buy", toPriceAmount(12), unit.muln(3), { makers: [maker, maker] });
+      const restingId = (await getEvents(signature)).find((e) => e.name === "orderPlacedEvent")!.data.orderId;
+      let result = await getPlaceOrderResult(signature);
+      expect(result.filledQuantity.eq(unit.muln(2))).toBe(true);
+      expect(result.averageFillPrice.eq(toPriceAmount(11))).toBe(true);
+      expect(result.restingOrderId.eq(restingId)).toBe(true);
+      expect(result.quoteSpent.eq(unit.muln(22))).toBe(true);
+      expect(result.quoteReceived.isZero()).toBe(true);
+
+      // 卖出 1 个与剩余买单全部成交，没有挂单
+      signature = await placeOrder(market, seller, "sell", toPriceAmount(12), unit, { makers: [taker] });
+      result = await getPlaceOrderResult(signature);
+      expect(result.filledQuantity.eq(unit)).toBe(true);
+      expect(result.averageFillPrice.eq(toPriceAmount(12))).toBe(true);
+      expect(result.restingOrderId.isZero()).toBe(true);
+      expect(result.quoteReceived.eq(unit.muln(12))).toBe(true);
+    },
+    60000
+  );
+
+  // 测试预言机价格带：配置预言机后，下单、改单、止损单下单与触发都必须传入订单簿配置的 Pyth 价格账户；
+  // 取消配置后恢复正常
+  it(
+    "Requires the configured oracle account when a price band is set",
+    async () => {
+      const market = await setupMarket();
+      const trader = await createTrader(market);
+      const stopper = await createTrader(market);
+      const maker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+      const oracle = Keypair.generate().publicKey;
+
+      // 配置预言机前：挂一个买单和一个止损单，并以 10 成交一笔使止损单满足触发条件
+      await placeOrder(market, trader, "buy", toPriceAmount(9), unit);
+      const restingId = (await program.account.orderbook.fetch(market.orderbook)).bids[0].orderId;
+      const stop = { triggerPrice: toPriceAmount(10), limitPrice: toPriceAmount(12) };
+      await placeOrder(market, stopper, "buy", toPriceAmount(12), unit, { stop });
+      await placeOrder(market, maker, "sell", toPriceAmount(10), unit);
+      await placeOrder(market, trader, "buy", toPriceAmount(10), unit, { makers: [maker] });
+
+      // 只有管理员可以设置预言机
+      await expect(
+        program.methods
+          .setOracle(oracle, 500, 60)
+          .accounts({ orderbook: market.orderbook, admin: trader.keypair.publicKey })
+          .signers([trader.keypair])
+          .rpc()
+      ).rejects.toThrow(/Unauthorized/);
+      await expect(
+        program.methods.setOracle(oracle, 10001, 60).accounts({ orderbook: market.orderbook, admin: payer.publicKey }).rpc()
+      ).rejects.toThrow(/InvalidFeeBps/);
+      await program.methods.setOracle(oracle, 500, 60).accounts({ orderbook: market.orderbook, admin: payer.publicKey }).rpc();
+      let orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.oracle!.equals(oracle)).toBe(true);
+      expect(orderbookAccount.maxDeviationBps).toBe(500);
+      expect(orderbookAccount.oracleMaxAge).toBe(60);
+
+      // 未传入预言机账户、传入其他账户或传入非 Pyth 程序拥有的账户都被拒绝
+      await expect(placeOrder(market, trader, "buy", toPriceAmount(10), unit)).rejects.toThrow(/InvalidOracle/);
+      await expect(
+        placeOrder(market, trader, "buy", toPriceAmount(10), unit, { oracle: market.orderbook })
+      ).rejects.toThrow(/InvalidOracle/);
+      await expect(
+        placeOrder(market, trader, "buy", toPriceAmount(10), unit, { oracle })
+      ).rejects.toThrow(/InvalidOracle/);
+
+      // 改单的新价格、止损单的限价以及触发后的限价同样需要预言机价格带检查
+      await expect(
+        program.methods
+          .modifyOrder(restingId, toPriceAmount(8), unit)
+          .accountsPartial({
+            orderbook: market.orderbook,
+            owner: trader.keypair.publicKey,
+            ownerBaseTokenAccount: trader.base,
+            ownerQuoteTokenAccount: trader.quote,
+            baseVault: market.baseVault,
+            quoteVault: market.quoteVault,
+            oracle: null,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([trader.keypair])
+          .rpc()
+      ).rejects.toThrow(/InvalidOracle/);
+      await expect(placeOrder(market, stopper, "buy", toPriceAmount(12), unit, { stop })).rejects.toThrow(/InvalidOracle/);
+      await expect(
+        program.methods
+          .triggerStops()
+          .accountsPartial({
+            orderbook: market.orderbook,
+            baseVault: market.baseVault,
+            quoteVault: market.quoteVault,
+            baseFeeVault: market.baseFeeVault,
+            quoteFeeVault: market.quoteFeeVault,
+            oracle: null,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .remainingAccounts(makerAccounts(market.orderbook, stopper))
+          .rpc()
+      ).rejects.toThrow(/InvalidOracle/);
+      orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.stops.length).toBe(1);
+
+      // 取消预言机后无需传入预言机账户
+      await program.methods.setOracle(null, 0, 0).accounts({ orderbook: market.orderbook, admin: payer.publicKey }).rpc();
+      await placeOrder(market, trader, "buy", toPriceAmount(10), unit);
+      orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.oracle).toBeNull();
+      expect(orderbookAccount.bids.length).toBe(2);
+    },
+    60000
+  );
+
+  // 测试成交量统计窗口：新订单簿的窗口起点为 0，第一笔成交跨越窗口边界，清零累计量并开启新窗口；
+  // 窗口内的后续成交继续累计
+  it(
+    "Resets the rolling volume when a fill crosses the window boundary",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      let orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.volumeWindowStart.isZero()).toBe(true);
+      expect(orderbookAccount.rollingVolumeBase.isZero()).toBe(true);
+
+      await placeOrder(market, maker, "sell", toPriceAmount(10), unit.muln(2));
+      await placeOrder(market, taker, "buy", toPriceAmount(10), unit, { makers: [maker] });
+      orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      const windowStart = orderbookAccount.volumeWindowStart.toNumber();
+      expect(Math.abs(windowStart - Date.now() / 1000)).toBeLessThan(120);
+      expect(orderbookAccount.rollingVolumeBase.eq(unit)).toBe(true);
+      expect(orderbookAccount.rollingVolumeQuote.eq(unit.muln(10))).toBe(true);
+
+      // 同一窗口内的成交（含卖方向）继续累计，窗口起点不变
+      await placeOrder(market, maker, "buy", toPriceAmount(8), unit);
+      await placeOrder(market, taker, "sell", toPriceAmount(8), unit, { makers: [maker] });
+      orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.volumeWindowStart.toNumber()).toBe(windowStart);
+      expect(orderbookAccount.rollingVolumeBase.eq(unit.muln(2))).toBe(true);
+      expect(orderbookAccount.rollingVolumeQuote.eq(unit.muln(18))).toBe(true);
+    },
+    60000
+  );
+
+  // 测试最优买卖价查询：空订单簿返回 0，挂单后返回各方向首位订单的价格与数量
+  it(
+    "Returns the best bid and ask via peek_best_bid_ask",
+    async () => {
+      const market = await setupMarket();
+      const trader = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      let top = await program.methods.peekBestBidAsk().accounts({ orderbook: market.orderbook }).view();
+      expect([top.bidPrice, top.bidQty, top.askPrice, top.askQty].every((v: BN) => v.isZero())).toBe(true);
+
+      await placeOrder(market, trader, "buy", toPriceAmount(8), unit);
+      await placeOrder(market, trader, "buy", toPriceAmount(9), unit.muln(2));
+      top = await program.methods.peekBestBidAsk().accounts({ orderbook: market.orderbook }).view();
+      expect(top.bidPrice.eq(toPriceAmount(9))).toBe(true);
+      expect(top.bidQty.eq(unit.muln(2))).toBe(true);
+      expect(top.askPrice.isZero() && top.askQty.isZero()).toBe(true);
+
+      await placeOrder(market, trader, "sell", toPriceAmount(12), unit);
+      await placeOrder(market, trader, "sell", toPriceAmount(11), unit.muln(3));
+      top = await program.methods.peekBestBidAsk().accounts({ orderbook: market.orderbook }).view();
+      expect(top.askPrice.eq(toPriceAmount(11))).toBe(true);
+      expect(top.askQty.eq(unit.muln(3))).toBe(true);
+    },
+    60000
+  );
+
+  // 测试挂单结算方：指定结算方的挂单，成交所得只能转入结算方的代币账户；未指定时仍按订单拥有者校验
+  it(
+    "Routes maker proceeds to the declared settlement authority",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const custodian = await createTrader(market);
+      const taker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      await placeOrder(market, maker, "sell", toPriceAmount(10), unit.muln(2), {
+        settlementAuthority: custodian.keypair.publicKey,
+      });
+      const orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.asks[0].settlementAuthority!.equals(custodian.keypair.publicKey)).toBe(true);
+
+      // 传入订单拥有者自己的代币账户被拒绝
+      await expect(
+        placeOrder(market, taker, "buy", toPriceAmount(10), unit, { makers: [maker] })
+      ).rejects.toThrow(/MakerAccountMismatch/);
+
+      // 传入结算方的代币账户后正常成交，报价代币转入结算方
+      const makerQuoteBefore = await getTokenBalance(maker.quote);
+      const custodianQuoteBefore = await getTokenBalance(custodian.quote);
+      await placeOrder(market, taker, "buy", toPriceAmount(10), unit, { makers: [{ ...custodian, orderOwner: maker.keypair.publicKey }] });
+      expect(await getTokenBalance(custodian.quote)).toBe(custodianQuoteBefore + BigInt(unit.muln(10).toString()));
+      expect(await getTokenBalance(maker.quote)).toBe(makerQuoteBefore);
+    },
+    60000
+  );
+
+  // 测试提取金库盈余：只提取超出挂单锁定资金的部分，没有盈余时报错
+  it(
+    "Sweeps only the vault surplus above locked collateral to the admin",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const recipient = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+      await placeOrder(market, maker, "sell", toPriceAmount(10), unit.muln(2));
+      await placeOrder(market, maker, "buy", toPriceAmount(5), unit);
+      const baseLocked = await getTokenBalance(market.baseVault);
+      const quoteLocked = await getTokenBalance(market.quoteVault);
+
+      const sweepAccounts = (admin: PublicKey) => ({
+        orderbook: market.orderbook,
+        admin,
+        baseVault: market.baseVault,
+        quoteVault: market.quoteVault,
+        adminBaseTokenAccount: recipient.base,
+        adminQuoteTokenAccount: recipient.quote,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      });
+      // 金库余额恰好等于锁定资金时没有盈余
+      await expect(
+        program.methods.sweepSurplus().accountsPartial(sweepAccounts(payer.publicKey)).rpc()
+      ).rejects.toThrow(/NoSurplus/);
+
+      // 直接转入金库的代币不属于任何挂单
+      await transfer(connection, payer.payer, maker.base, market.baseVault, maker.keypair, 3);
+      await transfer(connection, payer.payer, maker.quote, market.quoteVault, maker.keypair, 7);
+      await expect(
+        program.methods
+          .sweepSurplus()
+          .accountsPartial(sweepAccounts(maker.keypair.publicKey))
+          .signers([maker.keypair])
+          .rpc()
+      ).rejects.toThrow(/Unauthorized/);
+
+      const recipientBaseBefore = await getTokenBalance(recipient.base);
+      const recipientQuoteBefore = await getTokenBalance(recipient.quote);
+      await program.methods.sweepSurplus().accountsPartial(sweepAccounts(payer.publicKey)).rpc();
+      expect((await getTokenBalance(recipient.base)) - recipientBaseBefore).toBe(BigInt(3));
+      expect((await getTokenBalance(recipient.quote)) - recipientQuoteBefore).toBe(BigInt(7));
+      expect(await getTokenBalance(market.baseVault)).toBe(baseLocked);
+      expect(await getTokenBalance(market.quoteVault)).toBe(quoteLocked);
+      await expect(
+        program.methods.sweepSurplus().accountsPartial(sweepAccounts(payer.publicKey)).rpc()
+      ).rejects.toThrow(/NoSurplus/);
+    },
+    60000
+  );
+
+  // 测试集合竞价：竞价模式下交叉挂单不撮合，run_auction 选取成交量最大的统一清算价，所有成交按该价格结算
+  it(
+    "Runs a uniform-price batch auction in auction mode",
+    async () => {
+      const market = await setupMarket();
+      const [buyerA, buyerB, sellerC, sellerD] = await Promise.all([0, 1, 2, 3].map(() => createTrader(market)));
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      await expect(
+        program.methods
+          .setAuctionMode(true)
+          .accounts({ orderbook: market.orderbook, admin: buyerA.keypair.publicKey })
+          .signers([buyerA.keypair])
+          .rpc()
+      ).rejects.toThrow(/Unauthorized/);
+      await program.methods.setAuctionMode(true).accounts({ orderbook: market.orderbook, admin: payer.publicKey }).rpc();
+
+      // 交叉的限价单只挂单；不能挂单的市价单被拒绝
+      await placeOrder(market, buyerA, "buy", toPriceAmount(12), unit.muln(3));
+      await placeOrder(market, buyerB, "buy", toPriceAmount(10), unit.muln(2));
+      await placeOrder(market, sellerC, "sell", toPriceAmount(9), unit.muln(2));
+      await placeOrder(market, sellerD, "sell", toPriceAmount(11), unit.muln(4));
+      await expect(
+        placeOrder(market, buyerB, "buy", new BN(0), unit, { market: true, maxQuote: unit.muln(20) })
+      ).rejects.toThrow(/InvalidDeferredOrder/);
+      let orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.bids.length).toBe(2);
+      expect(orderbookAccount.asks.length).toBe(2);
+
+      const auctionAccounts = {
+        orderbook: market.orderbook,
+        baseVault: market.baseVault,
+        quoteVault: market.quoteVault,
+        baseFeeVault: market.baseFeeVault,
+        quoteFeeVault: market.quoteFeeVault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      };
+      // 竞价模式下不能连续撮合
+      await expect(program.methods.matchOrders(4).accountsPartial(auctionAccounts).rpc()).rejects.toThrow(
+        /AuctionModeActive/
+      );
+
+      // 价格 11 时需求 3、供给 6，成交量最大：A 先与 C 成交 2 个，再与 D 成交 1 个，均按 11 成交
+      const quoteA = await getTokenBalance(buyerA.quote);
+      const quoteC = await getTokenBalance(sellerC.quote);
+      const quoteD = await getTokenBalance(sellerD.quote);
+      const baseA = await getTokenBalance(buyerA.base);
+      const signature = await program.methods
+        .runAuction(10)
+        .accountsPartial(auctionAccounts)
+        .remainingAccounts([buyerA, sellerC, buyerA, sellerD].flatMap((trader) => makerAccounts(market.orderbook, trader)))
+        .rpc({ commitment: "confirmed" });
+
+      const events = await getEvents(signature);
+      const auction = events.find((e) => e.name === "auctionRunEvent")!.data;
+      expect(auction.clearingPrice.eq(toPriceAmount(11))).toBe(true);
+      expect(auction.volume.eq(unit.muln(3))).toBe(true);
+      expect(auction.fills).toBe(2);
+      const trades = events.filter((e) => e.name === "tradeEvent");
+      expect(trades.every((t) => t.data.price.eq(toPriceAmount(11)))).toBe(true);
+
+      // 买方 A 按 12 锁定、按 11 支付，差额退还
+      expect((await getTokenBalance(buyerA.base)) - baseA).toBe(BigInt(unit.muln(3).toString()));
+      expect((await getTokenBalance(buyerA.quote)) - quoteA).toBe(BigInt(unit.muln(3).toString()));
+      expect((await getTokenBalance(sellerC.quote)) - quoteC).toBe(BigInt(unit.muln(22).toString()));
+      expect((await getTokenBalance(sellerD.quote)) - quoteD).toBe(BigInt(unit.muln(11).toString()));
+
+      orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.bids.map((o) => [o.price.toNumber(), o.quantity.toString()])).toEqual([
+        [10, unit.muln(2).toString()],
+      ]);
+      expect(orderbookAccount.asks.map((o) => [o.price.toNumber(), o.quantity.toString()])).toEqual([
+        [11, unit.muln(3).toString()],
+      ]);
+    },
+    60000
+  );
+
+  // 测试每个拥有者的挂单数量上限：达到上限后不能再挂单，撤单或挂单全部成交后可以继续挂单
+  it(
+    "Limits the number of open orders per owner",
+    async () => {
+      const market = await setupMarket({ maxOrdersPerOwner: 2 });
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      await placeOrder(market, maker, "sell", toPriceAmount(10), unit);
+      let signature = await placeOrder(market, maker, "sell", toPriceAmount(11), unit);
+      const orderId = (await getEvents(signature)).find((e) => e.name === "orderPlacedEvent")!.data.orderId;
+      await expect(placeOrder(market, maker, "buy", toPriceAmount(5), unit)).rejects.toThrow(/TooManyOpenOrders/);
+      // 其他拥有者不受影响
+      await placeOrder(market, taker, "buy", toPriceAmount(5), unit);
+
+      // 撤单后可以再挂一个
+      await program.methods
+        .cancelOrder(orderId, { sell: {} })
+        .accountsPartial({
+          orderbook: market.orderbook,
+          owner: maker.keypair.publicKey,
+          ownerBaseTokenAccount: maker.base,
+          ownerQuoteTokenAccount: maker.quote,
+          baseVault: market.baseVault,
+          quoteVault: market.quoteVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([maker.keypair])
+        .rpc();
+      await placeOrder(market, maker, "sell", toPriceAmount(12), unit);
+      await expect(placeOrder(market, maker, "sell", toPriceAmount(13), unit)).rejects.toThrow(/TooManyOpenOrders/);
+
+      // 挂单全部成交后同样释放名额
+      await placeOrder(market, taker, "buy", toPriceAmount(10), unit, { makers: [maker] });
+      await placeOrder(market, maker, "sell", toPriceAmount(13), unit);
+      let orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.maxOrdersPerOwner).toBe(2);
+      expect(orderbookAccount.asks.filter((o) => o.owner.equals(maker.keypair.publicKey)).length).toBe(2);
+
+      // 改单重新插入时不计入被修改的挂单本身，达到上限时仍可修改价格
+      await program.methods
+        .modifyOrder(orderbookAccount.asks[1].orderId, toPriceAmount(14), unit)
+        .accountsPartial({
+          orderbook: market.orderbook,
+          owner: maker.keypair.publicKey,
+          ownerBaseTokenAccount: maker.base,
+          ownerQuoteTokenAccount: maker.quote,
+          baseVault: market.baseVault,
+          quoteVault: market.quoteVault,
+          oracle: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([maker.keypair])
+        .rpc();
+      orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.asks[1].price.eq(toPriceAmount(14))).toBe(true);
+
+      // 止损单不占用名额，但触发后剩余部分挂单时同样受上限约束：拥有者已达上限时剩余部分被撤销、
+      // 锁定资金记入待领取余额，同一次 trigger_stops 中其他拥有者的止损单照常挂单
+      const stopper = await createTrader(market);
+      const otherStopper = await createTrader(market);
+      const seller = await createTrader(market);
+      const stop = { triggerPrice: toPriceAmount(10), limitPrice: toPriceAmount(10) };
+      signature = await placeOrder(market, stopper, "buy", toPriceAmount(10), unit, { stop });
+      const cappedStopId = (await getEvents(signature)).find((e) => e.name === "stopOrderPlacedEvent")!.data.orderId;
+      signature = await placeOrder(market, otherStopper, "buy", toPriceAmount(10), unit, { stop });
+      const otherStopId = (await getEvents(signature)).find((e) => e.name === "stopOrderPlacedEvent")!.data.orderId;
+      await placeOrder(market, stopper, "buy", toPriceAmount(5), unit);
+      await placeOrder(market, stopper, "buy", toPriceAmount(6), unit);
+      await placeOrder(market, seller, "sell", toPriceAmount(10), unit);
+      await placeOrder(market, taker, "buy", toPriceAmount(10), unit, { makers: [seller] });
+      signature = await program.methods
+        .triggerStops()
+        .accountsPartial({
+          orderbook: market.orderbook,
+          baseVault: market.baseVault,
+          quoteVault: market.quoteVault,
+          baseFeeVault: market.baseFeeVault,
+          quoteFeeVault: market.quoteFeeVault,
+          oracle: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts([stopper, otherStopper].flatMap((trader) => makerAccounts(market.orderbook, trader)))
+        .rpc({ commitment: "confirmed" });
+      const events = await getEvents(signature);
+      expect(events.filter((e) => e.name === "stopOrderTriggeredEvent").length).toBe(2);
+      const canceled = events.filter((e) => e.name === "orderCanceledEvent");
+      expect(canceled.length).toBe(1);
+      expect(canceled[0].data.orderId.eq(cappedStopId)).toBe(true);
+      expect(canceled[0].data.refundAmount.eq(toPriceAmount(10).mul(unit))).toBe(true);
+      const claimable = await program.account.claimableBalance.fetch(claimablePda(market.orderbook, stopper.keypair.publicKey));
+      expect(claimable.quote.eq(toPriceAmount(10).mul(unit))).toBe(true);
+
+      orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.stops.length).toBe(0);
+      expect(orderbookAccount.bids.filter((o) => o.owner.equals(stopper.keypair.publicKey)).length).toBe(2);
+      const rested = orderbookAccount.bids.filter((o) => o.owner.equals(otherStopper.keypair.publicKey));
+      expect(rested.length).toBe(1);
+      expect(rested[0].orderId.eq(otherStopId)).toBe(true);
+    },
+    60000
+  );
+
+  // 测试手续费舍入方式：1 基点作用于极小金额时，向上取整、向下取整与四舍五入的结果不同
+  it(
+    "Rounds fees according to the configured rounding mode",
+    async () => {
+      const market = await setupMarket({ takerFeeBps: 1 });
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      await placeOrder(market, maker, "sell", new BN(1), new BN(20000));
+
+      // 买入 quantity 个最小单位，返回 taker 实收的基础代币与收取的手续费
+      const buy = async (quantity: number) => {
+        const baseBefore = await getTokenBalance(taker.base);
+        const feeBefore = await getTokenBalance(market.baseFeeVault);
+        await placeOrder(market, taker, "buy", new BN(1), new BN(quantity), { makers: [maker] });
+        return [(await getTokenBalance(taker.base)) - baseBefore, (await getTokenBalance(market.baseFeeVault)) - feeBefore];
+      };
+      const setRounding = (mode: { floor: {} } | { ceil: {} } | { nearest: {} }) =>
+        program.methods.setFeeRounding(mode).accounts({ orderbook: market.orderbook, admin: payer.publicKey }).rpc();
+
+      // 默认向上取整：1 × 1 基点的手续费为 1
+      expect((await program.account.orderbook.fetch(market.orderbook)).feeRounding).toEqual({ ceil: {} });
+      expect(await buy(1)).toEqual([BigInt(0), BigInt(1)]);
+
+      await expect(
+        program.methods
+          .setFeeRounding({ floor: {} })
+          .accounts({ orderbook: market.orderbook, admin: taker.keypair.publicKey })
+          .signers([taker.keypair])
+          .rpc()
+      ).rejects.toThrow(/Unauthorized/);
+      await setRounding({ floor: {} });
+      expect(await buy(1)).toEqual([BigInt(1), BigInt(0)]);
+      expect(await buy(5000)).toEqual([BigInt(5000), BigInt(0)]);
+
+      // 四舍五入：恰好一半（0.5）时向上
+      await setRounding({ nearest: {} });
+      expect(await buy(1)).toEqual([BigInt(1), BigInt(0)]);
+      expect(await buy(4999)).toEqual([BigInt(4999), BigInt(0)]);
+      expect(await buy(5000)).toEqual([BigInt(4999), BigInt(1)]);
+    },
+    60000
+  );
+
+  // 测试模拟下单：按撮合规则返回逐笔成交、剩余挂单数量与成交额，且不修改订单簿、不需要 maker 账户
+  it(
+    "Simulates an order without touching the book",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      await placeOrder(market, maker, "sell", toPriceAmount(10), unit);
+      await placeOrder(market, maker, "sell", toPriceAmount(11), unit.muln(2));
+      const before = await program.account.orderbook.fetch(market.orderbook);
+
+      const simulation = await program.methods
+        .simulateOrder({ buy: {} }, toPriceAmount(11), unit.muln(4))
+        .accounts({ orderbook: market.orderbook })
+        .view();
+      expect(simulation.fills.map((fill: any) => [fill.price.toString(), fill.quantity.toString()])).toEqual([
+        [toPriceAmount(10).toString(), unit.toString()],
+        [toPriceAmount(11).toString(), unit.muln(2).toString()],
+      ]);
+      expect(simulation.filledQuantity.eq(unit.muln(3))).toBe(true);
+      expect(simulation.restingQuantity.eq(unit)).toBe(true);
+      expect(simulation.quoteAmount.eq(unit.muln(32))).toBe(true);
+
+      // 不交叉的价格没有成交，全部挂单
+      const passive = await program.methods
+        .simulateOrder({ buy: {} }, toPriceAmount(9), unit)
+        .accounts({ orderbook: market.orderbook })
+        .view();
+      expect(passive.fills.length).toBe(0);
+      expect(passive.restingQuantity.eq(unit)).toBe(true);
+
+      const after = await program.account.orderbook.fetch(market.orderbook);
+      expect(after.asks.map((order: any) => order.quantity.toString())).toEqual(
+        before.asks.map((order: any) => order.quantity.toString())
+      );
+      expect(after.orderIdCounter.eq(before.orderIdCounter)).toBe(true);
+    },
+    60000
+  );
+
+  // 测试挂单被完全吃掉时的成交事件：剩余数量为 0 即表示挂单已移除，链下无需根据交易事件推算
+  it(
+    "Signals maker removal with a zero remaining quantity",
+    async () => {
+      const market = await setupMarket();
+      const makers = [await createTrader(market), await createTrader(market)];
+      const taker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      const orderIds: BN[] = [];
+      for (let i = 0; i < makers.length; i++) {
+        const signature = await placeOrder(market, makers[i], "buy", toPriceAmount(10 - i), unit.muln(2));
+        orderIds.push((await getEvents(signature)).find((e) => e.name === "orderPlacedEvent")!.data.orderId);
+      }
+
+      // 卖出 3 个：第一个买单全部成交并移除，第二个买单部分成交
+      const signature = await placeOrder(market, taker, "sell", toPriceAmount(9), unit.muln(3), { makers });
+      const filled = (await getEvents(signature)).filter((e) => e.name === "orderFilledEvent");
+      expect(filled.map((e) => [e.data.orderId.toString(), e.data.remainingQuantity.toString()])).toEqual([
+        [orderIds[0].toString(), "0"],
+        [orderIds[1].toString(), unit.toString()],
+      ]);
+      expect(filled.map((e) => e.data.fullFill)).toEqual([true, false]);
+      expect(filled.map((e) => e.data.owner.toBase58())).toEqual(makers.map((m) => m.keypair.publicKey.toBase58()));
+      expect(filled.every((e) => e.data.side.buy !== undefined)).toBe(true);
+
+      const orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.bids.map((o) => o.orderId.toString())).toEqual([orderIds[1].toString()]);
+    },
+    60000
+  );
+
+  // 测试使用 ATA 金库的订单簿：金库地址记录在订单簿中，下单、成交与撤单照常使用；
+  // 非订单簿 ATA 的金库在初始化时被拒绝
+  it(
+    "Trades against associated token account vaults",
+    async () => {
+      const market = await setupMarket({ ataVaults: true });
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      const orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.baseVault.equals(market.baseVault)).toBe(true);
+      expect(orderbookAccount.quoteVault.equals(market.quoteVault)).toBe(true);
+      const [pdaVault] = PublicKey.findProgramAddressSync([Buffer.from("base_vault"), market.orderbook.toBuffer()], program.programId);
+      expect(market.baseVault.equals(pdaVault)).toBe(false);
+
+      await placeOrder(market, maker, "sell", toPriceAmount(10), unit.muln(2));
+      expect(await getTokenBalance(market.baseVault)).toBe(BigInt(unit.muln(2).toString()));
+      await placeOrder(market, taker, "buy", toPriceAmount(10), unit, { makers: [maker] });
+      expect(await getTokenBalance(market.baseVault)).toBe(BigInt(unit.toString()));
+      expect(await getTokenBalance(taker.base)).toBe(BigInt(toTokenAmount(1001, BASE_DECIMALS).toString()));
+
+      // 基础代币与报价代币的 ATA 互换后不满足约束
+      const baseMint = await createMint(connection, payer.payer, payer.publicKey, null, BASE_DECIMALS);
+      const quoteMint = await createMint(connection, payer.payer, payer.publicKey, null, QUOTE_DECIMALS);
+      const [orderbook] = PublicKey.findProgramAddressSync([Buffer.from("orderbook"), baseMint.toBuffer(), quoteMint.toBuffer()], program.programId);
+      const [baseAta, quoteAta] = await Promise.all(
+        [baseMint, quoteMint].map((mint) =>
+          createAssociatedTokenAccountIdempotent(connection, payer.payer, mint, orderbook, {}, TOKEN_PROGRAM_ID, undefined, true)
+        )
+      );
+      await expect(
+        program.methods
+          .initializeWithVaults(new BN(0), { makerPrice: {} }, 0, 0, new BN(0), new BN(1), { none: {} }, false, 0)
+          .accountsPartial({
+            orderbook,
+            baseMint,
+            quoteMint,
+            baseVault: quoteAta,
+            quoteVault: baseAta,
+            baseFeeVault: PublicKey.findProgramAddressSync([Buffer.from("base_fee_vault"), orderbook.toBuffer()], program.programId)[0],
+            quoteFeeVault: PublicKey.findProgramAddressSync([Buffer.from("quote_fee_vault"), orderbook.toBuffer()], program.programId)[0],
+            payer: payer.publicKey,
+            systemProgram: SystemProgram.programId,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          })
+          .rpc()
+      ).rejects.toThrow();
+    },
+    60000
+  );
+
+  // 测试不挂单的限价单：按限价撮合，未成交部分退还实际锁定的资金，不留下挂单
+  it(
+    "Refunds the unmatched remainder when rest_remainder is false",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      // 买入 3 个、限价 11，只有 1 个价格为 10 的卖单：只支付 10，剩余锁定资金全部退还
+      await placeOrder(market, maker, "sell", toPriceAmount(10), unit);
+      await placeOrder(market, maker, "sell", toPriceAmount(12), unit);
+      const quoteBefore = await getTokenBalance(taker.quote);
+      await placeOrder(market, taker, "buy", toPriceAmount(11), unit.muln(3), { makers: [maker], restRemainder: false });
+      expect(quoteBefore - (await getTokenBalance(taker.quote))).toBe(BigInt(unit.muln(10).toString()));
+      let orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.bids.length).toBe(0);
+      expect(orderbookAccount.asks.map((o) => o.price.toString())).toEqual([toPriceAmount(12).toString()]);
+      expect(await getTokenBalance(market.quoteVault)).toBe(BigInt(0));
+
+      // 卖出方向同样只交付成交的基础代币
+      await placeOrder(market, maker, "buy", toPriceAmount(9), unit);
+      const baseBefore = await getTokenBalance(taker.base);
+      await placeOrder(market, taker, "sell", toPriceAmount(9), unit.muln(3), { makers: [maker], restRemainder: false });
+      expect(baseBefore - (await getTokenBalance(taker.base))).toBe(BigInt(unit.toString()));
+      orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.bids.length).toBe(0);
+      expect(orderbookAccount.asks.length).toBe(1);
+    },
+    60000
+  );
+
+  // 测试买卖两个方向的撮合结算对称：以中间价成交，买方手续费从基础代币中扣除，卖方手续费从报价代币中扣除，
+  // 买单按限价多锁定的报价代币在成交后退还
+  it(
+    "Settles buy and sell takers symmetrically",
+    async () => {
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+      const fillAgainstMaker = async (takerSide: "buy" | "sell") => {
+        const market = await setupMarket({ executionPriceMode: { midpoint: {} }, makerFeeBps: 100, takerFeeBps: 200 });
+        const maker = await createTrader(market);
+        const taker = await createTrader(market);
+        const accounts = [maker.base, maker.quote, taker.base, taker.quote, market.baseFeeVault, market.quoteFeeVault];
+        const before = await Promise.all(accounts.map(getTokenBalance));
+        // 卖单 10 与买单 12 交叉，中间价 11
+        const [takerPrice, makerPrice] = takerSide === "buy" ? [12, 10] : [10, 12];
+        await placeOrder(market, maker, takerSide === "buy" ? "sell" : "buy", toPriceAmount(makerPrice), unit);
+        await placeOrder(market, taker, takerSide, toPriceAmount(takerPrice), unit, { makers: [maker] });
+        const after = await Promise.all(accounts.map(getTokenBalance));
+        const orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+        expect(orderbookAccount.bids.length + orderbookAccount.asks.length).toBe(0);
+        expect(await getTokenBalance(market.baseVault)).toBe(BigInt(0));
+        expect(await getTokenBalance(market.quoteVault)).toBe(BigInt(0));
+        return after.map((balance, i) => balance - before[i]);
+      };
+
+      // [maker 基础, maker 报价, taker 基础, taker 报价, 基础手续费, 报价手续费]
+      expect(await fillAgainstMaker("buy")).toEqual(
+        [-1_000_000, 10_890_000, 980_000, -11_000_000, 20_000, 110_000].map(BigInt)
+      );
+      expect(await fillAgainstMaker("sell")).toEqual(
+        [990_000, -11_000_000, -1_000_000, 10_780_000, 10_000, 220_000].map(BigInt)
+      );
+    },
+    120000
+  );
+
+  // 测试挂单的下单数量：剩余数量随成交递减，下单数量保持不变；挂单前已部分成交的订单在挂单事件中可区分
+  it(
+    "Keeps the original quantity on partially filled orders",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      // 卖单 5 个，被吃掉 2 个后剩余 3 个，下单数量仍为 5
+      await placeOrder(market, maker, "sell", toPriceAmount(10), unit.muln(5));
+      await placeOrder(market, taker, "buy", toPriceAmount(10), unit.muln(2), { makers: [maker] });
+      let orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.asks[0].quantity.eq(unit.muln(3))).toBe(true);
+      expect(orderbookAccount.asks[0].originalQuantity.eq(unit.muln(5))).toBe(true);
+
+      // 买单 4 个先成交 3 个，剩余 1 个挂单：挂单事件同时给出剩余数量与下单数量
+      const signature = await placeOrder(market, taker, "buy", toPriceAmount(10), unit.muln(4), { makers: [maker] });
+      const placed = (await getEvents(signature)).find((e) => e.name === "orderPlacedEvent")!;
+      expect(placed.data.quantity.eq(unit)).toBe(true);
+      expect(placed.data.originalQuantity.eq(unit.muln(4))).toBe(true);
+      orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.bids[0].originalQuantity.eq(unit.muln(4))).toBe(true);
+      expect(orderbookAccount.asks.length).toBe(0);
+    },
+    60000
+  );
+
+  // 测试管理员强制撤单：退款到拥有者的代币账户；拥有者账户被冻结时转入管理员的托管账户
+  it(
+    "Force-cancels orders and escrows refunds for frozen owner accounts",
+    async () => {
+      const market = await setupMarket({ freezable: true });
+      const maker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+      const escrow = await createAccount(connection, payer.payer, market.baseMint, payer.publicKey, Keypair.generate());
+      const forceCancel = (orderId: BN, ownerTokenAccount: PublicKey, admin: Keypair = payer.payer) =>
+        program.methods
+          .forceCancel(orderId)
+          .accountsPartial({
+            orderbook: market.orderbook,
+            baseMint: market.baseMint,
+            quoteMint: market.quoteMint,
+            admin: admin.publicKey,
+            baseVault: market.baseVault,
+            quoteVault: market.quoteVault,
+            ownerTokenAccount,
+            escrowTokenAccount: escrow,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers(admin === payer.payer ? [] : [admin])
+          .rpc();
+
+      // 买单撤销后报价代币退还给拥有者；非管理员不能强制撤单
+      let signature = await placeOrder(market, maker, "buy", toPriceAmount(10), unit);
+      const bidId = (await getEvents(signature)).find((e) => e.name === "orderPlacedEvent")!.data.orderId;
+      await expect(forceCancel(bidId, maker.quote, maker.keypair)).rejects.toThrow(/Unauthorized/);
+      const quoteBefore = await getTokenBalance(maker.quote);
+      signature = await forceCancel(bidId, maker.quote);
+      expect((await getTokenBalance(maker.quote)) - quoteBefore).toBe(BigInt(unit.muln(10).toString()));
+      let event = (await getEvents(signature)).find((e) => e.name === "orderForceCanceledEvent")!;
+      expect(event.data.escrowed).toBe(false);
+      expect(event.data.refundAccount.equals(maker.quote)).toBe(true);
+
+      // 卖单拥有者的基础代币账户被冻结：退款转入托管账户
+      signature = await placeOrder(market, maker, "sell", toPriceAmount(12), unit.muln(2));
+      const askId = (await getEvents(signature)).find((e) => e.name === "orderPlacedEvent")!.data.orderId;
+      await freezeAccount(connection, payer.payer, maker.base, market.baseMint, payer.payer);
+      signature = await forceCancel(askId, maker.base);
+      expect(await getTokenBalance(escrow)).toBe(BigInt(unit.muln(2).toString()));
+      event = (await getEvents(signature)).find((e) => e.name === "orderForceCanceledEvent")!;
+      expect(event.data.escrowed).toBe(true);
+      expect(event.data.refundAmount.eq(unit.muln(2))).toBe(true);
+
+      const orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.bids.length + orderbookAccount.asks.length).toBe(0);
+      await expect(forceCancel(askId, maker.base)).rejects.toThrow(/OrderNotFound/);
+    },
+    60000
+  );
+
+  // 测试卖单吃买单时报价代币的来源：支付给 taker 的报价代币取自该买单锁定的部分，
+  // 部分成交后金库恰好等于剩余买单的锁定额，全部成交后两个金库归零
+  it(
+    "Pays sell takers out of the filled bid's locked quote",
+    async () => {
+      const market = await setupMarket();
+      const buyer = await createTrader(market);
+      const seller = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      await placeOrder(market, buyer, "buy", toPriceAmount(10), unit.muln(3));
+      expect(await getTokenBalance(market.quoteVault)).toBe(BigInt(unit.muln(30).toString()));
+
+      // 按 maker 价格 10 成交 1 个：taker 收到 10，金库剩余买单锁定的 20
+      const sellerQuoteBefore = await getTokenBalance(seller.quote);
+      await placeOrder(market, seller, "sell", toPriceAmount(9), unit, { makers: [buyer] });
+      expect((await getTokenBalance(seller.quote)) - sellerQuoteBefore).toBe(BigInt(unit.muln(10).toString()));
+      let orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.bids[0].lockedQuote.eq(unit.muln(20))).toBe(true);
+      expect(await getTokenBalance(market.quoteVault)).toBe(BigInt(unit.muln(20).toString()));
+      expect(await getTokenBalance(market.baseVault)).toBe(BigInt(0));
+
+      await placeOrder(market, seller, "sell", toPriceAmount(9), unit.muln(2), { makers: [buyer] });
+      orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.bids.length + orderbookAccount.asks.length).toBe(0);
+      expect(await getTokenBalance(market.quoteVault)).toBe(BigInt(0));
+      expect(await getTokenBalance(market.baseVault)).toBe(BigInt(0));
+    },
+    60000
+  );
+
+  // 测试撤单事件携带退款的代币、方向与撤销前的剩余数量；找不到订单时不触发事件
+  it(
+    "Reports the refund mint and remaining quantity in cancel events",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+      const cancel = (orderId: BN, side: { buy: {} } | { sell: {} }) =>
+        program.methods
+          .cancelOrder(orderId, side)
+          .accountsPartial({
+            orderbook: market.orderbook,
+            owner: maker.keypair.publicKey,
+            ownerBaseTokenAccount: maker.base,
+            ownerQuoteTokenAccount: maker.quote,
+            baseVault: market.baseVault,
+            quoteVault: market.quoteVault,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([maker.keypair])
+          .rpc({ commitment: "confirmed" });
+
+      // 买单部分成交后撤销：退还剩余 2 个对应的报价代币
+      let signature = await placeOrder(market, maker, "buy", toPriceAmount(10), unit.muln(3));
+      const bidId = (await getEvents(signature)).find((e) => e.name === "orderPlacedEvent")!.data.orderId;
+      await placeOrder(market, taker, "sell", toPriceAmount(10), unit, { makers: [maker] });
+      signature = await cancel(bidId, { buy: {} });
+      let canceled = (await getEvents(signature)).filter((e) => e.name === "orderCanceledEvent");
+      expect(canceled.length).toBe(1);
+      expect(canceled[0].data.side).toEqual({ buy: {} });
+      expect(canceled[0].data.quantity.eq(unit.muln(2))).toBe(true);
+      expect(canceled[0].data.refundMint.equals(market.quoteMint)).toBe(true);
+      expect(canceled[0].data.refundAmount.eq(unit.muln(20))).toBe(true);
+
+      // 卖单退还基础代币
+      signature = await placeOrder(market, maker, "sell", toPriceAmount(12), unit);
+      const askId = (await getEvents(signature)).find((e) => e.name === "orderPlacedEvent")!.data.orderId;
+      signature = await cancel(askId, { sell: {} });
+      canceled = (await getEvents(signature)).filter((e) => e.name === "orderCanceledEvent");
+      expect(canceled[0].data.side).toEqual({ sell: {} });
+      expect(canceled[0].data.quantity.eq(unit)).toBe(true);
+      expect(canceled[0].data.refundMint.equals(market.baseMint)).toBe(true);
+      expect(canceled[0].data.refundAmount.eq(unit)).toBe(true);
+
+      // 订单已不在订单簿上：交易失败，不会产生撤单事件
+      await expect(cancel(askId, { sell: {} })).rejects.toThrow(/OrderNotFound/);
+    },
+    60000
+  );
+
+  // 测试价格最小变动单位下的市价单与按预算买入：市价单不挂单，未用完的预算全部退还；
+  // 按预算买入的剩余部分以限价挂单，价格仍在最小变动单位上，换算数量时不足一个单位的零头退还
+  it(
+    "Keeps internally derived resting orders on the price tick",
+    async () => {
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+      const market = await setupMarket({ priceTick: toPriceAmount(5) });
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+
+      // 薄订单簿上的市价买单：只成交 1 个，剩余预算退还，不会挂单
+      await placeOrder(market, maker, "sell", toPriceAmount(10), unit);
+      let quoteBefore = await getTokenBalance(taker.quote);
+      await placeOrder(market, taker, "buy", new BN(0), unit.muln(3), {
+        market: true,
+        maxQuote: unit.muln(25),
+        makers: [maker],
+      });
+      expect(quoteBefore - (await getTokenBalance(taker.quote))).toBe(BigInt(unit.muln(10).toString()));
+      let orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.bids.length + orderbookAccount.asks.length).toBe(0);
+
+      // 按预算买入：成交 1 个后剩余预算 27 个单位加 3 的零头，按限价 15 换算为 1.8 个挂单
+      await placeOrder(market, maker, "sell", toPriceAmount(10), unit);
+      quoteBefore = await getTokenBalance(taker.quote);
+      await placeOrder(market, taker, "buy", toPriceAmount(15), unit.muln(37).addn(3), {
+        quoteBudget: true,
+        makers: [maker],
+      });
+      orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.bids.length).toBe(1);
+      const bid = orderbookAccount.bids[0];
+      expect(bid.price.modn(5)).toBe(0);
+      expect(bid.quantity.eq(unit.muln(18).divn(10))).toBe(true);
+      expect(bid.lockedQuote.eq(unit.muln(27))).toBe(true);
+      // 零头退还：实际支出 = 成交的 10 + 挂单锁定的 27
+      expect(quoteBefore - (await getTokenBalance(taker.quote))).toBe(BigInt(unit.muln(37).toString()));
+    },
+    60000
+  );
+
+  // 测试账户布局版本与迁移：新建的订单簿即为当前版本，迁移仅限管理员，
+  // 对当前版本的账户重复迁移不做任何修改
+  it(
+    "Migrates only outdated orderbooks and is idempotent",
+    async () => {
+      const market = await setupMarket();
+      const trader = await createTrader(market);
+      let orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.version).toBe(1);
+      const sizeBefore = (await connection.getAccountInfo(market.orderbook))!.data.length;
+      const logLengthBefore = orderbookAccount.adminLog.length;
+      const migrateAccounts = {
+        orderbook: market.orderbook,
+        baseMint: market.baseMint,
+        quoteMint: market.quoteMint,
+        admin: payer.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      };
+
+      await expect(
+        program.methods
+          .migrate()
+          .accountsPartial({ ...migrateAccounts, admin: trader.keypair.publicKey })
+          .signers([trader.keypair])
+          .rpc()
+      ).rejects.toThrow(/Unauthorized/);
+
+      await program.methods.migrate().accountsPartial(migrateAccounts).rpc();
+      orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.version).toBe(1);
+      expect(orderbookAccount.adminLog.length).toBe(logLengthBefore);
+      expect(orderbookAccount.baseVault.equals(market.baseVault)).toBe(true);
+      expect((await connection.getAccountInfo(market.orderbook))!.data.length).toBe(sizeBefore);
+    },
+    60000
+  );
+
+  // 测试要求改善最优价的 post-only：与同方向最优价持平时拒绝且不锁定资金，严格优于最优价时挂单
+  it(
+    "Rejects must-improve post-only orders that only tie the best price",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const trader = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+
+      await placeOrder(market, maker, "buy", toPriceAmount(10), unit);
+      await placeOrder(market, maker, "sell", toPriceAmount(20), unit);
+
+      // 与最优买价、最优卖价持平：拒绝，余额不变
+      const quoteBefore = await getTokenBalance(trader.quote);
+      const baseBefore = await getTokenBalance(trader.base);
+      await expect(
+        placeOrder(market, trader, "buy", toPriceAmount(10), unit, { postOnly: true, mustImprove: true })
+      ).rejects.toThrow(/MustImprove/);
+      await expect(
+        placeOrder(market, trader, "sell", toPriceAmount(20), unit, { postOnly: true, mustImprove: true })
+      ).rejects.toThrow(/MustImprove/);
+      expect(await getTokenBalance(trader.quote)).toBe(quoteBefore);
+      expect(await getTokenBalance(trader.base)).toBe(baseBefore);
+
+      // must_improve 只适用于 post-only 订单
+      await expect(
+        placeOrder(market, trader, "buy", toPriceAmount(11), unit, { mustImprove: true })
+      ).rejects.toThrow(/InvalidOrderParams/);
+
+      // 严格优于最优价：成为新的最优价
+      await placeOrder(market, trader, "buy", toPriceAmount(11), unit, { postOnly: true, mustImprove: true });
+      await placeOrder(market, trader, "sell", toPriceAmount(19), unit, { postOnly: true, mustImprove: true });
+      const orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.bids[0].price.toNumber()).toBe(11);
+      expect(orderbookAccount.bids[0].owner.equals(trader.keypair.publicKey)).toBe(true);
+      expect(orderbookAccount.asks[0].price.toNumber()).toBe(19);
+    },
+    60000
+  );
+
+  // 测试最近批次的成交证明：提交新批次后，较早批次的成交仍可按批次编号验证；
+  // 未提交的批次无法验证
+  it(
+    "Verifies fills from earlier retained batches",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const taker = await createTrader(market);
+      const quantity = toTokenAmount(1, BASE_DECIMALS);
+
+      // 每个批次只有一笔成交，其 Merkle 根即叶子哈希，证明为空
+      const fills = [];
+      for (const price of [10, 11]) {
+        await placeOrder(market, maker, "sell", toPriceAmount(price), quantity);
+        const signature = await placeOrder(market, taker, "buy", toPriceAmount(price), quantity, { makers: [maker] });
+        const trade = (await getEvents(signature)).find((e) => e.name === "tradeEvent")!;
+        fills.push({
+          sequenceNumber: trade.data.sequenceNumber,
+          taker: trade.data.taker,
+          maker: trade.data.maker,
+          price: trade.data.price,
+          quantity: trade.data.quantity,
+        });
+        await program.methods.settleFills().accounts({ orderbook: market.orderbook }).rpc();
+      }
+      const verify = (batchId: number, fill: (typeof fills)[number]) =>
+        program.methods.verifyFill(new BN(batchId), [], fill).accounts({ orderbook: market.orderbook }).rpc();
+
+      // 第 1 批的成交在第 2 批提交后仍可验证，但不属于第 2 批
+      await verify(1, fills[0]);
+      await verify(2, fills[1]);
+      await expect(verify(2, fills[0])).rejects.toThrow(/InvalidFillProof/);
+      // 尚未提交的批次
+      await expect(verify(3, fills[1])).rejects.toThrow(/UnknownFillBatch/);
+      await expect(verify(0, fills[0])).rejects.toThrow(/UnknownFillBatch/);
+    },
+    60000
+  );
+
+  // 测试过期挂单的退款去向与事件：撮合时移除和 reap_expired 清理都会触发撤单事件，
+  // 设置了结算方的挂单退还到结算方的代币账户（与成交所得一致），未设置时退还给拥有者
+  it(
+    "Refunds expired orders to their settlement owner and emits cancel events",
+    async () => {
+      const market = await setupMarket();
+      const maker = await createTrader(market);
+      const custodian = await createTrader(market);
+      const taker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+      const expired = new BN(1); // 早已过去的时间戳
+
+      // 撮合时遇到设置了结算方的过期卖单：基础代币退还到结算方账户
+      let signature = await placeOrder(market, maker, "sell", toPriceAmount(10), unit, {
+        expiryTs: expired,
+        settlementAuthority: custodian.keypair.publicKey,
+      });
+      const askId = (await getEvents(signature)).find((e) => e.name === "orderPlacedEvent")!.data.orderId;
+      const makerBaseBefore = await getTokenBalance(maker.base);
+      const custodianBaseBefore = await getTokenBalance(custodian.base);
+      signature = await placeOrder(market, taker, "buy", toPriceAmount(10), unit, {
+        makers: [{ ...custodian, orderOwner: maker.keypair.publicKey }],
+        restRemainder: false,
+      });
+      let canceled = (await getEvents(signature)).filter((e) => e.name === "orderCanceledEvent");
+      expect(canceled.length).toBe(1);
+      expect(canceled[0].data.orderId.eq(askId)).toBe(true);
+      expect(canceled[0].data.owner.equals(maker.keypair.publicKey)).toBe(true);
+      expect(canceled[0].data.refundAmount.eq(unit)).toBe(true);
+      expect((await getTokenBalance(custodian.base)) - custodianBaseBefore).toBe(BigInt(unit.toString()));
+      expect(await getTokenBalance(maker.base)).toBe(makerBaseBefore);
+
+      // reap_expired 清理未设置结算方的过期买单：报价代币退还给拥有者
+      signature = await placeOrder(market, maker, "buy", toPriceAmount(5), unit, { expiryTs: expired });
+      const bidId = (await getEvents(signature)).find((e) => e.name === "orderPlacedEvent")!.data.orderId;
+      const makerQuoteBefore = await getTokenBalance(maker.quote);
+      signature = await program.methods
+        .reapExpired()
+        .accounts({ orderbook: market.orderbook })
+        .remainingAccounts(makerAccounts(market.orderbook, maker))
+        .rpc({ commitment: "confirmed" });
+      canceled = (await getEvents(signature)).filter((e) => e.name === "orderCanceledEvent");
+      expect(canceled.length).toBe(1);
+      expect(canceled[0].data.orderId.eq(bidId)).toBe(true);
+      expect(canceled[0].data.side).toEqual({ buy: {} });
+      expect(canceled[0].data.refundMint.equals(market.quoteMint)).toBe(true);
+      expect(canceled[0].data.refundAmount.eq(unit.muln(5))).toBe(true);
+      expect((await getTokenBalance(maker.quote)) - makerQuoteBefore).toBe(BigInt(unit.muln(5).toString()));
+    },
+    60000
+  );
+
+  // 测试返佣记在各 maker 自己的待领取余额账户：maker 数量不受订单簿账户大小限制，每个 maker 都能累计并领取
+  it(
+    "Accrues rebates for more makers than the orderbook could hold inline",
+    async () => {
+      const market = await setupMarket({ makerFeeBps: 10 });
+      const taker = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+      const rebate = toPriceAmount(10).mul(unit).muln(5).divn(10000);
+      await program.methods.setRebateBps(5).accounts({ orderbook: market.orderbook, admin: payer.publicKey }).rpc();
+
+      // 9 个 maker 各挂一个卖单，taker 分三笔吃掉（每笔交易传入 3 个 maker 的账户）
+      const makers: Trader[] = [];
+      for (let i = 0; i < 9; i++) {
+        const maker = await createTrader(market);
+        await placeOrder(market, maker, "sell", toPriceAmount(10), unit);
+        makers.push(maker);
+      }
+      for (let i = 0; i < 9; i += 3) {
+        await placeOrder(market, taker, "buy", toPriceAmount(10), unit.muln(3), { makers: makers.slice(i, i + 3) });
+      }
+
+      for (const maker of makers) {
+        const claimable = await program.account.claimableBalance.fetch(claimablePda(market.orderbook, maker.keypair.publicKey));
+        expect(claimable.rebate.eq(rebate)).toBe(true);
+      }
+      let book = await program.account.orderbook.fetch(market.orderbook);
+      expect(book.rebatesOwed.eq(rebate.muln(9))).toBe(true);
+
+      // 最后一个 maker 领取返佣，只清零自己的累计
+      const last = makers[8];
+      const quoteBefore = await getTokenBalance(last.quote);
+      await program.methods
+        .claimRebate()
+        .accountsPartial({
+          orderbook: market.orderbook,
+          owner: last.keypair.publicKey,
+          claimable: claimablePda(market.orderbook, last.keypair.publicKey),
+          quoteFeeVault: market.quoteFeeVault,
+          ownerQuoteTokenAccount: last.quote,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([last.keypair])
+        .rpc();
+      expect((await getTokenBalance(last.quote)) - quoteBefore).toBe(BigInt(rebate.toString()));
+      book = await program.account.orderbook.fetch(market.orderbook);
+      expect(book.rebatesOwed.eq(rebate.muln(8))).toBe(true);
+
+      // 待领取余额账户必须属于订单的拥有者，传入其他交易者的账户被拒绝
+      await placeOrder(market, makers[0], "sell", toPriceAmount(10), unit);
+      await expect(
+        placeOrder(market, taker, "buy", toPriceAmount(10), unit, {
+          makers: [{ ...makers[0], orderOwner: makers[1].keypair.publicKey }],
+        })
+      ).rejects.toThrow(/MakerAccountMismatch/);
+    },
+    60000
+  );
+
+  // 测试迁移首个版本布局的订单簿：测试验证器预置的旧账户（见 tests/fixtures，买单 10 × 3，
+  // 卖单 12 × 2 与 15 × 1）只能由程序升级权限迁移，迁移后扩容为当前布局，升级权限成为管理员，
+  // 挂单补记锁定资金，并创建手续费金库
+  it(
+    "Migrates an orderbook stored in the original account layout",
+    async () => {
+      const baseMint = new PublicKey("BXVGzDjXikVepSHgRw511hwVMmneKkGAvB9cKxY2BKt2");
+      const quoteMint = new PublicKey("CuSzFVJHzFeK3GxtH2qSvPHrxzUEmexcuDji7Z1Pvz26");
+      const orderbook = new PublicKey("2rcavZiLFybGGc2gZbWvNr2j35xg3zT2HTkjPB3yPypS");
+      const [baseVault] = PublicKey.findProgramAddressSync([Buffer.from("base_vault"), orderbook.toBuffer()], program.programId);
+      const [quoteVault] = PublicKey.findProgramAddressSync([Buffer.from("quote_vault"), orderbook.toBuffer()], program.programId);
+      const [baseFeeVault] = PublicKey.findProgramAddressSync([Buffer.from("base_fee_vault"), orderbook.toBuffer()], program.programId);
+      // 本程序的 ProgramData 账户，记录升级权限（测试验证器以 provider 钱包为升级权限部署本程序）
+      const [programData] = PublicKey.findProgramAddressSync(
+        [program.programId.toBuffer()],
+        new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+      );
+      const migrateAccounts = { orderbook, baseMint, quoteMint, programData, admin: payer.publicKey, tokenProgram: TOKEN_PROGRAM_ID };
+      const legacy = (await connection.getAccountInfo(orderbook))!;
+      expect(legacy.owner.equals(program.programId)).toBe(true);
+      expect(legacy.data.length).toBe(8 + 32 + 32 + 8 + 4 + 56 * 50 + 4 + 56 * 50);
+
+      // 代币与订单簿 PDA 不对应时拒绝
+      await expect(
+        program.methods.migrate().accountsPartial({ ...migrateAccounts, quoteMint: baseMint }).rpc()
+      ).rejects.toThrow(/InvalidMigrationAccount/);
+
+      // 旧账户没有管理员：升级权限以外的签名者不能抢先迁移并成为管理员，不传入 ProgramData 账户同样被拒绝
+      const other = Keypair.generate();
+      await fundSol(other.publicKey);
+      await expect(
+        program.methods.migrate().accountsPartial({ ...migrateAccounts, admin: other.publicKey }).signers([other]).rpc()
+      ).rejects.toThrow(/Unauthorized/);
+      await expect(
+        program.methods.migrate().accountsPartial({ ...migrateAccounts, programData: null }).rpc()
+      ).rejects.toThrow(/Unauthorized/);
+      expect((await connection.getAccountInfo(orderbook))!.data.length).toBe(legacy.data.length);
+
+      await program.methods.migrate().accountsPartial(migrateAccounts).rpc();
+      const orderbookAccount = await program.account.orderbook.fetch(orderbook);
+      expect(orderbookAccount.version).toBe(1);
+      expect(orderbookAccount.admin.equals(payer.publicKey)).toBe(true);
+      expect(orderbookAccount.priceTick.toNumber()).toBe(1);
+      expect(orderbookAccount.maxOrders).toBe(24);
+      expect(orderbookAccount.makerFeeBps).toBe(0);
+      expect(orderbookAccount.takerFeeBps).toBe(0);
+      expect(orderbookAccount.orderIdCounter.toNumber()).toBe(3);
+      expect(orderbookAccount.baseVault.equals(baseVault)).toBe(true);
+      expect(orderbookAccount.quoteVault.equals(quoteVault)).toBe(true);
+      expect(orderbookAccount.adminLog.length).toBe(1);
+      expect(orderbookAccount.bids.map((o) => [o.price.toNumber(), o.quantity.toNumber(), o.lockedQuote.toNumber()])).toEqual([[10, 3, 30]]);
+      expect(orderbookAccount.asks.map((o) => [o.orderId.toNumber(), o.quantity.toNumber(), o.lockedBase.toNumber()])).toEqual([
+        [2, 2, 2],
+        [3, 1, 1],
+      ]);
+      expect((await connection.getAccountInfo(orderbook))!.data.length).toBeGreaterThan(legacy.data.length);
+      expect((await getAccount(connection, baseFeeVault)).owner.equals(orderbook)).toBe(true);
+
+      // 迁移后按当前布局处理：其他人不能再次迁移，管理员重复迁移不做任何修改
+      await expect(
+        program.methods.migrate().accountsPartial({ ...migrateAccounts, admin: other.publicKey }).signers([other]).rpc()
+      ).rejects.toThrow(/Unauthorized/);
+      await program.methods.migrate().accountsPartial(migrateAccounts).rpc();
+      expect((await program.account.orderbook.fetch(orderbook)).adminLog.length).toBe(1);
+    },
+    60000
+  );
+
+  // 测试触发止损单时的预言机价格带：限价偏离预言机价格的止损单被撤销、锁定资金记入待领取余额，
+  // 同一次 trigger_stops 中其余已触发的止损单照常撮合
+  it(
+    "Cancels an out-of-band triggered stop without blocking the others",
+    async () => {
+      const market = await setupMarket();
+      const outOfBand = await createTrader(market);
+      const inBand = await createTrader(market);
+      const maker = await createTrader(market);
+      const trader = await createTrader(market);
+      const unit = toTokenAmount(1, BASE_DECIMALS);
+      // Pyth 价格账户（测试验证器预加载），价格为 10；发布时间固定，因此不限制价格时效
+      const oracle = new PublicKey("9xXN5EWgeukXuN81ZdwR84GfanJ3CceHGXUZuT1sv6AG");
+
+      // 配置预言机前：两个买入止损单（限价 12 偏离预言机价格超过 5%，限价 10 在价格带内），以 10 成交一笔使两者都满足触发条件
+      const outOfBandStop = { triggerPrice: toPriceAmount(10), limitPrice: toPriceAmount(12) };
+      let signature = await placeOrder(market, outOfBand, "buy", toPriceAmount(12), unit, { stop: outOfBandStop });
+      const outOfBandId = (await getEvents(signature)).find((e) => e.name === "stopOrderPlacedEvent")!.data.orderId;
+      const inBandStop = { triggerPrice: toPriceAmount(10), limitPrice: toPriceAmount(10) };
+      signature = await placeOrder(market, inBand, "buy", toPriceAmount(10), unit, { stop: inBandStop });
+      const inBandId = (await getEvents(signature)).find((e) => e.name === "stopOrderPlacedEvent")!.data.orderId;
+      await placeOrder(market, maker, "sell", toPriceAmount(10), unit.muln(2));
+      await placeOrder(market, trader, "buy", toPriceAmount(10), unit, { makers: [maker] });
+      await program.methods.setOracle(oracle, 500, 0xffffffff).accounts({ orderbook: market.orderbook, admin: payer.publicKey }).rpc();
+
+      // 偏离价格带的止损单被撤销，限价在价格带内的止损单与剩余卖单成交
+      const inBandBaseBefore = await getTokenBalance(inBand.base);
+      signature = await program.methods
+        .triggerStops()
+        .accountsPartial({
+          orderbook: market.orderbook,
+          baseVault: market.baseVault,
+          quoteVault: market.quoteVault,
+          baseFeeVault: market.baseFeeVault,
+          quoteFeeVault: market.quoteFeeVault,
+          oracle,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts([outOfBand, inBand, maker].flatMap((trader) => makerAccounts(market.orderbook, trader)))
+        .rpc({ commitment: "confirmed" });
+      const events = await getEvents(signature);
+      const canceled = events.filter((e) => e.name === "orderCanceledEvent");
+      expect(canceled.length).toBe(1);
+      expect(canceled[0].data.orderId.eq(outOfBandId)).toBe(true);
+      expect(canceled[0].data.refundAmount.eq(toPriceAmount(12).mul(unit))).toBe(true);
+      const triggered = events.filter((e) => e.name === "stopOrderTriggeredEvent");
+      expect(triggered.length).toBe(1);
+      expect(triggered[0].data.orderId.eq(inBandId)).toBe(true);
+
+      expect((await getTokenBalance(inBand.base)) - inBandBaseBefore).toBe(BigInt(unit.toString()));
+      const claimable = await program.account.claimableBalance.fetch(claimablePda(market.orderbook, outOfBand.keypair.publicKey));
+      expect(claimable.quote.eq(toPriceAmount(12).mul(unit))).toBe(true);
+      const orderbookAccount = await program.account.orderbook.fetch(market.orderbook);
+      expect(orderbookAccount.stops.length).toBe(0);
+      expect(orderbookAccount.asks.length).toBe(0);
+      expect(orderbookAccount.claimableQuote.eq(toPriceAmount(12).mul(unit))).toBe(true);
+    },
+    60000
+  );
+});
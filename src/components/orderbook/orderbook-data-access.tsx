@@ -39,7 +39,7 @@ export function useOrderbookProgram() {
       const [quoteVault] = PublicKey.findProgramAddressSync([Buffer.from("quote_vault"), orderbook.toBuffer()], program.programId);
 
       return program.methods
-        .initialize()
+        .initialize(new BN(0), { makerPrice: {} }, 0, 0, new BN(0), new BN(1), { none: {} }, false, 0)
         .accounts({
           orderbook,
           baseMint,
@@ -90,9 +90,11 @@ export function useOrderbookProgramAccount({ account }: { account: PublicKey })
         if ( (side === 'buy' && price.gte(order.price)) || (side === 'sell' && price.lte(order.price)) ) {
             const makerBaseAta = getAssociatedTokenAddressSync(orderbookData.baseMint, order.owner);
             const makerQuoteAta = getAssociatedTokenAddressSync(orderbookData.quoteMint, order.owner);
+            const [makerClaimable] = PublicKey.findProgramAddressSync([Buffer.from("claimable"), account.toBuffer(), order.owner.toBuffer()], program.programId);
             remainingAccounts.push(
                 { pubkey: makerBaseAta, isSigner: false, isWritable: true },
-                { pubkey: makerQuoteAta, isSigner: false, isWritable: true }
+                { pubkey: makerQuoteAta, isSigner: false, isWritable: true },
+                { pubkey: makerClaimable, isSigner: false, isWritable: true }
             );
         }
     }
@@ -138,7 +140,7 @@ export function useOrderbookProgramAccount({ account }: { account: PublicKey })
       // 添加主业务指令
       instructions.push(
         await program.methods
-            .placeOrder(side === 'buy' ? { buy: {} } : { sell: {} }, priceBn, quantityBn)
+            .placeOrder(side === 'buy' ? { buy: {} } : { sell: {} }, priceBn, quantityBn, null, new BN(0), { limit: {} }, new BN(0), { goodTillCancel: {} }, false, new BN(0), { cancelResting: {} }, new BN(0), false, new BN(0), false, null, true, false)
             .accounts({
                 orderbook: account,
                 owner,
@@ -180,7 +182,7 @@ export function useOrderbookProgramAccount({ account }: { account: PublicKey })
 
         // 对于 cancelOrder，也应用相同的模式以确保健壮性
         return program.methods
-            .cancelOrder(order.orderId)
+            .cancelOrder(order.orderId, orderbookData.bids.some((bid) => bid.orderId.eq(order.orderId)) ? { buy: {} } : { sell: {} })
             .accounts({
                 orderbook: account,
                 owner,